@@ -4,10 +4,11 @@ use core::time::Duration;
 use rdevin::linux::{disable_grab, enable_grab, exit_grab_listen, start_grab_listen};
 use rdevin::Event;
 use rdevin::EventType;
+use rdevin::GrabAction;
 #[cfg(target_os = "linux")]
 use std::thread;
 
-fn callback(event: Event) -> Option<Event> {
+fn callback(event: Event) -> GrabAction {
     match event.event_type {
         EventType::KeyPress(_key) | EventType::KeyRelease(_key) => {
             let win_scancode: u32;
@@ -50,9 +51,9 @@ fn callback(event: Event) -> Option<Event> {
                 win_scancode, linux_keycode, macos_keycode
             );
 
-            Some(event)
+            GrabAction::Pass
         }
-        _ => Some(event),
+        _ => GrabAction::Pass,
     }
 }
 
@@ -4,10 +4,11 @@ use core::time::Duration;
 use rdevin::linux::{disable_grab, enable_grab, exit_grab_listen, start_grab_listen};
 use rdevin::Event;
 use rdevin::EventType;
+use rdevin::GrabAction;
 #[cfg(target_os = "linux")]
 use std::thread;
 
-fn callback(event: Event) -> Option<Event> {
+fn callback(event: Event) -> GrabAction {
     match event.event_type {
         EventType::KeyPress(_key) | EventType::KeyRelease(_key) => {
             /*  */
@@ -15,9 +16,9 @@ fn callback(event: Event) -> Option<Event> {
                 "name: {:?}, type: {:?}, code: {:#04X?}, scan: {:#06X?}",
                 &event.unicode, &event.event_type, &event.platform_code, &event.position_code
             );
-            Some(event)
+            GrabAction::Pass
         }
-        _ => Some(event),
+        _ => GrabAction::Pass,
     }
 }
 
@@ -38,7 +38,6 @@ fn main() {
     // This will block.
     std::env::set_var("KEYBOARD_ONLY", "y");
 
-    let mut keyboard = KEYBOARD.lock().unwrap();
     let func = move |evt: Event| {
         let (_key, _down) = match evt.event_type {
             KeyPress(k) => {
@@ -68,6 +67,7 @@ fn main() {
             _ => return,
         };
 
+        let mut keyboard = KEYBOARD.lock().unwrap();
         let char_s = keyboard.add(&evt.event_type).unwrap_or_default();
         dbg!(char_s);
         let is_dead = keyboard.is_dead();
@@ -89,7 +89,7 @@ fn main() {
 
         println!("--------------");
     };
-    if let Err(error) = rdevin::listen(func) {
+    if let Err(error) = rdevin::listen(func).and_then(|handle| handle.join()) {
         // rdevin::listen
         dbg!("{:?}", error);
     }
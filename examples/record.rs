@@ -0,0 +1,19 @@
+use rdevin::record::{Player, Recorder, Recording};
+use std::{thread, time::Duration};
+
+fn main() {
+    println!("Recording for 5 seconds");
+    let recorder = Recorder::start().expect("Could not listen");
+    thread::sleep(Duration::from_secs(5));
+    let recording = recorder.stop();
+    println!("Recorded {} events", recording.events.len());
+    recording
+        .save("recording.json")
+        .expect("Could not save the recording");
+
+    let recording = Recording::load("recording.json").expect("Could not load the recording");
+    println!("Playing back at double speed");
+    if let Err(error) = Player::new(recording).speed(2.0).play().join() {
+        println!("Error: {:?}", error)
+    }
+}
@@ -1,4 +1,4 @@
-use rdevin::{Event, EventType, Key, UnicodeInfo};
+use rdevin::{Event, EventType, Key, ModifierState, Origin, UnicodeInfo};
 use std::time::SystemTime;
 
 fn main() {
@@ -13,6 +13,9 @@ fn main() {
         platform_code: 0,
         position_code: 0,
         usb_hid: 0,
+        device: None,
+        modifiers: ModifierState::SHIFT_LEFT | ModifierState::CAPS_LOCK,
+        origin: Origin::Unknown,
         #[cfg(any(target_os = "windows", target_os = "macos"))]
         extra_data: 0 as _,
     };
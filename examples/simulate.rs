@@ -5,7 +5,7 @@ fn send(event_type: &EventType) {
     let delay = time::Duration::from_millis(20);
     match simulate(event_type) {
         Ok(()) => (),
-        Err(SimulateError) => {
+        Err(_) => {
             println!("We could not send {:?}", event_type);
         }
     }
@@ -117,6 +117,7 @@ fn test_simulate_char() {
 }
 
 #[cfg(target_os = "linux")]
+#[allow(dead_code)]
 fn simulate_combination() -> Result<(), SimulateError> {
     send(&EventType::KeyPress(Key::ControlLeft));
     rdevin::linux::simulate_char('€', true)?;
@@ -1,22 +1,12 @@
-use rdevin::listen;
-use std::thread;
-use tokio::sync::mpsc;
+use futures_util::StreamExt;
+use rdevin::listen_stream;
 
 #[tokio::main]
 async fn main() {
-    // spawn new thread because listen blocks
-    let (schan, mut rchan) = mpsc::unbounded_channel();
-    let _listener = thread::spawn(move || {
-        listen(move |event| {
-            schan
-                .send(event)
-                .unwrap_or_else(|e| println!("Could not send event {:?}", e));
-        })
-        .expect("Could not listen");
-    });
+    let mut events = listen_stream().expect("Could not listen");
 
-    loop {
-        let event = rchan.recv().await;
+    while let Some(event) = events.next().await {
         println!("Received {:?}", event);
     }
+    println!("Dropped {} events", events.handle().dropped_events());
 }
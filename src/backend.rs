@@ -0,0 +1,379 @@
+use crate::rdevin::{Event, EventType, GrabError, SimulateError};
+use crate::{GrabAction, ListenError, ListenHandle, TypingOptions, TypingReport};
+use std::fmt;
+use std::io;
+use std::sync::mpsc::Receiver;
+use thiserror::Error;
+
+/// The system interface input is read from and sent through.
+///
+/// On Windows and macOS, the platform's own API is the only backend, and it is what
+/// [`Auto`](Backend::Auto) picks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Picks the first usable backend at runtime.
+    ///
+    /// On Linux, X11 is tried first when `DISPLAY` is set and `WAYLAND_DISPLAY` isn't, since
+    /// XWayland only sees the input of X clients. Otherwise evdev is tried first. X11 can't grab
+    /// events one at a time, so grabbing always uses evdev.
+    #[default]
+    Auto,
+    /// XRecord for listening and XTest for simulating. Linux only.
+    X11,
+    /// XInput 2 raw events for listening and XTest for simulating. Linux only. See
+    /// [`linux::xinput`](crate::linux::xinput).
+    XInput2,
+    /// `/dev/input` for listening and grabbing, `/dev/uinput` for simulating. Linux only. See
+    /// [`linux::evdev`](crate::linux::evdev).
+    Evdev,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Auto => "auto",
+            Backend::X11 => "X11",
+            Backend::XInput2 => "XInput 2",
+            Backend::Evdev => "evdev",
+        })
+    }
+}
+
+/// What a backend is chosen for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Listen,
+    Grab,
+    Simulate,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Listen => "listening",
+            Operation::Grab => "grabbing",
+            Operation::Simulate => "simulating",
+        })
+    }
+}
+
+/// Errors that occur when choosing a backend.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum BackendError {
+    #[error("The {backend} backend doesn't support {operation} on this platform")]
+    Unsupported {
+        backend: Backend,
+        operation: Operation,
+    },
+    #[error("No X server: DISPLAY is unset or can't be opened")]
+    NoXServer,
+    #[error("The X server doesn't support XInput 2.1")]
+    NoXInput2,
+    #[error("Can't read the input devices in /dev/input")]
+    NoInputAccess(#[source] io::Error),
+    #[error("Can't write to /dev/uinput")]
+    NoUinputAccess(#[source] io::Error),
+    #[error("No usable backend ({})", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    NoneAvailable(Vec<BackendError>),
+}
+
+impl Backend {
+    /// Replaces [`Auto`](Backend::Auto) with the first usable backend, or checks that this one is
+    /// usable.
+    fn resolve(self, operation: Operation) -> Result<Backend, BackendError> {
+        if self != Backend::Auto {
+            return self.check(operation).map(|()| self);
+        }
+        let mut failures = Vec::new();
+        for candidate in candidates(operation) {
+            match candidate.check(operation) {
+                Ok(()) => return Ok(candidate),
+                Err(e) => failures.push(e),
+            }
+        }
+        Err(BackendError::NoneAvailable(failures))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn check(self, operation: Operation) -> Result<(), BackendError> {
+        match (self, operation) {
+            (Backend::X11 | Backend::XInput2, Operation::Grab) => Err(BackendError::Unsupported {
+                backend: self,
+                operation,
+            }),
+            (Backend::X11, _) => crate::linux::display_size()
+                .map(|_| ())
+                .map_err(|_| BackendError::NoXServer),
+            (Backend::XInput2, _) => crate::linux::xinput::check().map_err(|e| match e {
+                ListenError::NoDisplays => BackendError::NoXServer,
+                _ => BackendError::NoXInput2,
+            }),
+            (Backend::Evdev, Operation::Listen) => check_input(),
+            (Backend::Evdev, Operation::Simulate) => check_uinput(),
+            (Backend::Evdev, Operation::Grab) => check_input().and_then(|()| check_uinput()),
+            (Backend::Auto, _) => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn check(self, operation: Operation) -> Result<(), BackendError> {
+        match self {
+            Backend::Auto => Ok(()),
+            backend => Err(BackendError::Unsupported { backend, operation }),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn candidates(operation: Operation) -> Vec<Backend> {
+    let set = |var| std::env::var_os(var).is_some_and(|value| !value.is_empty());
+    linux_candidates(operation, set("DISPLAY"), set("WAYLAND_DISPLAY"))
+}
+
+#[cfg(target_os = "linux")]
+fn linux_candidates(operation: Operation, x11: bool, wayland: bool) -> Vec<Backend> {
+    match operation {
+        Operation::Grab => vec![Backend::Evdev],
+        _ if x11 && !wayland => vec![Backend::X11, Backend::Evdev],
+        _ => vec![Backend::Evdev, Backend::X11],
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn candidates(_operation: Operation) -> Vec<Backend> {
+    vec![Backend::Auto]
+}
+
+#[cfg(target_os = "linux")]
+fn check_input() -> Result<(), BackendError> {
+    match crate::linux::evdev::devices() {
+        Ok(devices) if !devices.is_empty() => Ok(()),
+        Ok(_) => Err(BackendError::NoInputAccess(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no input devices",
+        ))),
+        Err(e) => Err(BackendError::NoInputAccess(e)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_uinput() -> Result<(), BackendError> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open("/dev/uinput")
+        .map(|_| ())
+        .map_err(BackendError::NoUinputAccess)
+}
+
+/// Builds a [`Listener`].
+///
+/// ```no_run
+/// use rdevin::{Backend, Listener};
+///
+/// let listener = Listener::builder().backend(Backend::Auto).build()?;
+/// println!("Listening through {}", listener.backend());
+/// let handle = listener.listen(|e| {
+///     dbg!(e);
+/// })?;
+/// handle.join()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ListenerBuilder {
+    backend: Backend,
+}
+
+impl ListenerBuilder {
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Chooses the backend, failing if it isn't usable.
+    pub fn build(self) -> Result<Listener, BackendError> {
+        Ok(Listener {
+            backend: self.backend.resolve(Operation::Listen)?,
+        })
+    }
+}
+
+/// [`listen`](crate::listen) through a chosen backend.
+#[derive(Debug, Clone)]
+pub struct Listener {
+    backend: Backend,
+}
+
+impl Listener {
+    pub fn builder() -> ListenerBuilder {
+        ListenerBuilder::default()
+    }
+
+    /// The backend in use. Never [`Auto`](Backend::Auto), except on Windows and macOS.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn listen<T>(&self, callback: T) -> Result<ListenHandle, ListenError>
+    where
+        T: FnMut(Event) + Send + 'static,
+    {
+        match self.backend {
+            #[cfg(target_os = "linux")]
+            Backend::Evdev => crate::linux::evdev::listen(callback),
+            #[cfg(target_os = "linux")]
+            Backend::XInput2 => crate::linux::xinput::listen(callback),
+            _ => crate::listen(callback),
+        }
+    }
+
+    /// Like [`listen_channel`](crate::listen_channel), through this listener's backend.
+    pub fn listen_channel(&self) -> Result<(ListenHandle, Receiver<Event>), ListenError> {
+        crate::channel::listen_channel(|callback| self.listen(callback))
+    }
+
+    /// Like [`listen_stream`](crate::listen_stream), through this listener's backend.
+    #[cfg(feature = "stream")]
+    pub fn listen_stream(&self) -> Result<crate::EventStream, ListenError> {
+        crate::channel::listen_stream(|callback| self.listen(callback))
+    }
+}
+
+/// Builds a [`Grabber`].
+#[derive(Debug, Clone, Default)]
+pub struct GrabberBuilder {
+    backend: Backend,
+}
+
+impl GrabberBuilder {
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Chooses the backend, failing if it isn't usable.
+    pub fn build(self) -> Result<Grabber, BackendError> {
+        Ok(Grabber {
+            backend: self.backend.resolve(Operation::Grab)?,
+        })
+    }
+}
+
+/// [`grab`](crate::grab) through a chosen backend.
+#[derive(Debug, Clone)]
+pub struct Grabber {
+    backend: Backend,
+}
+
+impl Grabber {
+    pub fn builder() -> GrabberBuilder {
+        GrabberBuilder::default()
+    }
+
+    /// The backend in use. Never [`Auto`](Backend::Auto), except on Windows and macOS.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn grab<T>(&self, callback: T) -> Result<(), GrabError>
+    where
+        T: FnMut(Event) -> GrabAction + 'static,
+    {
+        crate::grab(callback)
+    }
+}
+
+/// Builds a [`Simulator`].
+#[derive(Debug, Clone, Default)]
+pub struct SimulatorBuilder {
+    backend: Backend,
+}
+
+impl SimulatorBuilder {
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Chooses the backend, failing if it isn't usable.
+    pub fn build(self) -> Result<Simulator, BackendError> {
+        Ok(Simulator {
+            backend: self.backend.resolve(Operation::Simulate)?,
+        })
+    }
+}
+
+/// [`simulate`](crate::simulate) through a chosen backend.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    backend: Backend,
+}
+
+impl Simulator {
+    pub fn builder() -> SimulatorBuilder {
+        SimulatorBuilder::default()
+    }
+
+    /// The backend in use. Never [`Auto`](Backend::Auto), except on Windows and macOS.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn simulate(&self, event_type: &EventType) -> Result<(), SimulateError> {
+        match self.backend {
+            #[cfg(target_os = "linux")]
+            Backend::Evdev => crate::linux::evdev::simulate(event_type),
+            _ => crate::simulate(event_type),
+        }
+    }
+
+    /// Like [`type_text`](crate::type_text), through this simulator's backend.
+    pub fn type_text(
+        &self,
+        text: &str,
+        options: TypingOptions,
+    ) -> Result<TypingReport, SimulateError> {
+        match self.backend {
+            #[cfg(target_os = "linux")]
+            Backend::Evdev => crate::typing::type_evdev(text, &options),
+            _ => crate::type_text(text, options),
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        use Backend::*;
+
+        assert_eq!(
+            linux_candidates(Operation::Listen, true, false),
+            [X11, Evdev]
+        );
+        // XWayland
+        assert_eq!(
+            linux_candidates(Operation::Listen, true, true),
+            [Evdev, X11]
+        );
+        assert_eq!(
+            linux_candidates(Operation::Simulate, false, false),
+            [Evdev, X11]
+        );
+        assert_eq!(linux_candidates(Operation::Grab, true, false), [Evdev]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(matches!(
+            Backend::X11.resolve(Operation::Grab),
+            Err(BackendError::Unsupported {
+                backend: Backend::X11,
+                operation: Operation::Grab,
+            })
+        ));
+    }
+}
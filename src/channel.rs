@@ -0,0 +1,140 @@
+use crate::{Event, ListenError, ListenHandle};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, TrySendError};
+use std::sync::Arc;
+
+/// How many events [`listen_channel`](crate::listen_channel) and `listen_stream` hold before new
+/// ones are dropped.
+///
+/// The OS stalls input while a listener callback runs on some platforms, so the listening thread
+/// never waits for a slow receiver. Instead, events that don't fit are counted in
+/// [`ListenHandle::dropped_events`].
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+pub(crate) type Callback = Box<dyn FnMut(Event) + Send>;
+
+fn count_dropped(dropped: &AtomicU64) {
+    if dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+        log::warn!("Event receiver is falling behind, dropping events");
+    }
+}
+
+/// Runs `listen` with a callback that forwards events to the returned receiver.
+pub(crate) fn listen_channel<L>(listen: L) -> Result<(ListenHandle, Receiver<Event>), ListenError>
+where
+    L: FnOnce(Callback) -> Result<ListenHandle, ListenError>,
+{
+    let (callback, receiver, dropped) = forward(CHANNEL_CAPACITY);
+    let handle = listen(callback)?;
+    Ok((handle.with_dropped(dropped), receiver))
+}
+
+fn forward(capacity: usize) -> (Callback, Receiver<Event>, Arc<AtomicU64>) {
+    let (sender, receiver) = sync_channel(capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let counter = dropped.clone();
+    let callback = Box::new(move |event| {
+        if let Err(TrySendError::Full(_)) = sender.try_send(event) {
+            count_dropped(&counter);
+        }
+    });
+    (callback, receiver, dropped)
+}
+
+#[cfg(feature = "stream")]
+pub use self::stream::EventStream;
+
+#[cfg(feature = "stream")]
+mod stream {
+    use super::{count_dropped, Callback, CHANNEL_CAPACITY};
+    use crate::{Event, ListenError, ListenHandle};
+    use futures_channel::mpsc;
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    /// Events from a listener, as a [`Stream`].
+    ///
+    /// Owns the listener's [`ListenHandle`], so listening stops when the stream is dropped. The
+    /// stream ends once listening stops.
+    #[derive(Debug)]
+    #[must_use = "listening stops when the stream is dropped"]
+    pub struct EventStream {
+        handle: ListenHandle,
+        receiver: mpsc::Receiver<Event>,
+    }
+
+    impl EventStream {
+        pub fn handle(&self) -> &ListenHandle {
+            &self.handle
+        }
+
+        pub fn handle_mut(&mut self) -> &mut ListenHandle {
+            &mut self.handle
+        }
+    }
+
+    impl Stream for EventStream {
+        type Item = Event;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+            Pin::new(&mut self.get_mut().receiver).poll_next(cx)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.receiver.size_hint()
+        }
+    }
+
+    pub(crate) fn listen_stream<L>(listen: L) -> Result<EventStream, ListenError>
+    where
+        L: FnOnce(Callback) -> Result<ListenHandle, ListenError>,
+    {
+        let (mut sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let counter = dropped.clone();
+        let handle = listen(Box::new(move |event| {
+            if let Err(e) = sender.try_send(event) {
+                if e.is_full() {
+                    count_dropped(&counter);
+                }
+            }
+        }))?;
+        Ok(EventStream {
+            handle: handle.with_dropped(dropped),
+            receiver,
+        })
+    }
+}
+
+#[cfg(feature = "stream")]
+pub(crate) use self::stream::listen_stream;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EventType;
+    use std::time::SystemTime;
+
+    fn event(x: f64) -> Event {
+        Event::for_test(EventType::MouseMove { x, y: 0.0 }, SystemTime::now())
+    }
+
+    #[test]
+    fn test_forward_drops_when_full() {
+        let (mut callback, receiver, dropped) = forward(2);
+        for x in 0..5 {
+            callback(event(x as f64));
+        }
+        let received: Vec<EventType> = receiver.try_iter().map(|e| e.event_type).collect();
+        // The oldest events are kept.
+        assert_eq!(received, [event(0.0).event_type, event(1.0).event_type]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+
+        // Once there is room again, events get through.
+        callback(event(5.0));
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::ListenError;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Wakes a listening thread up and makes it return.
+pub(crate) type Stop = Box<dyn FnOnce() + Send>;
+
+/// Tells [`ListenHandle::spawn`] that setup is over, handing it the way to stop listening.
+pub(crate) type Started = SyncSender<Result<Stop, ListenError>>;
+
+/// Controls a listener started by [`listen`](crate::listen), which runs on its own thread.
+///
+/// Listening stops when the handle is dropped, so keep it around for as long as you want events.
+///
+/// ```no_run
+/// let mut handle = rdevin::listen(|e| {
+///     dbg!(e);
+/// })?;
+/// assert!(handle.is_running());
+/// handle.stop();
+/// assert!(!handle.is_running());
+/// # Ok::<(), rdevin::ListenError>(())
+/// ```
+#[must_use = "listening stops when the handle is dropped"]
+pub struct ListenHandle {
+    running: Arc<AtomicBool>,
+    stop: Option<Stop>,
+    thread: Option<JoinHandle<Result<(), ListenError>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ListenHandle {
+    /// Runs `listen` on a new thread, and waits for it to report through its argument whether
+    /// setup succeeded.
+    pub(crate) fn spawn<F>(listen: F) -> Result<ListenHandle, ListenError>
+    where
+        F: FnOnce(Started) -> Result<(), ListenError> + Send + 'static,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let (started, setup) = sync_channel(1);
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || {
+                let result = listen(started);
+                running.store(false, Ordering::SeqCst);
+                if let Err(e) = &result {
+                    log::error!("Listening failed: {}", e);
+                }
+                result
+            })
+        };
+        match setup.recv() {
+            Ok(Ok(stop)) => Ok(ListenHandle {
+                running,
+                stop: Some(stop),
+                thread: Some(thread),
+                dropped: Arc::default(),
+            }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            // The thread returned or panicked before reporting.
+            Err(_) => match thread.join() {
+                Ok(Ok(())) => Ok(ListenHandle {
+                    running,
+                    stop: None,
+                    thread: None,
+                    dropped: Arc::default(),
+                }),
+                Ok(Err(e)) => Err(e),
+                Err(panic) => std::panic::resume_unwind(panic),
+            },
+        }
+    }
+
+    /// Counts events dropped on the way to the handle's receiver, for the channel and stream
+    /// listeners.
+    pub(crate) fn with_dropped(mut self, dropped: Arc<AtomicU64>) -> ListenHandle {
+        self.dropped = dropped;
+        self
+    }
+
+    /// Whether events are still being delivered.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// How many events were dropped because the receiver of
+    /// [`listen_channel`](crate::listen_channel) or `listen_stream` fell behind. Always 0 for
+    /// callbacks.
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stops listening and waits for the listening thread to exit.
+    ///
+    /// The callback won't be called anymore once this returns, unless this is called from the
+    /// callback itself, in which case it returns without waiting.
+    pub fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.thread().id() == thread::current().id() {
+                return;
+            }
+            let _ = thread.join();
+        }
+    }
+
+    /// Blocks until listening ends, which only happens when it fails.
+    pub fn join(mut self) -> Result<(), ListenError> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ListenHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl fmt::Debug for ListenHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListenHandle")
+            .field("running", &self.is_running())
+            .field("dropped_events", &self.dropped_events())
+            .finish()
+    }
+}
@@ -0,0 +1,393 @@
+//! Global shortcuts matched against the event stream.
+//!
+//! A [`HotkeyManager`] follows which keys are down and runs a callback when one of its hotkeys is
+//! pressed. Hotkeys are chords like `"Ctrl+Alt+T"` or sequences of them like
+//! `"Ctrl+K, Ctrl+C"`, written as in [`Chord`]. Left and right modifiers are interchangeable, so
+//! `Shift` matches either Shift key. Alt and AltGr are the exception: AltGr types characters on
+//! many layouts, so `Alt` only matches the left Alt key, and `AltGr` the right one.
+//!
+//! ```no_run
+//! use rdevin::hotkey::HotkeyManager;
+//!
+//! let mut manager = HotkeyManager::new();
+//! manager.register("Ctrl+Alt+T", |_| println!("Opening a terminal"))?;
+//! manager.register("Ctrl+K, Ctrl+C", |_| println!("Commenting"))?;
+//! let handle = manager.listen()?;
+//! handle.join()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! On top of [`grab`](crate::grab), the keys that make up a hotkey can be kept from applications
+//! with [`HotkeyManager::swallow`].
+
+use crate::rdevin::{Event, EventType, GrabError, Key, ModifierState};
+use crate::sequence::{Chord, Input, ParseError};
+use crate::{GrabAction, ListenError, ListenHandle};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+/// How long a sequence waits for its next chord by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Modifiers with a left and a right key. Alt and AltGr are told apart, see the module docs.
+const SIDES: [ModifierState; 3] = [
+    ModifierState::SHIFT_LEFT.union(ModifierState::SHIFT_RIGHT),
+    ModifierState::CONTROL_LEFT.union(ModifierState::CONTROL_RIGHT),
+    ModifierState::META_LEFT.union(ModifierState::META_RIGHT),
+];
+
+/// `modifiers` with both sides of each held modifier set and the lock keys left out.
+fn either_side(modifiers: ModifierState) -> ModifierState {
+    let mut modifiers = modifiers - (ModifierState::CAPS_LOCK | ModifierState::NUM_LOCK);
+    for sides in SIDES {
+        if modifiers.intersects(sides) {
+            modifiers |= sides;
+        }
+    }
+    modifiers
+}
+
+/// The modifier `input` holds, regardless of side.
+fn held_modifier(input: Input) -> ModifierState {
+    match input {
+        Input::Key(key) => either_side(ModifierState::from_key(key)),
+        Input::Button(_) => ModifierState::empty(),
+    }
+}
+
+fn same_input(a: Input, b: Input) -> bool {
+    let modifier = held_modifier(a);
+    if modifier.is_empty() {
+        a == b
+    } else {
+        modifier == held_modifier(b)
+    }
+}
+
+/// Identifies a registered hotkey.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HotkeyId(u64);
+
+type Callback = Box<dyn FnMut(&Event) + Send>;
+
+struct Hotkey {
+    id: HotkeyId,
+    steps: Vec<Chord>,
+    callback: Callback,
+    /// How many steps have been pressed.
+    progress: usize,
+    /// When the last of them was.
+    last_step: SystemTime,
+}
+
+/// Splits a hotkey into the chords it is pressed with.
+fn parse_steps(hotkey: &str) -> Result<Vec<Chord>, ParseError> {
+    let mut start = 0;
+    hotkey
+        .split(',')
+        .map(|step| {
+            let chord = Chord::parse_at(step, start);
+            start += step.len() + 1;
+            chord
+        })
+        .collect()
+}
+
+/// Runs callbacks when hotkeys are pressed. See the [module documentation](self).
+pub struct HotkeyManager {
+    hotkeys: Vec<Hotkey>,
+    next_id: u64,
+    timeout: Duration,
+    swallow: bool,
+    /// Keys that are down.
+    pressed: HashSet<Key>,
+    /// Inputs whose press was swallowed, so that their release is too.
+    swallowed: HashSet<Input>,
+}
+
+impl Default for HotkeyManager {
+    fn default() -> Self {
+        HotkeyManager {
+            hotkeys: Vec::new(),
+            next_id: 0,
+            timeout: DEFAULT_TIMEOUT,
+            swallow: false,
+            pressed: HashSet::new(),
+            swallowed: HashSet::new(),
+        }
+    }
+}
+
+impl HotkeyManager {
+    pub fn new() -> HotkeyManager {
+        HotkeyManager::default()
+    }
+
+    /// How long a sequence waits for its next chord before starting over. Defaults to
+    /// [`DEFAULT_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether the presses and releases of the keys that trigger a hotkey, or a step of one, are
+    /// kept from applications. Only [`grab`](HotkeyManager::grab) can do this. Modifiers always
+    /// go through.
+    pub fn swallow(mut self, swallow: bool) -> Self {
+        self.swallow = swallow;
+        self
+    }
+
+    /// Runs `callback` with the completing event whenever `hotkey` is pressed.
+    ///
+    /// The modifiers held must be exactly the chord's, so `Ctrl+T` doesn't fire on
+    /// `Ctrl+Shift+T`.
+    pub fn register<F>(&mut self, hotkey: &str, callback: F) -> Result<HotkeyId, ParseError>
+    where
+        F: FnMut(&Event) + Send + 'static,
+    {
+        let steps = parse_steps(hotkey)?;
+        let id = HotkeyId(self.next_id);
+        self.next_id += 1;
+        self.hotkeys.push(Hotkey {
+            id,
+            steps,
+            callback: Box::new(callback),
+            progress: 0,
+            last_step: SystemTime::UNIX_EPOCH,
+        });
+        Ok(id)
+    }
+
+    /// Removes a hotkey. Returns whether it was registered.
+    pub fn unregister(&mut self, id: HotkeyId) -> bool {
+        let len = self.hotkeys.len();
+        self.hotkeys.retain(|hotkey| hotkey.id != id);
+        self.hotkeys.len() != len
+    }
+
+    /// Follows `event`, running the callbacks of the hotkeys it completes. Returns whether it
+    /// should be swallowed.
+    pub fn handle(&mut self, event: &Event) -> bool {
+        let (input, pressed) = match event.event_type {
+            EventType::KeyPress(key) => (Input::Key(key), true),
+            EventType::KeyRelease(key) => (Input::Key(key), false),
+            EventType::ButtonPress(button) => (Input::Button(button), true),
+            EventType::ButtonRelease(button) => (Input::Button(button), false),
+            _ => return false,
+        };
+        if !pressed {
+            if let Input::Key(key) = input {
+                self.pressed.remove(&key);
+            }
+            return self.swallowed.remove(&input);
+        }
+        if let Input::Key(key) = input {
+            if !self.pressed.insert(key) {
+                // Autorepeat
+                return self.swallowed.contains(&input);
+            }
+        }
+
+        let held = either_side(
+            self.pressed
+                .iter()
+                .filter(|&&key| Input::Key(key) != input)
+                .map(|&key| ModifierState::from_key(key))
+                .collect(),
+        );
+        let matches = |chord: &Chord| {
+            same_input(chord.input, input)
+                && either_side(
+                    chord
+                        .modifiers
+                        .iter()
+                        .copied()
+                        .map(ModifierState::from_key)
+                        .collect(),
+                ) == held
+        };
+        let is_modifier = !held_modifier(input).is_empty();
+        let timeout = self.timeout;
+        let mut matched = false;
+        for hotkey in &mut self.hotkeys {
+            let expired = event
+                .time
+                .duration_since(hotkey.last_step)
+                .is_ok_and(|elapsed| elapsed > timeout);
+            if hotkey.progress > 0
+                && (expired || !is_modifier && !matches(&hotkey.steps[hotkey.progress]))
+            {
+                hotkey.progress = 0;
+            }
+            if !matches(&hotkey.steps[hotkey.progress]) {
+                continue;
+            }
+            matched = true;
+            hotkey.progress += 1;
+            hotkey.last_step = event.time;
+            if hotkey.progress == hotkey.steps.len() {
+                hotkey.progress = 0;
+                (hotkey.callback)(event);
+            }
+        }
+
+        let swallow = matched && self.swallow && !is_modifier;
+        if swallow {
+            self.swallowed.insert(input);
+        }
+        swallow
+    }
+
+    /// Matches hotkeys against [`listen`](crate::listen) events on a new thread.
+    pub fn listen(mut self) -> Result<ListenHandle, ListenError> {
+        crate::listen(move |event| {
+            self.handle(&event);
+        })
+    }
+
+    /// Matches hotkeys against [`grab`](crate::grab) events, swallowing them if
+    /// [`swallow`](HotkeyManager::swallow) is set.
+    pub fn grab(mut self) -> Result<(), GrabError> {
+        crate::grab(move |event| {
+            if self.handle(&event) {
+                GrabAction::Drop
+            } else {
+                GrabAction::Pass
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdevin::feed;
+    use std::sync::{Arc, Mutex};
+
+    /// A manager with `hotkey` registered, and the times it fired.
+    fn manager(hotkey: &str) -> (HotkeyManager, Arc<Mutex<Vec<SystemTime>>>) {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut manager = HotkeyManager::new().swallow(true);
+        let sink = fired.clone();
+        manager
+            .register(hotkey, move |event| sink.lock().unwrap().push(event.time))
+            .unwrap();
+        (manager, fired)
+    }
+
+    /// Presses and releases of `(key, pressed)` pairs, 100ms apart.
+    fn keys(keys: &[(Key, bool)]) -> Vec<(EventType, u64)> {
+        keys.iter()
+            .enumerate()
+            .map(|(i, &(key, pressed))| {
+                let event_type = if pressed {
+                    EventType::KeyPress(key)
+                } else {
+                    EventType::KeyRelease(key)
+                };
+                (event_type, i as u64 * 100)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chord() {
+        use Key::*;
+
+        let (mut manager, fired) = manager("Ctrl+Alt+T");
+        let swallowed = feed(
+            &keys(&[
+                (ControlRight, true),
+                (Alt, true),
+                (KeyT, true),
+                (KeyT, true),
+                (KeyT, false),
+                (Alt, false),
+                (ControlRight, false),
+            ]),
+            |event| manager.handle(event),
+        );
+        assert_eq!(fired.lock().unwrap().len(), 1);
+        assert_eq!(swallowed, [false, false, true, true, true, false, false]);
+
+        // Extra modifiers
+        feed(
+            &keys(&[
+                (ControlLeft, true),
+                (ShiftLeft, true),
+                (Alt, true),
+                (KeyT, true),
+            ]),
+            |event| manager.handle(event),
+        );
+        assert_eq!(fired.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_alt_gr_is_not_alt() {
+        let (mut manager, fired) = manager("Alt+Q");
+        feed(&keys(&[(Key::AltGr, true), (Key::KeyQ, true)]), |event| {
+            manager.handle(event)
+        });
+        assert!(fired.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sequence() {
+        use Key::*;
+
+        let (mut manager, fired) = manager("Ctrl+K, Ctrl+C");
+        feed(
+            &keys(&[
+                (ControlLeft, true),
+                (KeyK, true),
+                (KeyK, false),
+                (KeyC, true),
+                (KeyC, false),
+                (ControlLeft, false),
+            ]),
+            |event| manager.handle(event),
+        );
+        assert_eq!(fired.lock().unwrap().len(), 1);
+
+        // Interrupted
+        feed(
+            &keys(&[
+                (ControlLeft, true),
+                (KeyK, true),
+                (KeyX, true),
+                (KeyC, true),
+            ]),
+            |event| manager.handle(event),
+        );
+        assert_eq!(fired.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (manager, fired) = manager("Escape, Escape");
+        let mut manager = manager.timeout(Duration::from_millis(500));
+        for millis in [0, 600, 700] {
+            manager.handle(&Event::at_millis(EventType::KeyPress(Key::Escape), millis));
+            manager.handle(&Event::at_millis(
+                EventType::KeyRelease(Key::Escape),
+                millis + 50,
+            ));
+        }
+        assert_eq!(
+            *fired.lock().unwrap(),
+            [SystemTime::UNIX_EPOCH + Duration::from_millis(700)]
+        );
+    }
+
+    #[test]
+    fn test_register() {
+        let mut manager = HotkeyManager::new();
+        let id = manager.register("Meta+Space", |_| {}).unwrap();
+        assert!(manager.unregister(id));
+        assert!(!manager.unregister(id));
+        let error = manager.register("Ctrl+K, Ctrl+Kay", |_| {}).unwrap_err();
+        assert_eq!((error.token.as_str(), error.position), ("Kay", 13));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::rdevin::Key;
+use crate::rdevin::{Button, Key};
 
 macro_rules! decl_keycodes {
     ($($key:ident, $code:literal),*) => {
@@ -160,9 +160,37 @@ decl_keycodes!(
     Lang5, 0x005d
 );
 
+/// X11 button number of `button`. 4 to 7 are the wheel, so `Back` and `Forward` are 8 and 9, and
+/// `Side(n)` is `9 + n`.
+pub fn code_from_button(button: Button) -> Option<u8> {
+    match button {
+        Button::Left => Some(1),
+        Button::Middle => Some(2),
+        Button::Right => Some(3),
+        Button::Back => Some(8),
+        Button::Forward => Some(9),
+        Button::Side(n) if n > 0 => n.checked_add(9),
+        Button::Side(_) => None,
+        Button::Unknown(code) => Some(code),
+    }
+}
+
+pub fn button_from_code(code: u8) -> Button {
+    match code {
+        1 => Button::Left,
+        2 => Button::Middle,
+        3 => Button::Right,
+        8 => Button::Back,
+        9 => Button::Forward,
+        10..=u8::MAX => Button::Side(code - 9),
+        _ => Button::Unknown(code),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{code_from_key, key_from_code};
+    use super::{button_from_code, code_from_button, code_from_key, key_from_code};
+    use crate::rdevin::Button;
     #[test]
     fn test_reversible() {
         for code in 0..65636 {
@@ -173,4 +201,25 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_button_reversible() {
+        for code in 0..=u8::MAX {
+            assert_eq!(code_from_button(button_from_code(code)), Some(code));
+        }
+        for &button in &[
+            Button::Left,
+            Button::Right,
+            Button::Middle,
+            Button::Back,
+            Button::Forward,
+            Button::Side(1),
+            Button::Side(246),
+        ] {
+            assert_eq!(code_from_button(button).map(button_from_code), Some(button));
+        }
+        assert_eq!(code_from_button(Button::Back), Some(8));
+        assert_eq!(code_from_button(Button::Side(0)), None);
+        assert_eq!(code_from_button(Button::Side(247)), None);
+    }
 }
@@ -1,7 +1,7 @@
 #![allow(non_upper_case_globals)]
 
 use super::macos_virtual_keycodes::*;
-use crate::rdevin::Key;
+use crate::rdevin::{Button, Key};
 
 pub use super::macos_virtual_keycodes as virtual_keycodes;
 
@@ -162,9 +162,36 @@ decl_keycodes!(
     // Lang5, kVK_Unknown
 );
 
+/// Button number of `button`, as in `kCGMouseEventButtonNumber`. `Back` and `Forward` are 3 and 4,
+/// and `Side(n)` is `4 + n`.
+pub fn code_from_button(button: Button) -> Option<u8> {
+    match button {
+        Button::Left => Some(0),
+        Button::Right => Some(1),
+        Button::Middle => Some(2),
+        Button::Back => Some(3),
+        Button::Forward => Some(4),
+        Button::Side(n) if n > 0 => n.checked_add(4),
+        Button::Side(_) => None,
+        Button::Unknown(code) => Some(code),
+    }
+}
+
+pub fn button_from_code(code: u8) -> Button {
+    match code {
+        0 => Button::Left,
+        1 => Button::Right,
+        2 => Button::Middle,
+        3 => Button::Back,
+        4 => Button::Forward,
+        code => Button::Side(code - 4),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{code_from_key, key_from_code};
+    use super::{button_from_code, code_from_button, code_from_key, key_from_code};
+    use crate::rdevin::Button;
     #[test]
     fn test_reversible() {
         for code in 0..=65535 {
@@ -175,4 +202,24 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_button_reversible() {
+        for code in 0..=u8::MAX {
+            assert_eq!(code_from_button(button_from_code(code)), Some(code));
+        }
+        for &button in &[
+            Button::Left,
+            Button::Right,
+            Button::Middle,
+            Button::Back,
+            Button::Forward,
+            Button::Side(1),
+            Button::Side(251),
+        ] {
+            assert_eq!(code_from_button(button).map(button_from_code), Some(button));
+        }
+        assert_eq!(code_from_button(Button::Side(0)), None);
+        assert_eq!(code_from_button(Button::Side(252)), None);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::rdevin::Key;
+use crate::rdevin::{Button, Key};
 
 macro_rules! decl_keycodes {
     ($($key:ident, $code:literal, $scancode:literal),*) => {
@@ -226,9 +226,30 @@ decl_keycodes! {
     Pause, 19, 0x0000
 }
 
+/// `XBUTTON` number of `button`, as in the high word of `mouseData`. Left, right and middle have
+/// messages and flags of their own, and Windows has no buttons past `Forward`.
+pub fn code_from_button(button: Button) -> Option<u16> {
+    match button {
+        Button::Back => Some(1),
+        Button::Forward => Some(2),
+        Button::Unknown(code) => Some(code.into()),
+        _ => None,
+    }
+}
+
+pub fn button_from_code(code: u16) -> Button {
+    match code {
+        1 => Button::Back,
+        2 => Button::Forward,
+        // Only the low byte of an unknown code survives.
+        code => Button::Unknown(code as u8),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{code_from_key, key_from_code};
+    use super::{button_from_code, code_from_button, code_from_key, key_from_code};
+    use crate::rdevin::Button;
     #[test]
     fn test_reversible() {
         for code in 0..65535 {
@@ -240,4 +261,16 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_button_reversible() {
+        for code in 0..=u16::from(u8::MAX) {
+            assert_eq!(code_from_button(button_from_code(code)), Some(code));
+        }
+        for &button in &[Button::Back, Button::Forward] {
+            assert_eq!(code_from_button(button).map(button_from_code), Some(button));
+        }
+        assert_eq!(code_from_button(Button::Left), None);
+        assert_eq!(code_from_button(Button::Side(1)), None);
+    }
 }
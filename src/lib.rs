@@ -4,10 +4,15 @@
 //!
 //! ## Listening for input
 //!
-//! The [`listen`] and [`grab`][^1] functions can be used to run a callback for all input events.
+//! The [`listen`] and [`grab`] functions can be used to run a callback for all input events.
+//! [`listen`] runs in the background until the [`ListenHandle`] it returns is stopped or dropped.
 //!
 //! ```no_run
-//! rdevin::listen(|e| dbg!(e))?;
+//! let handle = rdevin::listen(|e| {
+//!     dbg!(e);
+//! })?;
+//! handle.join()?;
+//! # Ok::<(), rdevin::ListenError>(())
 //! ```
 //!
 //! ## Simulating input
@@ -18,26 +23,108 @@
 //! use rdevin::{simulate, EventType, Key};
 //!
 //! simulate(&EventType::KeyPress(Key::KeyS))?;
+//! # Ok::<(), rdevin::SimulateError>(())
+//! ```
+//!
+//! [`type_text`] types a string with whatever keys the active layout needs:
+//!
+//! ```no_run
+//! use rdevin::{type_text, TypingOptions};
+//!
+//! type_text("Hello, world!", TypingOptions::default())?;
+//! # Ok::<(), rdevin::SimulateError>(())
+//! ```
+//!
+//! Keys, chords and whole sequences can also be written as strings, see [`KeySequence`]:
+//!
+//! ```no_run
+//! use rdevin::KeySequence;
+//!
+//! "Ctrl+KeyL, <hold Shift>rdevin</hold>, Enter"
+//!     .parse::<KeySequence>()?
+//!     .simulate()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The [`hotkey`](crate::hotkey) module runs callbacks when those chords and sequences are
+//! pressed.
+//!
+//! ## Choosing a backend
+//!
+//! The functions above use each platform's default backend, which is Xlib on Linux. [`Listener`],
+//! [`Grabber`] and [`Simulator`] can pick one at runtime instead, e.g. evdev on Wayland or in a
+//! headless session:
+//!
+//! ```no_run
+//! use rdevin::{Backend, EventType, Key, Simulator};
+//!
+//! let simulator = Simulator::builder().backend(Backend::Auto).build()?;
+//! simulator.simulate(&EventType::KeyPress(Key::KeyS))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
 //!
 //! ## Serialization
 //!
 //! Serde support is gated behind the `serde` feature.
 //!
+//! ## Async
+//!
+//! [`listen_stream`] is gated behind the `stream` feature.
+//!
+//! ## Recording
+//!
+//! The [`record`](crate::record) module, which saves input to a file and plays it back, is gated
+//! behind the `record` feature.
+//!
 //! ## Acknowledgements
 //! - This crate is a fork of a fork of a fork of [Narsil's `rdev`
 //!   crate](https://crates.io/crates/rdev), created to ensure continued maintenance and to make
 //!   Rustdesk's many useful additions available on crates.io.
 //! - [Enigo](https://github.com/Enigo-rs/Enigo), an input simulation library, served as inspiration and reference for Narsil's original crate.
-//!
-//! [^1]: Not available on Linux
 
 mod rdevin;
 pub use crate::rdevin::{
-    Button, DisplayError, Event, EventType, GrabError, Key, KeyCode, KeyboardState, RawKey,
-    SimulateError, UnicodeInfo,
+    Button, DisplayError, DisplayInfo, Event, EventType, GrabError, Key, KeyCode, KeyboardState,
+    ModifierState, Origin, PenButton, PenTool, RawKey, SimulateError, UnicodeInfo, WheelUnit,
+};
+
+mod sequence;
+pub use crate::sequence::{Chord, Input, KeySequence, ParseError, ParseErrorKind};
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod backend;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::backend::{
+    Backend, BackendError, Grabber, GrabberBuilder, Listener, ListenerBuilder, Operation,
+    Simulator, SimulatorBuilder,
 };
 
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod channel;
+#[cfg(all(feature = "stream", not(any(target_os = "android", target_os = "ios"))))]
+pub use crate::channel::EventStream;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::channel::CHANNEL_CAPACITY;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod handle;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::handle::ListenHandle;
+
+#[cfg(all(feature = "record", not(any(target_os = "android", target_os = "ios"))))]
+pub mod record;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod hotkey;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod remap;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod rewrite;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::rewrite::GrabAction;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod typing;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub use crate::typing::{type_text, TypingOptions, TypingReport};
+
 /// Different OSes use different numererical representations for keys. Functions within this module
 /// provide simple, reliable conversions between the [`Key`] enum and OS-specific keycodes.
 pub mod keycodes;
@@ -55,7 +142,8 @@ pub mod codes_conv;
 pub use crate::keycodes::macos::{code_from_key, key_from_code};
 #[cfg(target_os = "macos")]
 use crate::macos::{
-    display_size as _display_size, grab as _grab, listen as _listen, simulate as _simulate,
+    cursor_position as _cursor_position, display_size as _display_size, displays as _displays,
+    grab as _grab, listen as _listen, simulate as _simulate,
 };
 #[cfg(target_os = "macos")]
 pub use crate::macos::{Keyboard, ListenError};
@@ -63,7 +151,10 @@ pub use crate::macos::{Keyboard, ListenError};
 #[cfg(any(target_os = "android", target_os = "linux"))]
 pub use crate::keycodes::linux::{code_from_key, key_from_code};
 #[cfg(target_os = "linux")]
-use crate::linux::{display_size as _display_size, listen as _listen, simulate as _simulate};
+use crate::linux::{
+    cursor_position as _cursor_position, display_size as _display_size, displays as _displays,
+    evdev::grab as _grab, listen as _listen, simulate as _simulate,
+};
 #[cfg(target_os = "linux")]
 pub use crate::linux::{Keyboard, ListenError};
 
@@ -71,26 +162,60 @@ pub use crate::linux::{Keyboard, ListenError};
 pub use crate::keycodes::windows::{code_from_key, key_from_code};
 #[cfg(target_os = "windows")]
 use crate::windows::{
-    display_size as _display_size, grab as _grab, listen as _listen, simulate as _simulate,
+    cursor_position as _cursor_position, display_size as _display_size, displays as _displays,
+    grab as _grab, listen as _listen, simulate as _simulate,
 };
 #[cfg(target_os = "windows")]
 pub use crate::windows::{Keyboard, ListenError};
 
 /// React to global input events.
+///
+/// The callback runs on a thread of its own until the returned handle is stopped or dropped.
+/// Any number of listeners can run at once.
+///
+/// On Linux this listens through XRecord, which can't tell injected input from a device's, so
+/// [`Event::origin`] is always [`Origin::Unknown`]. A [`Listener`] using [`Backend::XInput2`] or
+/// [`Backend::Evdev`] reports it.
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn listen<T>(callback: T) -> Result<(), ListenError>
+pub fn listen<T>(callback: T) -> Result<ListenHandle, ListenError>
 where
-    T: FnMut(Event) + 'static,
+    T: FnMut(Event) + Send + 'static,
 {
     _listen(callback)
 }
 
+/// Like [`listen`], but delivers events through a channel.
+///
+/// At most [`CHANNEL_CAPACITY`] events wait in the channel. Further events are dropped until the
+/// receiver catches up, and counted in [`ListenHandle::dropped_events`].
+///
+/// ```no_run
+/// let (_handle, events) = rdevin::listen_channel()?;
+/// for event in events {
+///     dbg!(event);
+/// }
+/// # Ok::<(), rdevin::ListenError>(())
+/// ```
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn listen_channel() -> Result<(ListenHandle, std::sync::mpsc::Receiver<Event>), ListenError> {
+    channel::listen_channel(listen)
+}
+
+/// Like [`listen_channel`], but delivers events as a [`Stream`](futures_core::Stream). Requires
+/// the `stream` feature.
+///
+/// The stream owns the listener, which stops when it is dropped.
+#[cfg(all(feature = "stream", not(any(target_os = "android", target_os = "ios"))))]
+pub fn listen_stream() -> Result<EventStream, ListenError> {
+    channel::listen_stream(listen)
+}
+
 /// Simulate an input event.
 ///
 /// # Example
 ///
 /// ```no_run
-/// use rdevin::{simulate, Button, EventType, Key};
+/// use rdevin::{simulate, Button, EventType, Key, WheelUnit};
 ///
 /// simulate(&EventType::KeyPress(Key::KeyS));
 /// simulate(&EventType::KeyRelease(Key::KeyS));
@@ -100,8 +225,15 @@ where
 /// simulate(&EventType::ButtonPress(Button::Left));
 /// simulate(&EventType::ButtonRelease(Button::Right));
 /// simulate(&EventType::Wheel {
-///     delta_x: 0,
-///     delta_y: 1,
+///     delta_x: 0.0,
+///     delta_y: 1.0,
+///     unit: WheelUnit::Lines,
+/// });
+/// // Half a notch, where the platform can scroll that finely.
+/// simulate(&EventType::Wheel {
+///     delta_x: 0.0,
+///     delta_y: 60.0,
+///     unit: WheelUnit::HighRes,
 /// });
 /// ```
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -117,20 +249,58 @@ pub fn display_size() -> Result<(u64, u64), DisplayError> {
     _display_size()
 }
 
-/// React to global input events, optionally preventing the event from being sent to applications.
+/// Returns the cursor's position, in the coordinates of [`EventType::MouseMove`].
 ///
-/// The callback can return `None` to ignore the event, or the event it was given to allow it to pass. **The event cannot be modified.**
+/// On Linux, this asks the X server, and fails when the cursor is on another X screen than the
+/// default one.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn cursor_position() -> Result<(f64, f64), DisplayError> {
+    _cursor_position()
+}
+
+/// Lists the monitors, to tell which one a position is on.
+///
+/// On Linux, this uses XRandR, and lists the monitors of the default X screen, which is the one
+/// [`EventType::MouseMove`] positions are on.
+///
+/// ```no_run
+/// use rdevin::displays;
+///
+/// let (x, y) = (2000.0, 500.0);
+/// let displays = displays()?;
+/// if let Some(display) = displays.iter().find(|display| display.contains(x, y)) {
+///     println!("({}, {}) is on {}", x, y, display.name);
+/// }
+/// # Ok::<(), rdevin::DisplayError>(())
+/// ```
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn displays() -> Result<Vec<DisplayInfo>, DisplayError> {
+    _displays()
+}
+
+/// React to global input events, optionally preventing them from reaching applications or
+/// rewriting them.
+///
+/// The callback returns a [`GrabAction`] for each event: pass it on, drop it, replace it with other
+/// events, or delay it until a later event decides. Replacements are injected so that the grab
+/// doesn't see them again.
+///
+/// On Linux, this takes an exclusive evdev grab on keyboards and pointers and re-emits the events
+/// that pass through uinput, so it works under X11 and Wayland alike but needs access to
+/// `/dev/input` and `/dev/uinput`. See [`linux::evdev::grab`].
 ///
 /// # Example
 ///
 /// ```no_run
-/// use rdevin::{grab, Event, EventType, Key};
+/// use rdevin::{grab, Event, EventType, GrabAction, Key};
 ///
-/// fn callback(event: Event) -> Option<Event> {
+/// fn callback(event: Event) -> GrabAction {
 ///     println!("My callback {:?}", event);
 ///     match event.event_type{
-///         EventType::KeyPress(Key::Tab) => None,
-///         _ => Some(event),
+///         EventType::KeyPress(Key::Tab) => GrabAction::Drop,
+///         EventType::KeyPress(Key::CapsLock) => GrabAction::Replace(vec![EventType::KeyPress(Key::Escape)]),
+///         EventType::KeyRelease(Key::CapsLock) => GrabAction::Replace(vec![EventType::KeyRelease(Key::Escape)]),
+///         _ => GrabAction::Pass,
 ///     }
 /// }
 /// fn main(){
@@ -139,10 +309,10 @@ pub fn display_size() -> Result<(u64, u64), DisplayError> {
 ///     }
 /// }
 /// ```
-#[cfg(not(any(target_os = "android", target_os = "ios", target_os = "linux")))]
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub fn grab<T>(callback: T) -> Result<(), GrabError>
 where
-    T: Fn(Event) -> Option<Event> + 'static,
+    T: FnMut(Event) -> GrabAction + 'static,
 {
     _grab(callback)
 }
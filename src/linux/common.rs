@@ -1,8 +1,11 @@
-use crate::keycodes::linux::key_from_code;
+use crate::keycodes::linux::{button_from_code, key_from_code};
 use crate::linux::keyboard::Keyboard;
-use crate::rdevin::{Button, Event, EventType, KeyboardState};
+use crate::rdevin::{Event, EventType, KeyboardState, ModifierState, Origin, WheelUnit};
 use std::convert::TryInto;
+use std::io;
+use std::mem::size_of;
 use std::os::raw::{c_int, c_uchar, c_uint};
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::ptr::null;
 use std::time::SystemTime;
 use x11::xlib;
@@ -24,48 +27,78 @@ pub fn convert_event(code: c_uchar, type_: c_int, x: f64, y: f64) -> Option<Even
             Some(EventType::KeyRelease(key))
         }
         xlib::ButtonPress => match code {
-            1 => Some(EventType::ButtonPress(Button::Left)),
-            2 => Some(EventType::ButtonPress(Button::Middle)),
-            3 => Some(EventType::ButtonPress(Button::Right)),
             4 => Some(EventType::Wheel {
-                delta_y: 1,
-                delta_x: 0,
+                delta_y: 1.0,
+                delta_x: 0.0,
+                unit: WheelUnit::Lines,
             }),
             5 => Some(EventType::Wheel {
-                delta_y: -1,
-                delta_x: 0,
+                delta_y: -1.0,
+                delta_x: 0.0,
+                unit: WheelUnit::Lines,
             }),
             6 => Some(EventType::Wheel {
-                delta_y: 0,
-                delta_x: -1,
+                delta_y: 0.0,
+                delta_x: -1.0,
+                unit: WheelUnit::Lines,
             }),
             7 => Some(EventType::Wheel {
-                delta_y: 0,
-                delta_x: 1,
+                delta_y: 0.0,
+                delta_x: 1.0,
+                unit: WheelUnit::Lines,
             }),
-            code => Some(EventType::ButtonPress(Button::Unknown(code))),
+            code => Some(EventType::ButtonPress(button_from_code(code))),
         },
         xlib::ButtonRelease => match code {
-            1 => Some(EventType::ButtonRelease(Button::Left)),
-            2 => Some(EventType::ButtonRelease(Button::Middle)),
-            3 => Some(EventType::ButtonRelease(Button::Right)),
-            4 | 5 => None,
-            #[allow(clippy::useless_conversion)]
-            _ => Some(EventType::ButtonRelease(Button::Unknown(code))),
+            4..=7 => None,
+            code => Some(EventType::ButtonRelease(button_from_code(code))),
         },
         xlib::MotionNotify => Some(EventType::MouseMove { x, y }),
         _ => None,
     }
 }
 
+/// Follows `event_type` after correcting `modifiers` with the X modifier mask of its event, which
+/// holds the state from before the event but can't tell left from right.
+pub fn update_modifiers(modifiers: &mut ModifierState, x_state: c_uint, event_type: &EventType) {
+    let masks = [
+        (
+            xlib::ShiftMask,
+            ModifierState::SHIFT_LEFT | ModifierState::SHIFT_RIGHT,
+        ),
+        (
+            xlib::ControlMask,
+            ModifierState::CONTROL_LEFT | ModifierState::CONTROL_RIGHT,
+        ),
+        (xlib::Mod1Mask, ModifierState::ALT),
+        (
+            xlib::Mod4Mask,
+            ModifierState::META_LEFT | ModifierState::META_RIGHT,
+        ),
+        (xlib::Mod5Mask, ModifierState::ALT_GR),
+    ];
+    for (mask, flags) in masks {
+        if x_state & mask == 0 {
+            modifiers.remove(flags);
+        }
+    }
+    modifiers.set(ModifierState::CAPS_LOCK, x_state & xlib::LockMask != 0);
+    // NumLock is Mod2 on virtually every keymap.
+    modifiers.set(ModifierState::NUM_LOCK, x_state & xlib::Mod2Mask != 0);
+    modifiers.update(event_type);
+}
+
 pub fn convert(
     keyboard: &mut Option<Keyboard>,
+    modifiers: &mut ModifierState,
     code: c_uint,
     type_: c_int,
+    x_state: c_uint,
     x: f64,
     y: f64,
 ) -> Option<Event> {
     let event_type = convert_event(code as c_uchar, type_, x, y)?;
+    update_modifiers(modifiers, x_state, &event_type);
     let kb: &mut Keyboard = (*keyboard).as_mut()?;
     let unicode = kb.add(&event_type);
     Some(Event {
@@ -75,6 +108,9 @@ pub fn convert(
         platform_code: code as _,
         position_code: code as _,
         usb_hid: 0,
+        device: None,
+        modifiers: *modifiers,
+        origin: Origin::Unknown,
     })
 }
 
@@ -93,6 +129,10 @@ impl Display {
         }
     }
 
+    pub fn as_raw(&self) -> *mut xlib::Display {
+        self.display
+    }
+
     pub fn get_size(&self) -> Option<(u64, u64)> {
         unsafe {
             let screen_ptr = xlib::XDefaultScreenOfDisplay(self.display);
@@ -107,10 +147,10 @@ impl Display {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn get_mouse_pos(&self) -> Option<(u64, u64)> {
+    /// The cursor's position on the default screen, or `None` if it's on another one.
+    pub fn get_mouse_pos(&self) -> Option<(f64, f64)> {
         unsafe {
-            let root_window = xlib::XRootWindow(self.display, 0);
+            let root_window = xlib::XDefaultRootWindow(self.display);
             let mut root_x = 0;
             let mut root_y = 0;
             let mut x = 0;
@@ -118,7 +158,7 @@ impl Display {
             let mut root = 0;
             let mut child = 0;
             let mut mask = 0;
-            let _screen_ptr = xlib::XQueryPointer(
+            let same_screen = xlib::XQueryPointer(
                 self.display,
                 root_window,
                 &mut root,
@@ -129,7 +169,7 @@ impl Display {
                 &mut y,
                 &mut mask,
             );
-            Some((root_x.try_into().ok()?, root_y.try_into().ok()?))
+            (same_screen != FALSE).then_some((root_x.into(), root_y.into()))
         }
     }
 }
@@ -140,3 +180,40 @@ impl Drop for Display {
         }
     }
 }
+
+/// An eventfd that wakes a listening thread up when it should stop.
+#[derive(Debug)]
+pub struct StopFd(RawFd);
+
+impl StopFd {
+    pub fn new() -> io::Result<StopFd> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(StopFd(fd))
+    }
+
+    pub fn signal(&self) {
+        let one: u64 = 1;
+        unsafe { libc::write(self.0, &one as *const u64 as *const _, size_of::<u64>()) };
+    }
+
+    /// Resets the signal, for threads that are woken up more than once.
+    pub fn clear(&self) {
+        let mut count: u64 = 0;
+        unsafe { libc::read(self.0, &mut count as *mut u64 as *mut _, size_of::<u64>()) };
+    }
+}
+
+impl AsRawFd for StopFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for StopFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
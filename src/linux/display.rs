@@ -1,7 +1,112 @@
-use crate::linux::common::Display;
-use crate::rdevin::DisplayError;
+use crate::linux::common::{Display, FALSE, TRUE};
+use crate::rdevin::{DisplayError, DisplayInfo};
+use std::ffi::CStr;
+use x11::{xlib, xrandr};
 
 pub fn display_size() -> Result<(u64, u64), DisplayError> {
     let display = Display::new().ok_or(DisplayError::NoDisplay)?;
     display.get_size().ok_or(DisplayError::NoDisplay)
 }
+
+pub fn cursor_position() -> Result<(f64, f64), DisplayError> {
+    let display = Display::new().ok_or(DisplayError::NoDisplay)?;
+    display.get_mouse_pos().ok_or(DisplayError::NoDisplay)
+}
+
+pub fn displays() -> Result<Vec<DisplayInfo>, DisplayError> {
+    let display = Display::new().ok_or(DisplayError::NoDisplay)?;
+    let display = display.as_raw();
+    let mut displays = Vec::new();
+    unsafe {
+        // Only the default screen, the one events and simulated motion are on.
+        let screen = xlib::XDefaultScreen(display);
+        let scale = resource_scale(display);
+        if has_monitors(display) {
+            let root = xlib::XRootWindow(display, screen);
+            let mut count = 0;
+            let info = xrandr::XRRGetMonitors(display, root, TRUE, &mut count);
+            if !info.is_null() {
+                for monitor in std::slice::from_raw_parts(info, count as usize) {
+                    displays.push(DisplayInfo {
+                        name: atom_name(display, monitor.name),
+                        x: monitor.x,
+                        y: monitor.y,
+                        width: monitor.width as u32,
+                        height: monitor.height as u32,
+                        scale,
+                        primary: monitor.primary != FALSE,
+                    });
+                }
+                xrandr::XRRFreeMonitors(info);
+            }
+        }
+        // Without RandR 1.5, or monitors, the screen counts as one.
+        if displays.is_empty() {
+            displays.push(DisplayInfo {
+                name: format!("screen {}", screen),
+                x: 0,
+                y: 0,
+                width: xlib::XDisplayWidth(display, screen) as u32,
+                height: xlib::XDisplayHeight(display, screen) as u32,
+                scale,
+                primary: true,
+            });
+        }
+    }
+    Ok(displays)
+}
+
+/// Whether the server has RandR 1.5, which added monitors.
+unsafe fn has_monitors(display: *mut xlib::Display) -> bool {
+    let (mut event, mut error) = (0, 0);
+    let (mut major, mut minor) = (0, 0);
+    xrandr::XRRQueryExtension(display, &mut event, &mut error) != FALSE
+        && xrandr::XRRQueryVersion(display, &mut major, &mut minor) != 0
+        && (major, minor) >= (1, 5)
+}
+
+unsafe fn atom_name(display: *mut xlib::Display, atom: xlib::Atom) -> String {
+    let name = xlib::XGetAtomName(display, atom);
+    if name.is_null() {
+        return String::new();
+    }
+    let owned = CStr::from_ptr(name).to_string_lossy().into_owned();
+    xlib::XFree(name as *mut _);
+    owned
+}
+
+/// X has no scale of its own, but desktops set `Xft.dpi` for toolkits to scale by.
+unsafe fn resource_scale(display: *mut xlib::Display) -> f64 {
+    let resources = xlib::XResourceManagerString(display);
+    if resources.is_null() {
+        return 1.0;
+    }
+    xft_scale(&CStr::from_ptr(resources).to_string_lossy()).unwrap_or(1.0)
+}
+
+fn xft_scale(resources: &str) -> Option<f64> {
+    resources.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim() != "Xft.dpi" {
+            return None;
+        }
+        let dpi: f64 = value.trim().parse().ok()?;
+        (dpi > 0.0).then_some(dpi / 96.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xft_scale() {
+        assert_eq!(
+            xft_scale("Xcursor.size:\t24\nXft.dpi:\t144\nXft.hinting:\t1\n"),
+            Some(1.5)
+        );
+        assert_eq!(xft_scale("Xft.dpi: 96"), Some(1.0));
+        assert_eq!(xft_scale("Xft.dpi:\tlarge"), None);
+        assert_eq!(xft_scale("Xcursor.size:\t24"), None);
+    }
+}
@@ -0,0 +1,562 @@
+// Definitions from linux/input.h and linux/input-event-codes.h that the libc crate doesn't export.
+use crate::keycodes::linux::{button_from_code, key_from_code};
+use crate::linux::common::Display;
+use crate::linux::evdev::device::{Device, DeviceKind, Frame};
+use crate::rdevin::{Button, Event, EventType, ModifierState, WheelUnit};
+use libc::{c_int, c_ulong, input_absinfo, input_event};
+use std::io;
+use std::mem::size_of;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, SystemTime};
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+
+pub const SYN_REPORT: u16 = 0;
+pub const SYN_DROPPED: u16 = 3;
+
+pub const BUS_VIRTUAL: u16 = 0x06;
+
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_WHEEL: u16 = 0x08;
+pub const REL_WHEEL_HI_RES: u16 = 0x0b;
+pub const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const ABS_PRESSURE: u16 = 0x18;
+pub const ABS_TILT_X: u16 = 0x1a;
+pub const ABS_TILT_Y: u16 = 0x1b;
+pub const ABS_MT_SLOT: u16 = 0x2f;
+pub const ABS_MT_POSITION_X: u16 = 0x35;
+pub const ABS_MT_POSITION_Y: u16 = 0x36;
+pub const ABS_MT_TRACKING_ID: u16 = 0x39;
+
+pub const BTN_MISC: u16 = 0x100;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+pub const BTN_SIDE: u16 = 0x113;
+pub const BTN_TASK: u16 = 0x117;
+pub const BTN_TOOL_PEN: u16 = 0x140;
+pub const BTN_TOOL_RUBBER: u16 = 0x141;
+pub const BTN_TOOL_BRUSH: u16 = 0x142;
+pub const BTN_TOOL_PENCIL: u16 = 0x143;
+pub const BTN_TOOL_AIRBRUSH: u16 = 0x144;
+pub const BTN_TOOL_FINGER: u16 = 0x145;
+pub const BTN_STYLUS3: u16 = 0x149;
+pub const BTN_TOUCH: u16 = 0x14a;
+pub const BTN_STYLUS: u16 = 0x14b;
+pub const BTN_STYLUS2: u16 = 0x14c;
+pub const KEY_Q: u16 = 16;
+pub const KEY_P: u16 = 25;
+pub const KEY_A: u16 = 30;
+pub const KEY_Z: u16 = 44;
+/// The modifiers: left and right Control, Shift, Alt and Meta.
+pub const MODIFIER_KEYS: [u16; 8] = [29, 97, 42, 54, 56, 100, 125, 126];
+/// First key code after the joystick, gamepad and digitizer buttons.
+pub const KEY_OK: u16 = 0x160;
+pub const KEY_MAX: u16 = 0x2ff;
+pub const EV_MAX: u16 = 0x1f;
+pub const REL_MAX: u16 = 0x0f;
+pub const ABS_MAX: u16 = 0x3f;
+pub const INPUT_PROP_DIRECT: u16 = 0x01;
+pub const INPUT_PROP_MAX: u16 = 0x1f;
+
+/// evdev key codes are X11 keycodes minus this offset.
+pub const X11_KEYCODE_OFFSET: u32 = 8;
+
+const IOC_NONE: c_ulong = 0;
+const IOC_WRITE: c_ulong = 1;
+const IOC_READ: c_ulong = 2;
+
+const fn ioc(dir: c_ulong, ty: u8, nr: u8, size: usize) -> c_ulong {
+    (dir << 30) | ((size as c_ulong) << 16) | ((ty as c_ulong) << 8) | nr as c_ulong
+}
+
+pub const fn eviocgname(len: usize) -> c_ulong {
+    ioc(IOC_READ, b'E', 0x06, len)
+}
+
+pub const fn eviocgprop(len: usize) -> c_ulong {
+    ioc(IOC_READ, b'E', 0x09, len)
+}
+
+pub const fn eviocgbit(ev: u16, len: usize) -> c_ulong {
+    ioc(IOC_READ, b'E', 0x20 + ev as u8, len)
+}
+
+pub const fn eviocgkey(len: usize) -> c_ulong {
+    ioc(IOC_READ, b'E', 0x18, len)
+}
+
+pub const fn eviocgabs(abs: u16) -> c_ulong {
+    ioc(IOC_READ, b'E', 0x40 + abs as u8, size_of::<input_absinfo>())
+}
+
+pub const EVIOCGID: c_ulong = ioc(IOC_READ, b'E', 0x02, size_of::<libc::input_id>());
+pub const EVIOCGRAB: c_ulong = ioc(IOC_WRITE, b'E', 0x90, size_of::<c_int>());
+
+pub const UI_DEV_CREATE: c_ulong = ioc(IOC_NONE, b'U', 1, 0);
+pub const UI_DEV_DESTROY: c_ulong = ioc(IOC_NONE, b'U', 2, 0);
+pub const UI_DEV_SETUP: c_ulong = ioc(IOC_WRITE, b'U', 3, size_of::<libc::uinput_setup>());
+pub const UI_ABS_SETUP: c_ulong = ioc(IOC_WRITE, b'U', 4, size_of::<libc::uinput_abs_setup>());
+pub const UI_SET_EVBIT: c_ulong = ioc(IOC_WRITE, b'U', 100, size_of::<c_int>());
+pub const UI_SET_KEYBIT: c_ulong = ioc(IOC_WRITE, b'U', 101, size_of::<c_int>());
+pub const UI_SET_RELBIT: c_ulong = ioc(IOC_WRITE, b'U', 102, size_of::<c_int>());
+pub const UI_SET_ABSBIT: c_ulong = ioc(IOC_WRITE, b'U', 103, size_of::<c_int>());
+pub const UI_SET_PROPBIT: c_ulong = ioc(IOC_WRITE, b'U', 110, size_of::<c_int>());
+
+/// Runs an ioctl, turning `-1` into the current `errno`.
+///
+/// # Safety
+/// `arg` must be valid for the request.
+pub unsafe fn ioctl<T>(fd: RawFd, request: c_ulong, arg: *mut T) -> io::Result<c_int> {
+    let res = libc::ioctl(fd, request as _, arg);
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+/// Runs an ioctl that takes its argument by value.
+pub fn ioctl_int(fd: RawFd, request: c_ulong, arg: c_int) -> io::Result<c_int> {
+    let res = unsafe { libc::ioctl(fd, request as _, arg) };
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(res)
+    }
+}
+
+/// Number of bytes needed for a bitmask holding `max + 1` bits.
+pub const fn bits_len(max: u16) -> usize {
+    max as usize / 8 + 1
+}
+
+#[inline]
+pub fn test_bit(bits: &[u8], bit: u16) -> bool {
+    bits.get(bit as usize / 8)
+        .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+}
+
+/// Kernel input events are stamped with `CLOCK_REALTIME` unless a client asks otherwise.
+pub fn event_time(raw: &libc::input_event) -> SystemTime {
+    SystemTime::UNIX_EPOCH
+        + Duration::new(
+            raw.time.tv_sec.max(0) as u64,
+            (raw.time.tv_usec.max(0) as u32).saturating_mul(1000),
+        )
+}
+
+/// Turns raw kernel records into [`Event`]s.
+///
+/// The kernel only reports relative motion for mice, so the cursor position is tracked here.
+/// It starts at the X pointer position when an X server is reachable, and is clamped to the X
+/// root window size if there is one.
+pub struct Converter {
+    x: f64,
+    y: f64,
+    bounds: Option<(f64, f64)>,
+    keyboard_only: bool,
+    modifiers: ModifierState,
+}
+
+impl Converter {
+    pub fn new() -> Converter {
+        let display = Display::new();
+        let (x, y) = display
+            .as_ref()
+            .and_then(|display| display.get_mouse_pos())
+            .unwrap_or_default();
+        let bounds = display
+            .as_ref()
+            .and_then(|display| display.get_size())
+            .map(|(w, h)| (w as f64, h as f64));
+        Converter {
+            x,
+            y,
+            bounds,
+            keyboard_only: crate::keyboard_only(),
+            modifiers: ModifierState::empty(),
+        }
+    }
+
+    pub fn convert(&mut self, device: &mut Device, raw: &input_event, emit: &mut dyn FnMut(Event)) {
+        if device.frame.dropped {
+            if raw.type_ == EV_SYN && raw.code == SYN_REPORT {
+                device.frame = Frame::default();
+                device.touches.reset();
+                self.resync_modifiers(device, raw, emit);
+            }
+            return;
+        }
+        match raw.type_ {
+            EV_KEY => {
+                if raw.code == BTN_TOUCH {
+                    device.touching = raw.value != 0;
+                    if !device.touching {
+                        device.last_touch = (None, None);
+                    }
+                }
+                let converted = match device.pen.convert_key(raw.code, raw.value) {
+                    Some(event_type) => Some((event_type, raw.code.into())),
+                    None if raw.code == BTN_TOUCH => None,
+                    None => convert_key(raw.code, raw.value),
+                };
+                let Some((event_type, code)) = converted else {
+                    return;
+                };
+                if self.keyboard_only
+                    && !matches!(
+                        event_type,
+                        EventType::KeyPress(_) | EventType::KeyRelease(_)
+                    )
+                {
+                    return;
+                }
+                // Autorepeat would toggle lock keys again.
+                if raw.value != 2 {
+                    self.modifiers.update(&event_type);
+                    device.modifiers.update(&event_type);
+                }
+                emit(self.event(event_type, code, device, raw));
+            }
+            EV_REL if !self.keyboard_only => match raw.code {
+                REL_X => device.frame.dx += raw.value,
+                REL_Y => device.frame.dy += raw.value,
+                REL_WHEEL => device.frame.wheel_y += raw.value,
+                REL_HWHEEL => device.frame.wheel_x += raw.value,
+                REL_WHEEL_HI_RES => {
+                    *device.frame.wheel_y_hi_res.get_or_insert(0) += raw.value;
+                }
+                REL_HWHEEL_HI_RES => {
+                    *device.frame.wheel_x_hi_res.get_or_insert(0) += raw.value;
+                }
+                _ => {}
+            },
+            EV_ABS if !self.keyboard_only => match raw.code {
+                ABS_X => device.frame.abs_x = Some(raw.value),
+                ABS_Y => device.frame.abs_y = Some(raw.value),
+                ABS_MT_SLOT..=ABS_MT_TRACKING_ID => device.touches.update(raw.code, raw.value),
+                code => device.frame.pen_moved |= device.pen.update(code, raw.value),
+            },
+            EV_SYN => match raw.code {
+                SYN_REPORT => self.flush(device, raw, emit),
+                SYN_DROPPED => device.frame.dropped = true,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn flush(&mut self, device: &mut Device, raw: &input_event, emit: &mut dyn FnMut(Event)) {
+        let frame = std::mem::take(&mut device.frame);
+        let mut moved = false;
+        if frame.dx != 0 || frame.dy != 0 {
+            self.x += frame.dx as f64;
+            self.y += frame.dy as f64;
+            moved = true;
+        }
+        if device.kind() == DeviceKind::Touchpad {
+            if device.touching {
+                let (last_x, last_y) = device.last_touch;
+                if let (Some(value), Some(last), Some(info)) = (frame.abs_x, last_x, &device.abs_x)
+                {
+                    self.x += touchpad_delta(value - last, info);
+                    moved = true;
+                }
+                if let (Some(value), Some(last), Some(info)) = (frame.abs_y, last_y, &device.abs_y)
+                {
+                    self.y += touchpad_delta(value - last, info);
+                    moved = true;
+                }
+                device.last_touch = (frame.abs_x.or(last_x), frame.abs_y.or(last_y));
+            }
+        } else {
+            if let (Some(value), Some(info)) = (frame.abs_x, device.abs_x.as_ref()) {
+                self.x = scale_abs(value, info, self.bounds.map(|(w, _)| w));
+                moved = true;
+            }
+            if let (Some(value), Some(info)) = (frame.abs_y, device.abs_y.as_ref()) {
+                self.y = scale_abs(value, info, self.bounds.map(|(_, h)| h));
+                moved = true;
+            }
+        }
+        if moved {
+            self.clamp();
+            emit(self.event(
+                EventType::MouseMove {
+                    x: self.x,
+                    y: self.y,
+                },
+                0,
+                device,
+                raw,
+            ));
+        }
+        // Tablets move the cursor like any absolute pointer, and say more about the pen after.
+        if device.pen.tool.is_some() && (moved || frame.pen_moved) {
+            let (tilt_x, tilt_y) = device.pen.tilt();
+            emit(self.event(
+                EventType::PenMove {
+                    x: self.x,
+                    y: self.y,
+                    pressure: device.pen.pressure(),
+                    tilt_x,
+                    tilt_y,
+                },
+                0,
+                device,
+                raw,
+            ));
+        }
+        let bounds = self.bounds;
+        let (abs_x, abs_y) = (device.touches.abs_x, device.touches.abs_y);
+        let touches = device.touches.flush(|x, y| {
+            (
+                abs_x.map_or(x.into(), |info| scale_abs(x, &info, bounds.map(|(w, _)| w))),
+                abs_y.map_or(y.into(), |info| scale_abs(y, &info, bounds.map(|(_, h)| h))),
+            )
+        });
+        for event_type in touches {
+            emit(self.event(event_type, 0, device, raw));
+        }
+        // High-resolution wheels also send whole notches once they add up, which would count
+        // twice.
+        let (delta_x, delta_y, unit) =
+            if frame.wheel_x_hi_res.is_some() || frame.wheel_y_hi_res.is_some() {
+                (
+                    frame.wheel_x_hi_res.unwrap_or(frame.wheel_x * 120),
+                    frame.wheel_y_hi_res.unwrap_or(frame.wheel_y * 120),
+                    WheelUnit::HighRes,
+                )
+            } else {
+                (frame.wheel_x, frame.wheel_y, WheelUnit::Lines)
+            };
+        if delta_x != 0 || delta_y != 0 {
+            emit(self.event(
+                EventType::Wheel {
+                    delta_x: delta_x.into(),
+                    delta_y: delta_y.into(),
+                    unit,
+                },
+                0,
+                device,
+                raw,
+            ));
+        }
+    }
+
+    /// Catches up with the modifiers pressed or released on `device` while its events were
+    /// dropped, which would otherwise stay held. Modifiers held on other devices are left alone.
+    fn resync_modifiers(
+        &mut self,
+        device: &mut Device,
+        raw: &input_event,
+        emit: &mut dyn FnMut(Event),
+    ) {
+        let keys = match device.key_state() {
+            Ok(keys) => keys,
+            Err(e) => {
+                log::debug!("Can't resync {}: {}", device.info.path.display(), e);
+                return;
+            }
+        };
+        for (event_type, code) in
+            modifier_changes(device.modifiers, &device.capabilities.key, &keys)
+        {
+            self.modifiers.update(&event_type);
+            device.modifiers.update(&event_type);
+            emit(self.event(event_type, code, device, raw));
+        }
+    }
+
+    fn clamp(&mut self) {
+        self.x = self.x.max(0.0);
+        self.y = self.y.max(0.0);
+        if let Some((w, h)) = self.bounds {
+            self.x = self.x.min(w - 1.0);
+            self.y = self.y.min(h - 1.0);
+        }
+    }
+
+    fn event(&self, event_type: EventType, code: u32, device: &Device, raw: &input_event) -> Event {
+        Event {
+            time: event_time(raw),
+            unicode: None,
+            event_type,
+            platform_code: code,
+            position_code: code,
+            usb_hid: 0,
+            device: Some(device.id()),
+            modifiers: self.modifiers,
+            origin: device.origin(),
+        }
+    }
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The presses and releases that bring the `modifiers` a device was holding in line with its keys
+/// that are down. Modifiers the device doesn't have are left alone.
+fn modifier_changes(
+    modifiers: ModifierState,
+    supported: &[u8],
+    down: &[u8],
+) -> Vec<(EventType, u32)> {
+    MODIFIER_KEYS
+        .iter()
+        .filter(|&&code| test_bit(supported, code))
+        .filter_map(|&code| convert_key(code, test_bit(down, code).into()))
+        .filter(|(event_type, _)| match *event_type {
+            EventType::KeyPress(key) => !modifiers.contains(ModifierState::from_key(key)),
+            EventType::KeyRelease(key) => modifiers.contains(ModifierState::from_key(key)),
+            _ => false,
+        })
+        .collect()
+}
+
+/// Maps an absolute axis onto `0..size`, or passes the raw value through when the screen size is
+/// unknown.
+fn scale_abs(value: i32, info: &input_absinfo, size: Option<f64>) -> f64 {
+    let range = info.maximum as f64 - info.minimum as f64;
+    match size {
+        Some(size) if range > 0.0 => (value as f64 - info.minimum as f64) / range * (size - 1.0),
+        _ => value as f64,
+    }
+}
+
+/// Converts finger travel on a touchpad to pixels, using libinput's normalisation of one
+/// millimetre to the distance a 1000 DPI mouse reports for it.
+fn touchpad_delta(units: i32, info: &input_absinfo) -> f64 {
+    const PIXELS_PER_MM: f64 = 1000.0 / 25.4;
+    if info.resolution > 0 {
+        units as f64 / info.resolution as f64 * PIXELS_PER_MM
+    } else {
+        units as f64
+    }
+}
+
+/// Converts an `EV_KEY` record, returning the event and its platform code.
+///
+/// Keyboard keys are reported with their X11 keycode so that they line up with
+/// [`keycodes::linux`](crate::keycodes::linux). Mouse buttons past the third use the X11 numbering
+/// as well, so `BTN_SIDE` is `Back` and `BTN_EXTRA` is `Forward`.
+pub fn convert_key(code: u16, value: i32) -> Option<(EventType, u32)> {
+    // 0 is a release, 1 a press and 2 an autorepeat, which X11 also reports as a press.
+    let pressed = match value {
+        0 => false,
+        1 | 2 => true,
+        _ => return None,
+    };
+    match code {
+        BTN_LEFT..=BTN_TASK => {
+            let button = match code {
+                BTN_LEFT => Button::Left,
+                BTN_RIGHT => Button::Right,
+                BTN_MIDDLE => Button::Middle,
+                _ => button_from_code((code - BTN_SIDE + 8) as u8),
+            };
+            // Autorepeat only makes sense for keys.
+            if value == 2 {
+                return None;
+            }
+            let event_type = if pressed {
+                EventType::ButtonPress(button)
+            } else {
+                EventType::ButtonRelease(button)
+            };
+            Some((event_type, code.into()))
+        }
+        code if code < BTN_MISC || (KEY_OK..=KEY_MAX).contains(&code) => {
+            let code = code as u32 + X11_KEYCODE_OFFSET;
+            let key = key_from_code(code);
+            let event_type = if pressed {
+                EventType::KeyPress(key)
+            } else {
+                EventType::KeyRelease(key)
+            };
+            Some((event_type, code))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Key;
+
+    #[test]
+    fn test_convert_key() {
+        // KEY_A
+        assert_eq!(
+            convert_key(30, 1),
+            Some((EventType::KeyPress(Key::KeyA), 38))
+        );
+        assert_eq!(
+            convert_key(30, 2),
+            Some((EventType::KeyPress(Key::KeyA), 38))
+        );
+        assert_eq!(
+            convert_key(30, 0),
+            Some((EventType::KeyRelease(Key::KeyA), 38))
+        );
+        assert_eq!(
+            convert_key(BTN_RIGHT, 1),
+            Some((EventType::ButtonPress(Button::Right), BTN_RIGHT.into()))
+        );
+        assert_eq!(
+            convert_key(BTN_SIDE, 0),
+            Some((EventType::ButtonRelease(Button::Back), BTN_SIDE.into()))
+        );
+        assert_eq!(
+            convert_key(BTN_TASK, 1),
+            Some((EventType::ButtonPress(Button::Side(3)), BTN_TASK.into()))
+        );
+        assert_eq!(convert_key(BTN_LEFT, 2), None);
+        // BTN_TOUCH
+        assert_eq!(convert_key(0x14a, 1), None);
+    }
+
+    #[test]
+    fn test_modifier_changes() {
+        let bits = |codes: &[u16]| {
+            let mut bits = vec![0; bits_len(KEY_MAX)];
+            for &code in codes {
+                bits[code as usize / 8] |= 1 << (code % 8);
+            }
+            bits
+        };
+        // A keyboard without a right Meta key, which held left Shift and now has right Control
+        // down.
+        let supported = bits(&[29, 97, 42, 54, 56, 100, 125]);
+        let held = ModifierState::SHIFT_LEFT | ModifierState::META_RIGHT | ModifierState::CAPS_LOCK;
+        assert_eq!(
+            modifier_changes(held, &supported, &bits(&[97])),
+            [
+                (EventType::KeyPress(Key::ControlRight), 105),
+                (EventType::KeyRelease(Key::ShiftLeft), 50),
+            ]
+        );
+        assert_eq!(
+            modifier_changes(ModifierState::SHIFT_LEFT, &supported, &bits(&[42])),
+            []
+        );
+        // Left Shift held on another keyboard isn't this one's to release.
+        assert_eq!(
+            modifier_changes(ModifierState::empty(), &supported, &bits(&[])),
+            []
+        );
+    }
+}
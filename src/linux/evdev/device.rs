@@ -0,0 +1,362 @@
+use crate::linux::evdev::common::*;
+use crate::linux::evdev::touch::{Pen, Touches};
+use crate::rdevin::{ModifierState, Origin};
+use libc::{input_absinfo, input_event, input_id};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::mem::{size_of, zeroed};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+pub const INPUT_DIR: &str = "/dev/input";
+
+/// What an input device is, judged from the capabilities its driver advertises.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    Keyboard,
+    /// Mice, trackballs, pointing sticks, tablets and touchscreens.
+    Pointer,
+    /// Touchpads report absolute finger positions but move the cursor relatively.
+    Touchpad,
+    /// Power buttons, lid switches, accelerometers and the like.
+    Other,
+}
+
+/// Description of a device found under `/dev/input`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// The `N` of `/dev/input/eventN`, as found in [`Event::device`](crate::Event::device).
+    pub id: u32,
+    pub path: PathBuf,
+    /// Name reported by the kernel driver.
+    pub name: String,
+    pub kind: DeviceKind,
+}
+
+/// Motion, wheel and pen data collected until the next `SYN_REPORT`.
+#[derive(Debug, Default)]
+pub(crate) struct Frame {
+    pub dx: i32,
+    pub dy: i32,
+    pub wheel_x: i32,
+    pub wheel_y: i32,
+    /// In 120ths of a notch, from high-resolution wheels.
+    pub wheel_x_hi_res: Option<i32>,
+    pub wheel_y_hi_res: Option<i32>,
+    pub abs_x: Option<i32>,
+    pub abs_y: Option<i32>,
+    /// Whether the pressure or tilt of a tablet tool changed.
+    pub pen_moved: bool,
+    /// Set after `SYN_DROPPED`: everything up to the next report is incomplete.
+    pub dropped: bool,
+}
+
+/// An opened `/dev/input/event*` node.
+#[derive(Debug)]
+pub(crate) struct Device {
+    file: File,
+    pub info: DeviceInfo,
+    /// The bus the device is on, [`BUS_VIRTUAL`] for uinput devices.
+    pub bus: u16,
+    pub capabilities: Capabilities,
+    pub abs_x: Option<input_absinfo>,
+    pub abs_y: Option<input_absinfo>,
+    pub frame: Frame,
+    /// Whether a finger is on the touchpad, and where it was at the last report.
+    pub touching: bool,
+    pub last_touch: (Option<i32>, Option<i32>),
+    /// Contacts of a touchscreen, with no slots for other devices.
+    pub touches: Touches,
+    pub pen: Pen,
+    /// The modifiers this device holds down, as far as its own events tell.
+    pub modifiers: ModifierState,
+}
+
+impl Device {
+    pub fn open(path: &Path) -> io::Result<Device> {
+        let id = event_node_id(path).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(path)?;
+        let fd = file.as_raw_fd();
+
+        let mut name = [0u8; 256];
+        let name = match unsafe { ioctl(fd, eviocgname(name.len()), name.as_mut_ptr()) } {
+            Ok(_) => {
+                let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+                String::from_utf8_lossy(&name[..len]).into_owned()
+            }
+            Err(_) => String::new(),
+        };
+
+        let mut input_id: input_id = unsafe { zeroed() };
+        let bus = match unsafe { ioctl(fd, EVIOCGID, &mut input_id) } {
+            Ok(_) => input_id.bustype,
+            Err(_) => 0,
+        };
+
+        let capabilities = Capabilities::read(fd)?;
+        let abs = |axis| {
+            if test_bit(&capabilities.abs, axis) {
+                Self::abs_info(fd, axis).ok()
+            } else {
+                None
+            }
+        };
+        let (abs_x, abs_y) = (abs(ABS_X), abs(ABS_Y));
+        // Fingers on a touchpad move the cursor instead.
+        let touches = match abs(ABS_MT_SLOT) {
+            Some(info) if test_bit(&capabilities.props, INPUT_PROP_DIRECT) => Touches {
+                abs_x: abs(ABS_MT_POSITION_X),
+                abs_y: abs(ABS_MT_POSITION_Y),
+                ..Touches::new(info.maximum.max(0) as usize + 1)
+            },
+            _ => Touches::default(),
+        };
+        let pen = Pen {
+            abs_pressure: abs(ABS_PRESSURE),
+            abs_tilt_x: abs(ABS_TILT_X),
+            abs_tilt_y: abs(ABS_TILT_Y),
+            ..Pen::default()
+        };
+
+        Ok(Device {
+            file,
+            info: DeviceInfo {
+                id,
+                path: path.to_owned(),
+                name,
+                kind: capabilities.kind(),
+            },
+            bus,
+            capabilities,
+            abs_x,
+            abs_y,
+            frame: Frame::default(),
+            touching: false,
+            last_touch: (None, None),
+            touches,
+            pen,
+            modifiers: ModifierState::empty(),
+        })
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.info.id
+    }
+
+    #[inline]
+    pub fn kind(&self) -> DeviceKind {
+        self.info.kind
+    }
+
+    /// Input from uinput devices is injected. Grab mirrors keep the bus of the device they
+    /// mirror, so what passes through them keeps its origin.
+    pub fn origin(&self) -> Origin {
+        if self.bus == BUS_VIRTUAL {
+            Origin::Synthetic { tag: 0 }
+        } else {
+            Origin::Hardware
+        }
+    }
+
+    /// Reads the capability bitmask for `ev` (`0` for the supported event types).
+    fn bits(fd: RawFd, ev: u16, max: u16) -> io::Result<Vec<u8>> {
+        let mut bits = vec![0u8; bits_len(max)];
+        unsafe { ioctl(fd, eviocgbit(ev, bits.len()), bits.as_mut_ptr())? };
+        Ok(bits)
+    }
+
+    pub fn abs_info(fd: RawFd, axis: u16) -> io::Result<input_absinfo> {
+        let mut info: input_absinfo = unsafe { zeroed() };
+        unsafe { ioctl(fd, eviocgabs(axis), &mut info)? };
+        Ok(info)
+    }
+
+    /// Reads which keys are down, as a bitmask.
+    pub fn key_state(&self) -> io::Result<Vec<u8>> {
+        let mut keys = vec![0u8; bits_len(KEY_MAX)];
+        unsafe { ioctl(self.as_raw_fd(), eviocgkey(keys.len()), keys.as_mut_ptr())? };
+        Ok(keys)
+    }
+
+    /// Reads as many pending events as fit in `buf`, returning how many were read.
+    pub fn read(&mut self, buf: &mut [input_event]) -> io::Result<usize> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, std::mem::size_of_val(buf))
+        };
+        let read = self.file.read(bytes)?;
+        Ok(read / size_of::<input_event>())
+    }
+}
+
+impl AsRawFd for Device {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// Capability bitmasks of a device.
+#[derive(Debug, Default)]
+pub(crate) struct Capabilities {
+    pub key: Vec<u8>,
+    pub rel: Vec<u8>,
+    pub abs: Vec<u8>,
+    pub props: Vec<u8>,
+}
+
+impl Capabilities {
+    fn read(fd: RawFd) -> io::Result<Capabilities> {
+        let ev = Device::bits(fd, 0, EV_MAX)?;
+        let bits = |ev_type, max| {
+            if test_bit(&ev, ev_type) {
+                Device::bits(fd, ev_type, max).unwrap_or_default()
+            } else {
+                Vec::new()
+            }
+        };
+        let key = bits(EV_KEY, KEY_MAX);
+        let rel = bits(EV_REL, REL_MAX);
+        let abs = bits(EV_ABS, ABS_MAX);
+        let mut props = vec![0u8; bits_len(INPUT_PROP_MAX)];
+        if unsafe { ioctl(fd, eviocgprop(props.len()), props.as_mut_ptr()) }.is_err() {
+            props.clear();
+        }
+        Ok(Capabilities {
+            key,
+            rel,
+            abs,
+            props,
+        })
+    }
+
+    /// Roughly the rules libinput and udev's `input_id` builtin use.
+    pub fn kind(&self) -> DeviceKind {
+        let has_abs_xy = test_bit(&self.abs, ABS_X) && test_bit(&self.abs, ABS_Y);
+        let has_rel_xy = test_bit(&self.rel, REL_X) && test_bit(&self.rel, REL_Y);
+        let direct = test_bit(&self.props, INPUT_PROP_DIRECT);
+
+        if has_abs_xy && test_bit(&self.key, BTN_TOOL_FINGER) && !direct {
+            DeviceKind::Touchpad
+        } else if (has_rel_xy && test_bit(&self.key, BTN_LEFT))
+            || (has_abs_xy
+                && (test_bit(&self.key, BTN_TOUCH)
+                    || test_bit(&self.key, BTN_TOOL_PEN)
+                    || test_bit(&self.key, BTN_LEFT)))
+        {
+            DeviceKind::Pointer
+        } else if (KEY_Q..=KEY_P)
+            .chain(KEY_A..=KEY_Z)
+            .all(|key| test_bit(&self.key, key))
+        {
+            DeviceKind::Keyboard
+        } else {
+            DeviceKind::Other
+        }
+    }
+}
+
+/// The `N` of an `eventN` entry of `/dev/input`.
+pub(crate) fn event_node_id(path: &Path) -> Option<u32> {
+    let n = path.file_name()?.to_str()?.strip_prefix("event")?;
+    if n.bytes().all(|b| b.is_ascii_digit()) {
+        n.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Lists the input devices the current user is allowed to read.
+pub fn devices() -> io::Result<Vec<DeviceInfo>> {
+    let mut devices: Vec<DeviceInfo> = open_all()?.into_iter().map(|device| device.info).collect();
+    devices.sort_by_key(|device| device.id);
+    Ok(devices)
+}
+
+/// Opens every event node we are allowed to read.
+///
+/// Only fails when there are event nodes but none of them could be opened.
+pub(crate) fn open_all() -> io::Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    let mut last_error = None;
+    for entry in fs::read_dir(INPUT_DIR)? {
+        let path = entry?.path();
+        if event_node_id(&path).is_none() {
+            continue;
+        }
+        match Device::open(&path) {
+            Ok(device) => devices.push(device),
+            Err(e) => {
+                log::debug!("Skipping {}: {}", path.display(), e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match (devices.is_empty(), last_error) {
+        (true, Some(e)) => Err(e),
+        _ => Ok(devices),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(max: u16, set: &[u16]) -> Vec<u8> {
+        let mut bits = vec![0u8; bits_len(max)];
+        for bit in set {
+            bits[*bit as usize / 8] |= 1 << (bit % 8);
+        }
+        bits
+    }
+
+    #[test]
+    fn test_event_node_id() {
+        assert_eq!(event_node_id(Path::new("/dev/input/event12")), Some(12));
+        assert_eq!(event_node_id(Path::new("/dev/input/mouse0")), None);
+        assert_eq!(event_node_id(Path::new("/dev/input/event")), None);
+        assert_eq!(event_node_id(Path::new("/dev/input/by-id")), None);
+    }
+
+    #[test]
+    fn test_classify() {
+        let letters: Vec<u16> = (KEY_Q..=KEY_P).chain(KEY_A..=KEY_Z).collect();
+        let keyboard = Capabilities {
+            key: bits(KEY_MAX, &letters),
+            ..Default::default()
+        };
+        assert_eq!(keyboard.kind(), DeviceKind::Keyboard);
+
+        let mouse = Capabilities {
+            key: bits(KEY_MAX, &[BTN_LEFT, BTN_RIGHT]),
+            rel: bits(REL_MAX, &[REL_X, REL_Y]),
+            ..Default::default()
+        };
+        assert_eq!(mouse.kind(), DeviceKind::Pointer);
+
+        let touchpad = Capabilities {
+            key: bits(KEY_MAX, &[BTN_LEFT, BTN_TOOL_FINGER, BTN_TOUCH]),
+            abs: bits(ABS_MAX, &[ABS_X, ABS_Y]),
+            ..Default::default()
+        };
+        assert_eq!(touchpad.kind(), DeviceKind::Touchpad);
+
+        let touchscreen = Capabilities {
+            key: bits(KEY_MAX, &[BTN_TOOL_FINGER, BTN_TOUCH]),
+            abs: bits(ABS_MAX, &[ABS_X, ABS_Y]),
+            props: bits(INPUT_PROP_MAX, &[INPUT_PROP_DIRECT]),
+            ..Default::default()
+        };
+        assert_eq!(touchscreen.kind(), DeviceKind::Pointer);
+
+        // KEY_POWER
+        let power_button = Capabilities {
+            key: bits(KEY_MAX, &[116]),
+            ..Default::default()
+        };
+        assert_eq!(power_button.kind(), DeviceKind::Other);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::keyboard_only;
+use crate::linux::evdev::common::*;
+use crate::linux::evdev::device::{Device, DeviceKind};
+use crate::linux::evdev::listen::{Epoll, HOTPLUG};
+use crate::linux::evdev::registry::{Change, DeviceRegistry};
+use crate::linux::evdev::simulate::VirtualInput;
+use crate::linux::evdev::uinput::{VirtualDevice, SETTLE_TIME};
+use crate::rdevin::{Event, EventType, GrabError, SimulateError};
+use crate::rewrite::{rewriter, Outcome};
+use crate::GrabAction;
+use epoll::Events;
+use libc::input_event;
+use std::collections::HashMap;
+use std::io;
+use std::mem::zeroed;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Prefix of the names of mirror devices, and of the devices replacements are injected through,
+/// which are never grabbed themselves.
+pub const MIRROR_PREFIX: &str = "rdevin mirror: ";
+
+/// How long to wait for keys held when the grab starts to be released.
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often devices plugged in while grabbing are checked on until they are grabbed.
+const PENDING_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The virtual device a grabbed device's allowed events are re-emitted through.
+struct Mirror {
+    device: VirtualDevice,
+    /// Records of the current report that will be passed on.
+    pending: Vec<input_event>,
+}
+
+impl Mirror {
+    fn create(device: &Device) -> io::Result<Mirror> {
+        let name = format!("{}{}", MIRROR_PREFIX, device.info.name);
+        Ok(Mirror {
+            device: VirtualDevice::mirror(&name, device)?,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Runs `passes` on the events a record completes, and forwards the report once it ends
+    /// without the parts that didn't pass.
+    fn process(
+        &mut self,
+        converter: &mut Converter,
+        device: &mut Device,
+        raw: &input_event,
+        passes: &mut dyn FnMut(Event) -> bool,
+    ) -> io::Result<()> {
+        let mut events = Vec::new();
+        converter.convert(device, raw, &mut |event| events.push(event));
+
+        match (raw.type_, raw.code) {
+            (EV_KEY, _) => {
+                // Every event is looked at, since a delayed one might be let through.
+                let mut passed = true;
+                for event in events {
+                    passed &= passes(event);
+                }
+                if passed {
+                    self.pending.push(*raw);
+                }
+            }
+            (EV_SYN, SYN_REPORT) => {
+                let mut drop_motion = false;
+                let mut drop_wheel = false;
+                let mut drop_pen = false;
+                let mut drop_touch = false;
+                for event in events {
+                    let event_type = event.event_type;
+                    let code = event.platform_code;
+                    let dropped = !passes(event);
+                    match event_type {
+                        // Keys only come with a report when catching up after dropped events.
+                        EventType::KeyPress(_) | EventType::KeyRelease(_) if !dropped => {
+                            let pressed = matches!(event_type, EventType::KeyPress(_));
+                            self.pending.push(input_event {
+                                type_: EV_KEY,
+                                code: (code - X11_KEYCODE_OFFSET) as u16,
+                                value: pressed.into(),
+                                ..*raw
+                            });
+                        }
+                        EventType::MouseMove { .. } => drop_motion |= dropped,
+                        EventType::Wheel { .. } => drop_wheel |= dropped,
+                        EventType::PenMove { .. } => drop_pen |= dropped,
+                        EventType::TouchBegin { .. }
+                        | EventType::TouchUpdate { .. }
+                        | EventType::TouchEnd { .. } => drop_touch |= dropped,
+                        _ => {}
+                    }
+                }
+                self.pending.retain(|raw| {
+                    !(drop_motion && is_motion(raw)
+                        || drop_wheel && is_wheel(raw)
+                        || drop_pen && (is_motion(raw) || is_pen(raw))
+                        || drop_touch && is_touch(raw))
+                });
+                if !self.pending.is_empty() {
+                    self.pending.push(*raw);
+                    self.device.write(&self.pending)?;
+                    self.pending.clear();
+                }
+            }
+            (EV_SYN, SYN_DROPPED) => self.pending.clear(),
+            _ => self.pending.push(*raw),
+        }
+        Ok(())
+    }
+}
+
+fn is_motion(raw: &input_event) -> bool {
+    match raw.type_ {
+        EV_REL => matches!(raw.code, REL_X | REL_Y),
+        EV_ABS => matches!(
+            raw.code,
+            ABS_X | ABS_Y | ABS_MT_POSITION_X | ABS_MT_POSITION_Y
+        ),
+        _ => false,
+    }
+}
+
+fn is_wheel(raw: &input_event) -> bool {
+    raw.type_ == EV_REL
+        && matches!(
+            raw.code,
+            REL_WHEEL | REL_HWHEEL | REL_WHEEL_HI_RES | REL_HWHEEL_HI_RES
+        )
+}
+
+fn is_pen(raw: &input_event) -> bool {
+    raw.type_ == EV_ABS && matches!(raw.code, ABS_PRESSURE | ABS_TILT_X | ABS_TILT_Y)
+}
+
+fn is_touch(raw: &input_event) -> bool {
+    raw.type_ == EV_ABS && (ABS_MT_SLOT..=ABS_MT_TRACKING_ID).contains(&raw.code)
+}
+
+fn should_grab(device: &Device) -> bool {
+    if device.info.name.starts_with(MIRROR_PREFIX) {
+        return false;
+    }
+    match device.kind() {
+        DeviceKind::Keyboard => true,
+        DeviceKind::Pointer | DeviceKind::Touchpad => !keyboard_only(),
+        // Switches and the like have nothing to filter and aren't mirrored faithfully.
+        DeviceKind::Other => false,
+    }
+}
+
+/// Waits for every key of the device to be up.
+///
+/// Grabbing while a key is down would leave it stuck for everyone else, as its release would
+/// only reach us.
+fn wait_for_release(device: &Device) {
+    let start = Instant::now();
+    while start.elapsed() < RELEASE_TIMEOUT && keys_down(device) {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn keys_down(device: &Device) -> bool {
+    matches!(device.key_state(), Ok(keys) if keys.iter().any(|byte| *byte != 0))
+}
+
+fn inject(injector: &mut Option<VirtualInput>, events: &[EventType]) -> Result<(), SimulateError> {
+    let injector = match injector {
+        Some(injector) => injector,
+        None => injector.insert(VirtualInput::create(MIRROR_PREFIX)?),
+    };
+    for event_type in events {
+        injector.simulate(event_type)?;
+    }
+    Ok(())
+}
+
+fn take_grab(device: &Device) -> io::Result<()> {
+    wait_for_release(device);
+    ioctl_int(device.as_raw_fd(), EVIOCGRAB, 1)?;
+    Ok(())
+}
+
+/// Grabs keyboards and pointers exclusively, and passes on the events the callback lets through
+/// through a virtual mirror of each device.
+///
+/// Devices plugged in while grabbing are grabbed too, and a device whose mirror stops accepting
+/// events is released. The grab is released when the devices are closed, i.e. when the process
+/// exits. Like [`listen`](super::listen), this needs read access
+/// to `/dev/input/event*`, and additionally write access to `/dev/uinput`.
+///
+/// Dropping a [`MouseMove`](EventType::MouseMove), [`Wheel`](EventType::Wheel),
+/// [`PenMove`](EventType::PenMove) or touch event withholds that part of the device's report;
+/// everything the callback doesn't see, like touchpad fingers, is passed on untouched.
+/// Replacements, and delayed events let through late, are sent through virtual devices of their
+/// own, created on first use. Touch and pen events can't be, so they can't be delayed either.
+pub fn grab<T>(callback: T) -> Result<(), GrabError>
+where
+    T: FnMut(Event) -> GrabAction + 'static,
+{
+    let mut callback = rewriter(callback);
+    let mut injector: Option<VirtualInput> = None;
+    let mut passes = move |event| match callback(event) {
+        Outcome::Pass => true,
+        Outcome::Drop => false,
+        Outcome::Inject(events) => {
+            if let Err(e) = inject(&mut injector, &events) {
+                log::warn!("Can't inject {:?}: {}", events, e);
+            }
+            false
+        }
+    };
+
+    let mut registry = DeviceRegistry::new()?;
+    let epoll = Epoll::new()?;
+    epoll.add(registry.as_raw_fd(), HOTPLUG)?;
+
+    let mut mirrors = HashMap::new();
+    for device in registry.devices().filter(|device| should_grab(device)) {
+        mirrors.insert(device.id(), Mirror::create(device)?);
+    }
+    thread::sleep(SETTLE_TIME);
+    for device in registry.devices() {
+        if !mirrors.contains_key(&device.id()) {
+            continue;
+        }
+        if let Err(e) = take_grab(device) {
+            log::warn!("Can't grab {}: {}", device.info.path.display(), e);
+            mirrors.remove(&device.id());
+            continue;
+        }
+        epoll.add(device.as_raw_fd(), device.id().into())?;
+    }
+
+    // Devices plugged in while grabbing, and when, until their mirror has settled and their keys
+    // are up. Waiting for them here would hold up every other device.
+    let mut pending: HashMap<u32, Instant> = HashMap::new();
+    let mut converter = Converter::new();
+    let mut ready = [epoll::Event::new(Events::empty(), 0); 16];
+    let mut buf: [input_event; 64] = unsafe { zeroed() };
+    loop {
+        let timeout = if pending.is_empty() {
+            -1
+        } else {
+            PENDING_INTERVAL.as_millis() as i32
+        };
+        let n = epoll.wait(timeout, &mut ready)?;
+        pending.retain(|&id, added| {
+            let Some(device) = registry.get_mut(id) else {
+                return false;
+            };
+            let waited = added.elapsed();
+            if waited < SETTLE_TIME || waited < SETTLE_TIME + RELEASE_TIMEOUT && keys_down(device) {
+                return true;
+            }
+            let grabbed = ioctl_int(device.as_raw_fd(), EVIOCGRAB, 1)
+                .and_then(|_| epoll.add(device.as_raw_fd(), id.into()));
+            if let Err(e) = grabbed {
+                log::warn!("Can't grab {}: {}", device.info.path.display(), e);
+                mirrors.remove(&id);
+            }
+            false
+        });
+        for ready in &ready[..n] {
+            if ready.data == HOTPLUG {
+                for change in registry.refresh()? {
+                    match change {
+                        Change::Added(id) => {
+                            let Some(device) = registry.get_mut(id) else {
+                                continue;
+                            };
+                            if !should_grab(device) {
+                                continue;
+                            }
+                            match Mirror::create(device) {
+                                Ok(mirror) => {
+                                    mirrors.insert(id, mirror);
+                                    pending.insert(id, Instant::now());
+                                }
+                                Err(e) => {
+                                    log::warn!("Can't grab {}: {}", device.info.path.display(), e)
+                                }
+                            }
+                        }
+                        Change::Removed(id) => {
+                            mirrors.remove(&id);
+                            pending.remove(&id);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let id = ready.data as u32;
+            let (Some(device), Some(mirror)) = (registry.get_mut(id), mirrors.get_mut(&id)) else {
+                continue;
+            };
+            match device.read(&mut buf) {
+                Ok(count) => {
+                    let written = buf[..count].iter().try_for_each(|raw| {
+                        mirror.process(&mut converter, device, raw, &mut passes)
+                    });
+                    // Leave the device to everyone else rather than swallowing its input.
+                    if let Err(e) = written {
+                        log::warn!("Releasing {}: {}", device.info.path.display(), e);
+                        let _ = epoll.remove(device.as_raw_fd());
+                        let _ = ioctl_int(device.as_raw_fd(), EVIOCGRAB, 0);
+                        mirrors.remove(&id);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    log::debug!("Closing {}: {}", device.info.path.display(), e);
+                    let _ = epoll.remove(device.as_raw_fd());
+                    registry.remove(id);
+                    mirrors.remove(&id);
+                }
+            }
+        }
+    }
+}
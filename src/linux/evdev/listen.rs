@@ -0,0 +1,136 @@
+use crate::handle::ListenHandle;
+use crate::linux::common::StopFd;
+use crate::linux::evdev::common::Converter;
+use crate::linux::evdev::registry::{Change, DeviceRegistry};
+use crate::linux::ListenError;
+use crate::rdevin::Event;
+use epoll::{ControlOptions, Events};
+use libc::input_event;
+use std::io;
+use std::mem::zeroed;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+
+/// Owns an epoll instance and closes it on drop.
+pub(crate) struct Epoll(pub RawFd);
+
+impl Epoll {
+    pub fn new() -> io::Result<Epoll> {
+        Ok(Epoll(epoll::create(true)?))
+    }
+
+    pub fn add(&self, fd: RawFd, token: u64) -> io::Result<()> {
+        epoll::ctl(
+            self.0,
+            ControlOptions::EPOLL_CTL_ADD,
+            fd,
+            epoll::Event::new(Events::EPOLLIN, token),
+        )
+    }
+
+    pub fn remove(&self, fd: RawFd) -> io::Result<()> {
+        epoll::ctl(
+            self.0,
+            ControlOptions::EPOLL_CTL_DEL,
+            fd,
+            epoll::Event::new(Events::empty(), 0),
+        )
+    }
+
+    /// Waits for readiness, retrying when interrupted by a signal.
+    pub fn wait(&self, timeout: i32, buf: &mut [epoll::Event]) -> io::Result<usize> {
+        loop {
+            match epoll::wait(self.0, timeout, buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        let _ = epoll::close(self.0);
+    }
+}
+
+/// epoll token of the registry's inotify descriptor. Devices use their id.
+pub(crate) const HOTPLUG: u64 = u64::MAX;
+/// epoll token of the descriptor that signals the listener to stop.
+pub(crate) const STOP: u64 = u64::MAX - 1;
+
+pub fn listen<T>(callback: T) -> Result<ListenHandle, ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenHandle::spawn(move |started| {
+        let setup = || -> Result<(DeviceRegistry, Epoll, Arc<StopFd>), ListenError> {
+            let registry = DeviceRegistry::new()?;
+            let epoll = Epoll::new()?;
+            let stop = Arc::new(StopFd::new()?);
+            epoll.add(registry.as_raw_fd(), HOTPLUG)?;
+            epoll.add(stop.as_raw_fd(), STOP)?;
+            for device in registry.devices() {
+                epoll.add(device.as_raw_fd(), device.id().into())?;
+            }
+            Ok((registry, epoll, stop))
+        };
+        let (registry, epoll, stop) = match setup() {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return Ok(());
+            }
+        };
+        let signal = stop.clone();
+        let _ = started.send(Ok(Box::new(move || signal.signal())));
+        run(registry, epoll, callback)
+    })
+}
+
+fn run<T>(mut registry: DeviceRegistry, epoll: Epoll, mut callback: T) -> Result<(), ListenError>
+where
+    T: FnMut(Event),
+{
+    let mut converter = Converter::new();
+    let mut ready = [epoll::Event::new(Events::empty(), 0); 16];
+    let mut buf: [input_event; 64] = unsafe { zeroed() };
+    loop {
+        let n = epoll.wait(-1, &mut ready)?;
+        for ready in &ready[..n] {
+            if ready.data == STOP {
+                return Ok(());
+            }
+            if ready.data == HOTPLUG {
+                for change in registry.refresh()? {
+                    // Removed devices were closed, which already took them out of the epoll set.
+                    if let Change::Added(id) = change {
+                        if let Some(device) = registry.get_mut(id) {
+                            epoll.add(device.as_raw_fd(), id.into())?;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let id = ready.data as u32;
+            let Some(device) = registry.get_mut(id) else {
+                continue;
+            };
+            match device.read(&mut buf) {
+                Ok(count) => {
+                    for raw in &buf[..count] {
+                        converter.convert(device, raw, &mut callback);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    // ENODEV once the device is unplugged.
+                    log::debug!("Closing {}: {}", device.info.path.display(), e);
+                    let _ = epoll.remove(device.as_raw_fd());
+                    registry.remove(id);
+                }
+            }
+        }
+    }
+}
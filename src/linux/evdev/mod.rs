@@ -0,0 +1,39 @@
+//! Input through the kernel's evdev interface, which works without an X server: on Wayland, on a
+//! bare TTY and on headless machines.
+//!
+//! Reading `/dev/input/event*` usually requires root or membership of the `input` group. Devices
+//! plugged in or removed while listening are picked up automatically, and every event records the
+//! device it came from in [`Event::device`](crate::Event::device). Touchscreens and drawing tablets
+//! report their contacts and pens too, with events like
+//! [`TouchBegin`](crate::EventType::TouchBegin) and [`PenMove`](crate::EventType::PenMove).
+//!
+//! [`simulate`] injects events through virtual uinput devices instead, which needs write access to
+//! `/dev/uinput`. Absolute mouse motion needs the screen size, which is asked of the X server when
+//! there is one and can otherwise be given with [`set_screen_size`].
+//!
+//! [`grab`] combines the two: it takes keyboards and pointers away from everyone else and
+//! re-emits what its callback lets through on virtual copies of them.
+//!
+//! ```no_run
+//! let handle = rdevin::linux::evdev::listen(|e| {
+//!     dbg!(e);
+//! })?;
+//! handle.join()?;
+//! # Ok::<(), rdevin::ListenError>(())
+//! ```
+
+mod common;
+mod device;
+mod grab;
+mod listen;
+mod registry;
+mod simulate;
+mod touch;
+mod typing;
+mod uinput;
+
+pub use crate::linux::evdev::device::{devices, DeviceInfo, DeviceKind};
+pub use crate::linux::evdev::grab::grab;
+pub use crate::linux::evdev::listen::listen;
+pub use crate::linux::evdev::simulate::{set_screen_size, simulate};
+pub(crate) use crate::linux::evdev::typing::type_char;
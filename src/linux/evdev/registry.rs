@@ -0,0 +1,105 @@
+use crate::linux::evdev::device::{self, event_node_id, Device, INPUT_DIR};
+use inotify::{EventMask, Inotify, WatchMask};
+use std::collections::{hash_map::Entry, HashMap};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+
+/// A change to the set of open devices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Change {
+    Added(u32),
+    Removed(u32),
+}
+
+/// The open input devices, kept up to date by watching `/dev/input`.
+///
+/// udev creates event nodes as root-only and fixes up their permissions afterwards, so a node we
+/// can't open yet is retried when its attributes change.
+pub(crate) struct DeviceRegistry {
+    inotify: Inotify,
+    devices: HashMap<u32, Device>,
+    buffer: Vec<u8>,
+}
+
+impl DeviceRegistry {
+    pub fn new() -> io::Result<DeviceRegistry> {
+        let inotify = Inotify::init()?;
+        // Watch before listing so that nothing plugged in between the two is missed.
+        inotify.watches().add(
+            INPUT_DIR,
+            WatchMask::CREATE | WatchMask::ATTRIB | WatchMask::DELETE,
+        )?;
+        let devices = device::open_all()?
+            .into_iter()
+            .map(|device| (device.id(), device))
+            .collect();
+        Ok(DeviceRegistry {
+            inotify,
+            devices,
+            buffer: vec![0; 4096],
+        })
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Device> {
+        self.devices.get_mut(&id)
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices.values()
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Device> {
+        self.devices.remove(&id)
+    }
+
+    /// Applies pending notifications from `/dev/input`.
+    pub fn refresh(&mut self) -> io::Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        loop {
+            let events = match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            let mut pending = Vec::new();
+            for event in events {
+                let Some(name) = event.name else {
+                    continue;
+                };
+                let path = Path::new(INPUT_DIR).join(name);
+                let Some(id) = event_node_id(&path) else {
+                    continue;
+                };
+                pending.push((event.mask, id, path));
+            }
+            if pending.is_empty() {
+                break;
+            }
+            for (mask, id, path) in pending {
+                if mask.contains(EventMask::DELETE) {
+                    if self.devices.remove(&id).is_some() {
+                        changes.push(Change::Removed(id));
+                    }
+                } else if let Entry::Vacant(entry) = self.devices.entry(id) {
+                    match Device::open(&path) {
+                        Ok(device) => {
+                            log::debug!("Added {} ({})", path.display(), device.info.name);
+                            entry.insert(device);
+                            changes.push(Change::Added(id));
+                        }
+                        Err(e) => log::debug!("Can't open {} yet: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+impl AsRawFd for DeviceRegistry {
+    /// The inotify descriptor, readable when `/dev/input` changed.
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
@@ -0,0 +1,230 @@
+use crate::keycodes::linux::{code_from_button, code_from_key};
+use crate::linux::evdev::common::*;
+use crate::linux::evdev::uinput::{VirtualDevice, SETTLE_TIME};
+use crate::rdevin::{Button, EventType, Key, RawKey, SimulateError, WheelRemainder, WheelUnit};
+use std::convert::{TryFrom, TryInto};
+use std::sync::Mutex;
+use std::thread;
+
+pub const KEYBOARD_NAME: &str = "rdevin virtual keyboard";
+pub const MOUSE_NAME: &str = "rdevin virtual mouse";
+pub const POINTER_NAME: &str = "rdevin virtual pointer";
+
+/// The virtual devices behind [`simulate`], created on first use.
+///
+/// Absolute motion goes through a separate device whose axes span the screen, since uinput has
+/// no notion of pixels.
+pub(crate) struct VirtualInput {
+    /// Put before the device names.
+    prefix: String,
+    keyboard: VirtualDevice,
+    mouse: VirtualDevice,
+    pointer: Option<VirtualDevice>,
+    /// Scrolling short of a 120th of a notch.
+    wheel: WheelRemainder,
+    /// 120ths of a notch short of a whole notch.
+    notches: WheelRemainder,
+}
+
+lazy_static::lazy_static! {
+    static ref VIRTUAL_INPUT: Mutex<Option<VirtualInput>> = Mutex::new(None);
+    static ref SCREEN_SIZE: Mutex<Option<(u64, u64)>> = Mutex::new(None);
+}
+
+impl VirtualInput {
+    pub(crate) fn create(prefix: &str) -> Result<VirtualInput, SimulateError> {
+        let keys: Vec<u16> = (1..BTN_MISC).chain(KEY_OK..=KEY_MAX).collect();
+        let keyboard =
+            VirtualDevice::create(&format!("{}{}", prefix, KEYBOARD_NAME), &keys, &[], &[])?;
+        let buttons: Vec<u16> = (BTN_LEFT..=BTN_TASK).collect();
+        let mouse = VirtualDevice::create(
+            &format!("{}{}", prefix, MOUSE_NAME),
+            &buttons,
+            &[
+                REL_X,
+                REL_Y,
+                REL_WHEEL,
+                REL_HWHEEL,
+                REL_WHEEL_HI_RES,
+                REL_HWHEEL_HI_RES,
+            ],
+            &[],
+        )?;
+        thread::sleep(SETTLE_TIME);
+        Ok(VirtualInput {
+            prefix: prefix.to_owned(),
+            keyboard,
+            mouse,
+            pointer: None,
+            wheel: WheelRemainder::new(),
+            notches: WheelRemainder::new(),
+        })
+    }
+
+    fn pointer(&mut self) -> Result<&mut VirtualDevice, SimulateError> {
+        if self.pointer.is_none() {
+            let (width, height) = screen_size()?;
+            // A button makes udev and libinput treat the device as an absolute mouse rather than
+            // a touchscreen.
+            let pointer = VirtualDevice::create(
+                &format!("{}{}", self.prefix, POINTER_NAME),
+                &[BTN_LEFT],
+                &[],
+                &[
+                    (ABS_X, 0, width.saturating_sub(1).try_into()?),
+                    (ABS_Y, 0, height.saturating_sub(1).try_into()?),
+                ],
+            )?;
+            thread::sleep(SETTLE_TIME);
+            self.pointer = Some(pointer);
+        }
+        Ok(self.pointer.as_mut().unwrap())
+    }
+
+    pub(crate) fn simulate(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        match event_type {
+            EventType::KeyPress(key) => self.keyboard.emit(&[(EV_KEY, evdev_code(key)?, 1)])?,
+            EventType::KeyRelease(key) => self.keyboard.emit(&[(EV_KEY, evdev_code(key)?, 0)])?,
+            EventType::ButtonPress(button) => {
+                self.mouse.emit(&[(EV_KEY, button_code(button)?, 1)])?
+            }
+            EventType::ButtonRelease(button) => {
+                self.mouse.emit(&[(EV_KEY, button_code(button)?, 0)])?
+            }
+            EventType::MouseMove { x, y } => {
+                let x = if x.is_finite() { x.round() as i32 } else { 0 };
+                let y = if y.is_finite() { y.round() as i32 } else { 0 };
+                self.pointer()?
+                    .emit(&[(EV_ABS, ABS_X, x), (EV_ABS, ABS_Y, y)])?
+            }
+            EventType::Wheel {
+                delta_x,
+                delta_y,
+                unit,
+            } => {
+                // Like high-resolution wheels, report 120ths of a notch, and whole notches for
+                // the programs that only read those.
+                let (hi_res_x, hi_res_y) =
+                    self.wheel
+                        .take(*delta_x, *delta_y, *unit, WheelUnit::HighRes);
+                let (notches_x, notches_y) = self.notches.take(
+                    hi_res_x as f64,
+                    hi_res_y as f64,
+                    WheelUnit::HighRes,
+                    WheelUnit::Lines,
+                );
+                let mut events = Vec::with_capacity(4);
+                for (code, value) in [
+                    (REL_WHEEL_HI_RES, hi_res_y),
+                    (REL_HWHEEL_HI_RES, hi_res_x),
+                    (REL_WHEEL, notches_y),
+                    (REL_HWHEEL, notches_x),
+                ] {
+                    if value != 0 {
+                        events.push((EV_REL, code, i32::try_from(value)?));
+                    }
+                }
+                self.mouse.emit(&events)?
+            }
+            EventType::MouseMoveRelative { dx, dy } => {
+                let dx = if dx.is_finite() { dx.round() as i32 } else { 0 };
+                let dy = if dy.is_finite() { dy.round() as i32 } else { 0 };
+                self.mouse
+                    .emit(&[(EV_REL, REL_X, dx), (EV_REL, REL_Y, dy)])?
+            }
+            _ => return Err(SimulateError::Unsupported),
+        }
+        Ok(())
+    }
+}
+
+fn screen_size() -> Result<(u64, u64), SimulateError> {
+    if let Some(size) = *SCREEN_SIZE.lock().unwrap() {
+        return Ok(size);
+    }
+    crate::linux::display_size().map_err(|_| SimulateError::NoDisplay)
+}
+
+/// Sets the screen size used to place absolute [`MouseMove`](EventType::MouseMove)s.
+///
+/// Without an X server to ask, absolute motion fails with [`SimulateError::NoDisplay`] until this
+/// is called.
+pub fn set_screen_size(width: u64, height: u64) {
+    *SCREEN_SIZE.lock().unwrap() = Some((width, height));
+    // Recreate the absolute device with the new range on next use.
+    if let Some(input) = VIRTUAL_INPUT.lock().unwrap().as_mut() {
+        input.pointer = None;
+    }
+}
+
+/// Converts a key to its evdev code.
+fn evdev_code(key: &Key) -> Result<u16, SimulateError> {
+    let code = match key {
+        Key::RawKey(RawKey::LinuxConsoleKeycode(code)) => return Ok(u16::try_from(*code)?),
+        Key::RawKey(RawKey::LinuxXorgKeycode(code)) => *code,
+        Key::RawKey(_) => {
+            return Err(SimulateError::InvalidRawKey {
+                expected: "Linux".into(),
+                got: None,
+            })
+        }
+        _ => code_from_key(*key).ok_or(SimulateError::NoCode)?,
+    };
+    code.checked_sub(X11_KEYCODE_OFFSET)
+        .ok_or(SimulateError::NoCode)?
+        .try_into()
+        .map_err(Into::into)
+}
+
+fn button_code(button: &Button) -> Result<u16, SimulateError> {
+    match button {
+        Button::Left => Ok(BTN_LEFT),
+        Button::Right => Ok(BTN_RIGHT),
+        Button::Middle => Ok(BTN_MIDDLE),
+        // X11 numbering, as produced by the listeners.
+        button => match code_from_button(*button) {
+            Some(code @ 8..=12) => Ok(BTN_SIDE + u16::from(code) - 8),
+            _ => Err(SimulateError::NoCode),
+        },
+    }
+}
+
+/// Simulates an event through uinput virtual devices, which are created on first use and kept
+/// for the lifetime of the process.
+///
+/// Writing to `/dev/uinput` usually requires root or a udev rule granting access.
+pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    let mut guard = VIRTUAL_INPUT.lock().unwrap();
+    if guard.is_none() {
+        *guard = Some(VirtualInput::create("")?);
+    }
+    guard.as_mut().unwrap().simulate(event_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        // KEY_A
+        assert_eq!(evdev_code(&Key::KeyA).unwrap(), 30);
+        assert_eq!(
+            evdev_code(&Key::RawKey(RawKey::LinuxConsoleKeycode(30))).unwrap(),
+            30
+        );
+        assert_eq!(
+            evdev_code(&Key::RawKey(RawKey::LinuxXorgKeycode(38))).unwrap(),
+            30
+        );
+        assert!(evdev_code(&Key::RawKey(RawKey::ScanCode(30))).is_err());
+
+        for code in BTN_LEFT..=BTN_TASK {
+            let (event_type, _) = convert_key(code, 1).unwrap();
+            let EventType::ButtonPress(button) = event_type else {
+                panic!("{:#x} is not a button", code);
+            };
+            assert_eq!(button_code(&button).unwrap(), code);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+// Touchscreen contacts and tablet tools, which carry more state between reports than mice do.
+use crate::linux::evdev::common::*;
+use crate::rdevin::{EventType, PenButton, PenTool};
+use libc::input_absinfo;
+
+/// Most touchscreens track 10 fingers, and none come close to this.
+const MAX_SLOTS: usize = 64;
+
+/// A contact slot of a touchscreen.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Slot {
+    /// Whether a finger is in the slot.
+    pub active: bool,
+    pub x: i32,
+    pub y: i32,
+    /// What happened to the slot since the last report.
+    pub began: bool,
+    pub moved: bool,
+    pub ended: bool,
+}
+
+/// The contacts of a multi-touch screen, as told by the kernel's slotted (type B) protocol.
+#[derive(Debug, Default)]
+pub(crate) struct Touches {
+    pub slots: Vec<Slot>,
+    /// The slot `ABS_MT_*` records are about.
+    pub current: usize,
+    pub abs_x: Option<input_absinfo>,
+    pub abs_y: Option<input_absinfo>,
+}
+
+impl Touches {
+    pub fn new(slots: usize) -> Touches {
+        Touches {
+            slots: vec![Slot::default(); slots.min(MAX_SLOTS)],
+            ..Default::default()
+        }
+    }
+
+    /// Follows an `ABS_MT_*` record. Devices without slots ignore them.
+    pub fn update(&mut self, code: u16, value: i32) {
+        if code == ABS_MT_SLOT {
+            self.current = value.max(0) as usize;
+            return;
+        }
+        let Some(slot) = self.slots.get_mut(self.current) else {
+            return;
+        };
+        match code {
+            ABS_MT_TRACKING_ID if value < 0 => {
+                slot.ended |= slot.active;
+                slot.active = false;
+            }
+            ABS_MT_TRACKING_ID => {
+                // A new tracking ID in an active slot is another finger.
+                if slot.active {
+                    slot.ended = true;
+                }
+                slot.active = true;
+                slot.began = true;
+            }
+            ABS_MT_POSITION_X => {
+                slot.x = value;
+                slot.moved = true;
+            }
+            ABS_MT_POSITION_Y => {
+                slot.y = value;
+                slot.moved = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Turns what changed since the last report into events, placing contacts with `position`.
+    pub fn flush(&mut self, position: impl Fn(i32, i32) -> (f64, f64)) -> Vec<EventType> {
+        let mut events = Vec::new();
+        for (slot, touch) in self.slots.iter_mut().enumerate() {
+            let slot = slot as u32;
+            if touch.ended {
+                events.push(EventType::TouchEnd { slot });
+            }
+            if touch.active && (touch.began || touch.moved) {
+                let (x, y) = position(touch.x, touch.y);
+                events.push(if touch.began {
+                    EventType::TouchBegin { slot, x, y }
+                } else {
+                    EventType::TouchUpdate { slot, x, y }
+                });
+            }
+            touch.began = false;
+            touch.moved = false;
+            touch.ended = false;
+        }
+        events
+    }
+
+    /// Forgets the changes of an incomplete report.
+    pub fn reset(&mut self) {
+        for touch in &mut self.slots {
+            touch.began = false;
+            touch.moved = false;
+            touch.ended = false;
+        }
+    }
+}
+
+/// The tool of a tablet, and its axes.
+#[derive(Debug, Default)]
+pub(crate) struct Pen {
+    /// The tool in range, if any.
+    pub tool: Option<PenTool>,
+    pub tip: bool,
+    pub pressure: i32,
+    pub tilt_x: i32,
+    pub tilt_y: i32,
+    pub abs_pressure: Option<input_absinfo>,
+    pub abs_tilt_x: Option<input_absinfo>,
+    pub abs_tilt_y: Option<input_absinfo>,
+}
+
+impl Pen {
+    /// Converts the `EV_KEY` records of tablet tools: tools coming in and out of range, the tip
+    /// and the barrel buttons. Anything else is left to [`convert_key`].
+    pub fn convert_key(&mut self, code: u16, value: i32) -> Option<EventType> {
+        let pressed = match value {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let tool = match code {
+            BTN_TOOL_PEN => Some(PenTool::Pen),
+            BTN_TOOL_RUBBER => Some(PenTool::Eraser),
+            BTN_TOOL_BRUSH => Some(PenTool::Brush),
+            BTN_TOOL_PENCIL => Some(PenTool::Pencil),
+            BTN_TOOL_AIRBRUSH => Some(PenTool::Airbrush),
+            _ => None,
+        };
+        if let Some(tool) = tool {
+            if pressed {
+                self.tool = Some(tool);
+            } else if self.tool == Some(tool) {
+                self.tool = None;
+            }
+            return Some(EventType::PenProximity {
+                tool,
+                in_range: pressed,
+            });
+        }
+        let button = match code {
+            // Touchscreens report fingers with BTN_TOUCH too. The tip may be lifted after the
+            // tool has left.
+            BTN_TOUCH if self.tool.is_some() || self.tip => {
+                self.tip = pressed;
+                PenButton::Tip
+            }
+            BTN_STYLUS => PenButton::Primary,
+            BTN_STYLUS2 => PenButton::Secondary,
+            BTN_STYLUS3 => PenButton::Tertiary,
+            _ => return None,
+        };
+        Some(if pressed {
+            EventType::PenButtonPress(button)
+        } else {
+            EventType::PenButtonRelease(button)
+        })
+    }
+
+    /// Follows an axis record, returning whether it was one of the pen's.
+    pub fn update(&mut self, code: u16, value: i32) -> bool {
+        match code {
+            ABS_PRESSURE => self.pressure = value,
+            ABS_TILT_X => self.tilt_x = value,
+            ABS_TILT_Y => self.tilt_y = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The current pressure, from 0 to 1.
+    pub fn pressure(&self) -> f64 {
+        match &self.abs_pressure {
+            Some(info) if info.maximum > info.minimum => {
+                let range = info.maximum as f64 - info.minimum as f64;
+                ((self.pressure as f64 - info.minimum as f64) / range).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The current tilt in degrees.
+    pub fn tilt(&self) -> (f64, f64) {
+        (
+            tilt_degrees(self.tilt_x, self.abs_tilt_x.as_ref()),
+            tilt_degrees(self.tilt_y, self.abs_tilt_y.as_ref()),
+        )
+    }
+}
+
+/// Tilt resolutions are in units per radian. Drivers that don't give one report degrees.
+fn tilt_degrees(value: i32, info: Option<&input_absinfo>) -> f64 {
+    match info {
+        Some(info) if info.resolution > 0 => (value as f64 / info.resolution as f64).to_degrees(),
+        _ => value as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::zeroed;
+
+    #[test]
+    fn test_touches() {
+        let mut touches = Touches::new(2);
+        let position = |x, y| (f64::from(x), f64::from(y));
+        for (code, value) in [
+            (ABS_MT_SLOT, 0),
+            (ABS_MT_TRACKING_ID, 10),
+            (ABS_MT_POSITION_X, 100),
+            (ABS_MT_POSITION_Y, 200),
+            (ABS_MT_SLOT, 1),
+            (ABS_MT_TRACKING_ID, 11),
+            (ABS_MT_POSITION_X, 300),
+            (ABS_MT_POSITION_Y, 400),
+        ] {
+            touches.update(code, value);
+        }
+        assert_eq!(
+            touches.flush(position),
+            vec![
+                EventType::TouchBegin {
+                    slot: 0,
+                    x: 100.0,
+                    y: 200.0
+                },
+                EventType::TouchBegin {
+                    slot: 1,
+                    x: 300.0,
+                    y: 400.0
+                },
+            ]
+        );
+
+        // The current slot carries over reports.
+        touches.update(ABS_MT_POSITION_X, 310);
+        touches.update(ABS_MT_SLOT, 0);
+        touches.update(ABS_MT_TRACKING_ID, -1);
+        assert_eq!(
+            touches.flush(position),
+            vec![
+                EventType::TouchEnd { slot: 0 },
+                EventType::TouchUpdate {
+                    slot: 1,
+                    x: 310.0,
+                    y: 400.0
+                },
+            ]
+        );
+        assert_eq!(touches.flush(position), vec![]);
+
+        // Slots the device doesn't have are ignored.
+        touches.update(ABS_MT_SLOT, 5);
+        touches.update(ABS_MT_TRACKING_ID, 12);
+        assert_eq!(touches.flush(position), vec![]);
+    }
+
+    #[test]
+    fn test_pen() {
+        let mut pen = Pen::default();
+        assert_eq!(pen.convert_key(BTN_TOUCH, 1), None);
+        assert_eq!(
+            pen.convert_key(BTN_TOOL_RUBBER, 1),
+            Some(EventType::PenProximity {
+                tool: PenTool::Eraser,
+                in_range: true
+            })
+        );
+        assert_eq!(
+            pen.convert_key(BTN_TOUCH, 1),
+            Some(EventType::PenButtonPress(PenButton::Tip))
+        );
+        pen.convert_key(BTN_TOOL_RUBBER, 0);
+        assert_eq!(pen.tool, None);
+        assert_eq!(
+            pen.convert_key(BTN_TOUCH, 0),
+            Some(EventType::PenButtonRelease(PenButton::Tip))
+        );
+        assert_eq!(
+            pen.convert_key(BTN_STYLUS2, 1),
+            Some(EventType::PenButtonPress(PenButton::Secondary))
+        );
+        assert_eq!(pen.convert_key(BTN_LEFT, 1), None);
+
+        let mut info: input_absinfo = unsafe { zeroed() };
+        info.maximum = 2047;
+        pen.abs_pressure = Some(info);
+        assert!(pen.update(ABS_PRESSURE, 1023));
+        assert!((pen.pressure() - 0.5).abs() < 0.001);
+
+        info.minimum = -64;
+        info.maximum = 63;
+        info.resolution = 57;
+        pen.abs_tilt_x = Some(info);
+        assert!(pen.update(ABS_TILT_X, 57));
+        assert!(pen.update(ABS_TILT_Y, 20));
+        assert!(!pen.update(ABS_X, 20));
+        let (tilt_x, tilt_y) = pen.tilt();
+        assert!((tilt_x - 57.3).abs() < 0.1);
+        assert_eq!(tilt_y, 20.0);
+    }
+}
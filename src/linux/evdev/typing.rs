@@ -0,0 +1,76 @@
+use crate::linux::evdev::simulate::simulate;
+use crate::rdevin::{EventType, Key, SimulateError};
+use crate::sequence::key_from_char;
+
+/// Shifted characters and the unshifted ones on the same keys, on a US layout.
+const SHIFTED: [(char, char); 21] = [
+    ('~', '`'),
+    ('!', '1'),
+    ('@', '2'),
+    ('#', '3'),
+    ('$', '4'),
+    ('%', '5'),
+    ('^', '6'),
+    ('&', '7'),
+    ('*', '8'),
+    ('(', '9'),
+    (')', '0'),
+    ('_', '-'),
+    ('+', '='),
+    ('{', '['),
+    ('}', ']'),
+    ('|', '\\'),
+    (':', ';'),
+    ('"', '\''),
+    ('<', ','),
+    ('>', '.'),
+    ('?', '/'),
+];
+
+/// The key that types `chr` on a US layout, and whether it needs Shift.
+fn key_with_shift(chr: char) -> Option<(Key, bool)> {
+    if chr.is_ascii_uppercase() {
+        return key_from_char(chr.to_ascii_lowercase()).map(|key| (key, true));
+    }
+    if let Some(&(_, unshifted)) = SHIFTED.iter().find(|&&(shifted, _)| shifted == chr) {
+        return key_from_char(unshifted).map(|key| (key, true));
+    }
+    key_from_char(chr).map(|key| (key, false))
+}
+
+/// Types `chr` on the virtual keyboard behind [`simulate`], returning whether it could.
+///
+/// The kernel knows nothing of layouts, so this assumes a US one and can't type anything it
+/// lacks.
+pub(crate) fn type_char(chr: char) -> Result<bool, SimulateError> {
+    let (key, shift) = match key_with_shift(chr) {
+        Some(found) => found,
+        None => return Ok(false),
+    };
+    if shift {
+        simulate(&EventType::KeyPress(Key::ShiftLeft))?;
+    }
+    let result =
+        simulate(&EventType::KeyPress(key)).and_then(|()| simulate(&EventType::KeyRelease(key)));
+    if shift {
+        simulate(&EventType::KeyRelease(Key::ShiftLeft))?;
+    }
+    result.map(|()| true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_with_shift() {
+        assert_eq!(key_with_shift('a'), Some((Key::KeyA, false)));
+        assert_eq!(key_with_shift('A'), Some((Key::KeyA, true)));
+        assert_eq!(key_with_shift('7'), Some((Key::Num7, false)));
+        assert_eq!(key_with_shift('&'), Some((Key::Num7, true)));
+        assert_eq!(key_with_shift('"'), Some((Key::Quote, true)));
+        assert_eq!(key_with_shift('\n'), Some((Key::Return, false)));
+        assert_eq!(key_with_shift('ö'), None);
+        assert_eq!(key_with_shift('✓'), None);
+    }
+}
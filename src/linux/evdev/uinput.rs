@@ -0,0 +1,152 @@
+use crate::linux::evdev::common::*;
+use crate::linux::evdev::device::Device;
+use libc::{input_absinfo, input_event, uinput_abs_setup, uinput_setup};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem::{size_of_val, zeroed};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+pub const UINPUT_PATH: &str = "/dev/uinput";
+
+/// How long the display server is given to notice new devices before they are used.
+pub const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// A virtual input device backed by `/dev/uinput`, destroyed on drop.
+#[derive(Debug)]
+pub(crate) struct VirtualDevice {
+    file: File,
+}
+
+impl VirtualDevice {
+    /// Creates a device advertising the given key, relative and absolute (`code, min, max`) axes.
+    pub fn create(
+        name: &str,
+        keys: &[u16],
+        rels: &[u16],
+        abs: &[(u16, i32, i32)],
+    ) -> io::Result<VirtualDevice> {
+        let abs: Vec<(u16, input_absinfo)> = abs
+            .iter()
+            .map(|(code, minimum, maximum)| {
+                let mut info: input_absinfo = unsafe { zeroed() };
+                info.minimum = *minimum;
+                info.maximum = *maximum;
+                (*code, info)
+            })
+            .collect();
+        Self::create_with(name, BUS_VIRTUAL, keys, rels, &abs, &[])
+    }
+
+    /// Creates a device on the same bus, with the same keys, axes and properties as `device`.
+    pub fn mirror(name: &str, device: &Device) -> io::Result<VirtualDevice> {
+        let capabilities = &device.capabilities;
+        let set = |bits: &[u8], max: u16| -> Vec<u16> {
+            (0..=max).filter(|bit| test_bit(bits, *bit)).collect()
+        };
+        let abs = set(&capabilities.abs, ABS_MAX)
+            .into_iter()
+            .filter_map(|code| {
+                Device::abs_info(device.as_raw_fd(), code)
+                    .ok()
+                    .map(|info| (code, info))
+            })
+            .collect::<Vec<_>>();
+        Self::create_with(
+            name,
+            device.bus,
+            &set(&capabilities.key, KEY_MAX),
+            &set(&capabilities.rel, REL_MAX),
+            &abs,
+            &set(&capabilities.props, INPUT_PROP_MAX),
+        )
+    }
+
+    fn create_with(
+        name: &str,
+        bus: u16,
+        keys: &[u16],
+        rels: &[u16],
+        abs: &[(u16, input_absinfo)],
+        props: &[u16],
+    ) -> io::Result<VirtualDevice> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+
+        if !keys.is_empty() {
+            ioctl_int(fd, UI_SET_EVBIT, EV_KEY.into())?;
+            for key in keys {
+                ioctl_int(fd, UI_SET_KEYBIT, (*key).into())?;
+            }
+        }
+        if !rels.is_empty() {
+            ioctl_int(fd, UI_SET_EVBIT, EV_REL.into())?;
+            for rel in rels {
+                ioctl_int(fd, UI_SET_RELBIT, (*rel).into())?;
+            }
+        }
+        if !abs.is_empty() {
+            ioctl_int(fd, UI_SET_EVBIT, EV_ABS.into())?;
+            for (code, info) in abs {
+                ioctl_int(fd, UI_SET_ABSBIT, (*code).into())?;
+                let mut setup: uinput_abs_setup = unsafe { zeroed() };
+                setup.code = *code;
+                setup.absinfo = *info;
+                unsafe { ioctl(fd, UI_ABS_SETUP, &mut setup)? };
+            }
+        }
+        for prop in props {
+            ioctl_int(fd, UI_SET_PROPBIT, (*prop).into())?;
+        }
+
+        let mut setup: uinput_setup = unsafe { zeroed() };
+        setup.id.bustype = bus;
+        setup.id.version = 1;
+        for (dst, src) in setup
+            .name
+            .iter_mut()
+            .zip(name.bytes().take(libc::UINPUT_MAX_NAME_SIZE - 1))
+        {
+            *dst = src as _;
+        }
+        unsafe {
+            ioctl(fd, UI_DEV_SETUP, &mut setup)?;
+            ioctl(fd, UI_DEV_CREATE, std::ptr::null_mut::<u8>())?;
+        }
+
+        Ok(VirtualDevice { file })
+    }
+
+    /// Writes the events followed by a `SYN_REPORT`.
+    pub fn emit(&mut self, events: &[(u16, u16, i32)]) -> io::Result<()> {
+        let raw: Vec<input_event> = events
+            .iter()
+            .chain(&[(EV_SYN, SYN_REPORT, 0)])
+            .map(|(type_, code, value)| {
+                let mut event: input_event = unsafe { zeroed() };
+                event.type_ = *type_;
+                event.code = *code;
+                event.value = *value;
+                event
+            })
+            .collect();
+        self.write(&raw)
+    }
+
+    /// Writes raw events as they are. The kernel stamps them with the current time.
+    pub fn write(&mut self, raw: &[input_event]) -> io::Result<()> {
+        let bytes =
+            unsafe { std::slice::from_raw_parts(raw.as_ptr() as *const u8, size_of_val(raw)) };
+        self.file.write_all(bytes)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        let _ = ioctl_int(self.file.as_raw_fd(), UI_DEV_DESTROY, 0);
+    }
+}
@@ -1,11 +1,14 @@
 use crate::rdevin::UnicodeInfo;
 // This code is awful. Good luck
-use crate::{key_from_code, Event, EventType, GrabError, Keyboard, KeyboardState, ListenError};
+use crate::{
+    key_from_code, Event, EventType, GrabAction, GrabError, Keyboard, KeyboardState, ListenError,
+    ModifierState, Origin,
+};
 use log::error;
 use mio::{unix::SourceFd, Events, Interest, Poll, Token};
 use std::{
     mem::zeroed,
-    os::raw::c_int,
+    os::raw::{c_int, c_uint},
     ptr,
     sync::{
         mpsc::{channel, Receiver, Sender},
@@ -14,9 +17,12 @@ use std::{
     thread,
     time::{Duration, SystemTime},
 };
-use x11::xlib::{self, GrabModeAsync, KeyPressMask, KeyReleaseMask, Window};
+use x11::xlib::{
+    self, ButtonPressMask, ButtonReleaseMask, GrabModeAsync, KeyPressMask, KeyReleaseMask,
+    PointerMotionMask, Window,
+};
 
-use super::common::KEYBOARD;
+use super::common::{self, KEYBOARD};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -26,7 +32,7 @@ pub enum Error {
 
 enum GrabEvent {
     Exit,
-    KeyEvent(Event),
+    InputEvent(Event),
 }
 
 enum GrabControl {
@@ -48,12 +54,12 @@ unsafe impl Sync for KeyboardGrabber {}
 lazy_static::lazy_static! {
     static ref GRAB_KEY_EVENT_SENDER: Arc<Mutex<Option<Sender<GrabEvent>>>> = Arc::new(Mutex::new(None));
     static ref GRAB_CONTROL_SENDER: Arc<Mutex<Option<Sender<GrabControl>>>> = Arc::new(Mutex::new(None));
+    static ref MODIFIERS: Mutex<ModifierState> = Mutex::new(ModifierState::empty());
 }
 
-const KEYPRESS_EVENT: i32 = 2;
 // It is ok to use unsafe mut here.
 static mut IS_GRABBING: bool = false;
-static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event) -> Option<Event>>> = None;
+static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event) -> GrabAction>> = None;
 const GRAB_RECV: Token = Token(0);
 
 impl KeyboardGrabber {
@@ -131,7 +137,13 @@ fn is_control(unicode_info: &Option<UnicodeInfo>) -> bool {
     })
 }
 
-fn convert_event(code: u32, is_press: bool) -> Event {
+fn modifiers(x_state: c_uint, event_type: &EventType) -> ModifierState {
+    let mut modifiers = MODIFIERS.lock().unwrap();
+    common::update_modifiers(&mut modifiers, x_state, event_type);
+    *modifiers
+}
+
+fn convert_event(code: u32, is_press: bool, x_state: c_uint) -> Event {
     let key = key_from_code(code);
     let event_type = if is_press {
         EventType::KeyPress(key)
@@ -154,12 +166,64 @@ fn convert_event(code: u32, is_press: bool) -> Event {
     };
 
     Event {
-        event_type,
         time: SystemTime::now(),
         unicode,
         platform_code,
         position_code: code as _,
         usb_hid: 0,
+        device: None,
+        modifiers: modifiers(x_state, &event_type),
+        origin: Origin::Unknown,
+        event_type,
+    }
+}
+
+fn convert_mouse_event(code: u32, type_: c_int, x_state: c_uint, x: f64, y: f64) -> Option<Event> {
+    let event_type = common::convert_event(code as _, type_, x, y)?;
+    Some(Event {
+        time: SystemTime::now(),
+        unicode: None,
+        platform_code: code as _,
+        position_code: code as _,
+        usb_hid: 0,
+        device: None,
+        modifiers: modifiers(x_state, &event_type),
+        origin: Origin::Unknown,
+        event_type,
+    })
+}
+
+fn convert_x_event(x_event: &xlib::XEvent) -> Option<Event> {
+    match x_event.get_type() {
+        type_ @ (xlib::KeyPress | xlib::KeyRelease) => {
+            let key = unsafe { x_event.key };
+            Some(convert_event(
+                key.keycode,
+                type_ == xlib::KeyPress,
+                key.state,
+            ))
+        }
+        type_ @ (xlib::ButtonPress | xlib::ButtonRelease) => {
+            let button = unsafe { x_event.button };
+            convert_mouse_event(
+                button.button,
+                type_,
+                button.state,
+                button.x_root as _,
+                button.y_root as _,
+            )
+        }
+        xlib::MotionNotify => {
+            let motion = unsafe { x_event.motion };
+            convert_mouse_event(
+                0,
+                xlib::MotionNotify,
+                motion.state,
+                motion.x_root as _,
+                motion.y_root as _,
+            )
+        }
+        _ => None,
     }
 }
 
@@ -167,7 +231,7 @@ fn grab_keys(display: Arc<Mutex<u64>>, grab_window: libc::c_ulong) {
     unsafe {
         let lock = display.lock().unwrap();
         let display = *lock as *mut xlib::Display;
-        xlib::XGrabKeyboard(
+        let status = xlib::XGrabKeyboard(
             display,
             grab_window,
             c_int::from(true),
@@ -175,11 +239,43 @@ fn grab_keys(display: Arc<Mutex<u64>>, grab_window: libc::c_ulong) {
             GrabModeAsync,
             xlib::CurrentTime,
         );
+        if status != xlib::GrabSuccess {
+            log::warn!("Can't grab the keyboard: {}", grab_failure(status));
+        }
+        // Like the keyboard, the pointer only reports to us while it is grabbed. Selecting button
+        // events on the root window instead fails whenever a window manager already did.
+        if !crate::keyboard_only() {
+            let status = xlib::XGrabPointer(
+                display,
+                grab_window,
+                c_int::from(true),
+                (ButtonPressMask | ButtonReleaseMask | PointerMotionMask) as _,
+                GrabModeAsync,
+                GrabModeAsync,
+                0,
+                0,
+                xlib::CurrentTime,
+            );
+            if status != xlib::GrabSuccess {
+                log::warn!("Can't grab the pointer: {}", grab_failure(status));
+            }
+        }
         xlib::XFlush(display);
     }
     thread::sleep(Duration::from_millis(50));
 }
 
+/// Why `XGrabKeyboard` or `XGrabPointer` failed.
+fn grab_failure(status: c_int) -> &'static str {
+    match status {
+        xlib::AlreadyGrabbed => "another client has grabbed it",
+        xlib::GrabInvalidTime => "invalid time",
+        xlib::GrabNotViewable => "the window isn't viewable",
+        xlib::GrabFrozen => "another grab has frozen it",
+        _ => "unknown status",
+    }
+}
+
 fn ungrab_keys(display: Arc<Mutex<u64>>) {
     {
         let lock = display.lock().unwrap();
@@ -192,6 +288,7 @@ fn ungrab_keys(display: Arc<Mutex<u64>>) {
 fn ungrab_keys_(display: *mut xlib::Display) {
     unsafe {
         xlib::XUngrabKeyboard(display, xlib::CurrentTime);
+        xlib::XUngrabPointer(display, xlib::CurrentTime);
         xlib::XFlush(display);
     }
 }
@@ -200,9 +297,10 @@ fn start_callback_event_thread(recv: Receiver<GrabEvent>) {
     thread::spawn(move || loop {
         if let Ok(data) = recv.recv() {
             match data {
-                GrabEvent::KeyEvent(event) => unsafe {
+                GrabEvent::InputEvent(event) => unsafe {
                     if let Some(callback) = &mut GLOBAL_CALLBACK {
-                        callback(event);
+                        // Nothing gets past the X grab, so there is nothing to act on.
+                        let _ = callback(event);
                     }
                 },
                 GrabEvent::Exit => {
@@ -239,11 +337,10 @@ fn read_x_event(x_event: &mut xlib::XEvent, display: *mut xlib::Display) {
             // https://linux.die.net/man/3/xnextevent
             xlib::XNextEvent(display, x_event);
         }
-        let keycode = unsafe { x_event.key.keycode };
-        let is_press = unsafe { x_event.type_ == KEYPRESS_EVENT };
-        let event = convert_event(keycode, is_press);
-        if let Some(tx) = GRAB_KEY_EVENT_SENDER.lock().unwrap().as_ref() {
-            tx.send(GrabEvent::KeyEvent(event)).ok();
+        if let Some(event) = convert_x_event(x_event) {
+            if let Some(tx) = GRAB_KEY_EVENT_SENDER.lock().unwrap().as_ref() {
+                tx.send(GrabEvent::InputEvent(event)).ok();
+            }
         }
     }
 }
@@ -368,9 +465,17 @@ pub fn is_grabbed() -> bool {
     unsafe { IS_GRABBING }
 }
 
+/// Starts listening through an X11 grab, which [`enable_grab`] and [`disable_grab`] turn on and
+/// off.
+///
+/// While grabbed, the X server sends every key and button to this client alone, XTest input
+/// included, so events can neither be let through nor injected: the callback's [`GrabAction`] is
+/// ignored and everything is withheld. Use [`evdev::grab`](super::evdev::grab) to rewrite events.
+///
+/// The X server refuses the grab while another client holds one, which is logged as a warning.
 pub fn start_grab_listen<T>(callback: T) -> Result<(), GrabError>
 where
-    T: FnMut(Event) -> Option<Event> + 'static,
+    T: FnMut(Event) -> GrabAction + 'static,
 {
     if is_grabbed() {
         return Ok(());
@@ -395,3 +500,49 @@ pub fn exit_grab_listen() {
     }
     send_grab_control(GrabControl::Exit);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Button, WheelUnit};
+
+    #[test]
+    fn test_convert_mouse_events() {
+        let mut x_event: xlib::XEvent = unsafe { zeroed() };
+        x_event.button = xlib::XButtonEvent {
+            type_: xlib::ButtonPress,
+            button: 3,
+            x_root: 10,
+            y_root: 20,
+            ..unsafe { zeroed() }
+        };
+        assert_eq!(
+            convert_x_event(&x_event).map(|e| e.event_type),
+            Some(EventType::ButtonPress(Button::Right))
+        );
+
+        x_event.button.button = 5;
+        assert_eq!(
+            convert_x_event(&x_event).map(|e| e.event_type),
+            Some(EventType::Wheel {
+                delta_x: 0.0,
+                delta_y: -1.0,
+                unit: WheelUnit::Lines,
+            })
+        );
+
+        x_event.button.type_ = xlib::ButtonRelease;
+        assert_eq!(convert_x_event(&x_event).map(|e| e.event_type), None);
+
+        x_event.motion = xlib::XMotionEvent {
+            type_: xlib::MotionNotify,
+            x_root: 10,
+            y_root: 20,
+            ..unsafe { zeroed() }
+        };
+        assert_eq!(
+            convert_x_event(&x_event).map(|e| e.event_type),
+            Some(EventType::MouseMove { x: 10.0, y: 20.0 })
+        );
+    }
+}
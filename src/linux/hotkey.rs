@@ -0,0 +1,467 @@
+use crate::handle::ListenHandle;
+use crate::keycodes::linux::code_from_key;
+use crate::linux::common::{StopFd, FALSE, TRUE};
+use crate::rdevin::{Event, EventType, Key, ModifierState, Origin};
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_uint};
+use std::os::unix::io::AsRawFd;
+use std::ptr::null;
+use std::sync::{Arc, Mutex, Once};
+use std::time::SystemTime;
+use thiserror::Error;
+use x11::xlib;
+
+/// Modifiers that are on or off regardless of which shortcut is meant. Each hotkey is grabbed once
+/// per combination of them.
+const IGNORED_MODIFIERS: [c_uint; 4] = [
+    0,
+    xlib::LockMask,
+    xlib::Mod2Mask,
+    xlib::LockMask | xlib::Mod2Mask,
+];
+
+/// Errors that occur when registering a hotkey.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HotkeyError {
+    #[error("No displays")]
+    NoDisplays,
+    #[error("{0:?} has no X11 keycode")]
+    UnknownKey(Key),
+    #[error("{0:?} is not a modifier")]
+    NotAModifier(Key),
+    #[error("Another client already grabbed this hotkey")]
+    AlreadyGrabbed,
+    #[error("X11 error {0}")]
+    XError(u8),
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+}
+
+/// A key pressed while holding modifiers, as registered with [`HotkeyGrabber::register`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hotkey {
+    pub key: Key,
+    pub modifiers: Vec<Key>,
+}
+
+fn modifier_mask(modifier: Key) -> Result<c_uint, HotkeyError> {
+    match modifier {
+        Key::ShiftLeft | Key::ShiftRight => Ok(xlib::ShiftMask),
+        Key::ControlLeft | Key::ControlRight => Ok(xlib::ControlMask),
+        Key::Alt => Ok(xlib::Mod1Mask),
+        Key::MetaLeft | Key::MetaRight => Ok(xlib::Mod4Mask),
+        Key::AltGr => Ok(xlib::Mod5Mask),
+        key => Err(HotkeyError::NotAModifier(key)),
+    }
+}
+
+/// The modifiers held according to the X modifier mask `x_state`, telling left from right with
+/// `is_down`. A side is assumed to be the left one when neither key is down anymore.
+fn held_modifiers(x_state: c_uint, is_down: impl Fn(Key) -> bool) -> ModifierState {
+    let sides = [
+        (xlib::ShiftMask, Key::ShiftLeft, Key::ShiftRight),
+        (xlib::ControlMask, Key::ControlLeft, Key::ControlRight),
+        (xlib::Mod1Mask, Key::Alt, Key::Alt),
+        (xlib::Mod4Mask, Key::MetaLeft, Key::MetaRight),
+        (xlib::Mod5Mask, Key::AltGr, Key::AltGr),
+    ];
+    let mut modifiers = ModifierState::empty();
+    for (mask, left, right) in sides {
+        if x_state & mask == 0 {
+            continue;
+        }
+        let right_down = is_down(right);
+        if right_down {
+            modifiers |= ModifierState::from_key(right);
+        }
+        if is_down(left) || !right_down {
+            modifiers |= ModifierState::from_key(left);
+        }
+    }
+    modifiers.set(ModifierState::CAPS_LOCK, x_state & xlib::LockMask != 0);
+    modifiers.set(ModifierState::NUM_LOCK, x_state & xlib::Mod2Mask != 0);
+    modifiers
+}
+
+/// The keys down on the display right now, as a bit per keycode.
+fn keymap(display: *mut xlib::Display) -> [u8; 32] {
+    let mut keys = [0; 32];
+    unsafe { xlib::XQueryKeymap(display, keys.as_mut_ptr()) };
+    keys.map(|byte| byte as u8)
+}
+
+/// The keycode and modifier mask to grab for a hotkey.
+fn combination(key: Key, modifiers: &[Key]) -> Result<(u32, c_uint), HotkeyError> {
+    let keycode = code_from_key(key).ok_or(HotkeyError::UnknownKey(key))?;
+    let mask = modifiers.iter().try_fold(0, |mask, &modifier| {
+        modifier_mask(modifier).map(|m| mask | m)
+    })?;
+    Ok((keycode, mask))
+}
+
+/// `XEventsQueued` mode that only counts the events Xlib has already read. Not in the x11 crate.
+const QUEUED_ALREADY: c_int = 0;
+
+/// The mask of the modifiers a hotkey is registered with.
+const MODIFIER_MASK: c_uint =
+    xlib::ShiftMask | xlib::ControlMask | xlib::Mod1Mask | xlib::Mod4Mask | xlib::Mod5Mask;
+
+type ErrorHandler = unsafe extern "C" fn(*mut xlib::Display, *mut xlib::XErrorEvent) -> c_int;
+
+static INSTALL_HANDLER: Once = Once::new();
+/// The handler that was installed before ours, which gets the errors of other connections.
+static PREVIOUS_HANDLER: Mutex<Option<ErrorHandler>> = Mutex::new(None);
+/// The last error of each hotkey connection, by display address.
+static X_ERRORS: Mutex<Vec<(usize, u8)>> = Mutex::new(Vec::new());
+
+unsafe extern "C" fn record_error(
+    display: *mut xlib::Display,
+    event: *mut xlib::XErrorEvent,
+) -> c_int {
+    if let Some((_, error)) = X_ERRORS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter_mut()
+        .find(|(ours, _)| *ours == display as usize)
+    {
+        *error = (*event).error_code;
+        return 0;
+    }
+    let previous = *PREVIOUS_HANDLER.lock().unwrap_or_else(|e| e.into_inner());
+    match previous {
+        Some(previous) => previous(display, event),
+        None => 0,
+    }
+}
+
+/// Takes the last error of `display`, if it is a hotkey connection.
+fn take_error(display: *mut xlib::Display) -> u8 {
+    X_ERRORS
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|(ours, _)| *ours == display as usize)
+        .map_or(0, |(_, error)| std::mem::take(error))
+}
+
+/// An X connection shared by the grabbing and the listening thread. Xlib isn't thread safe by
+/// itself, so every call goes through the lock.
+struct Connection {
+    display: Mutex<*mut xlib::Display>,
+    /// Wakes the listening thread when another thread read its events off the socket.
+    wake: StopFd,
+}
+
+unsafe impl Send for Connection {}
+unsafe impl Sync for Connection {}
+
+impl Connection {
+    /// Waits for the server to handle the requests so far. Events that arrive meanwhile are
+    /// queued where polling the socket won't see them, so the listening thread is woken up.
+    fn sync(&self, display: *mut xlib::Display) {
+        unsafe {
+            xlib::XSync(display, FALSE);
+            if xlib::XEventsQueued(display, QUEUED_ALREADY) > 0 {
+                self.wake.signal();
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let display = *self.display.get_mut().unwrap_or_else(|e| e.into_inner());
+        unsafe { xlib::XCloseDisplay(display) };
+        // A connection opened since may have the same address, and needs its own entry.
+        let mut errors = X_ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = errors
+            .iter()
+            .position(|(ours, _)| *ours == display as usize)
+        {
+            errors.remove(index);
+        }
+    }
+}
+
+type Hotkeys = Arc<Mutex<HashMap<(c_uint, c_uint), Hotkey>>>;
+
+/// Global shortcuts through passive key grabs.
+///
+/// The X server sends a registered key combination to the callback instead of the focused window,
+/// and every other key goes where it would have gone. NumLock and CapsLock don't matter.
+///
+/// The first grabber installs an X error handler to find out about hotkeys that are taken. It
+/// only handles errors of grabber connections, and passes the others to the handler before it.
+///
+/// ```no_run
+/// use rdevin::linux::HotkeyGrabber;
+/// use rdevin::Key;
+///
+/// let mut grabber = HotkeyGrabber::new(|hotkey, _event| println!("{:?}", hotkey))?;
+/// grabber.register(Key::KeyK, &[Key::ControlLeft, Key::Alt])?;
+/// # Ok::<(), rdevin::linux::HotkeyError>(())
+/// ```
+pub struct HotkeyGrabber {
+    connection: Arc<Connection>,
+    root: xlib::Window,
+    hotkeys: Hotkeys,
+    handle: Option<ListenHandle>,
+}
+
+impl HotkeyGrabber {
+    /// Starts waiting for hotkeys on a new thread, calling `callback` whenever a registered one is
+    /// pressed.
+    pub fn new<T>(callback: T) -> Result<HotkeyGrabber, HotkeyError>
+    where
+        T: FnMut(&Hotkey, Event) + Send + 'static,
+    {
+        let wake = StopFd::new()?;
+        let display = unsafe { xlib::XOpenDisplay(null()) };
+        if display.is_null() {
+            return Err(HotkeyError::NoDisplays);
+        }
+        INSTALL_HANDLER.call_once(|| {
+            let previous = unsafe { xlib::XSetErrorHandler(Some(record_error)) };
+            *PREVIOUS_HANDLER.lock().unwrap() = previous;
+        });
+        X_ERRORS.lock().unwrap().push((display as usize, 0));
+        let connection = Arc::new(Connection {
+            display: Mutex::new(display),
+            wake,
+        });
+        let root = unsafe { xlib::XDefaultRootWindow(display) };
+        let hotkeys = Hotkeys::default();
+        let handle = {
+            let connection = connection.clone();
+            let hotkeys = hotkeys.clone();
+            ListenHandle::spawn(move |started| {
+                let stop = match StopFd::new() {
+                    Ok(stop) => Arc::new(stop),
+                    Err(e) => {
+                        let _ = started.send(Err(e.into()));
+                        return Ok(());
+                    }
+                };
+                let signal = stop.clone();
+                let _ = started.send(Ok(Box::new(move || signal.signal())));
+                run(&connection, &hotkeys, &stop, callback).map_err(Into::into)
+            })
+        }
+        .map_err(|e| match e {
+            // Only setting up the stop signal can fail.
+            crate::ListenError::Io(e) => HotkeyError::Io(e),
+            _ => HotkeyError::NoDisplays,
+        })?;
+        Ok(HotkeyGrabber {
+            connection,
+            root,
+            hotkeys,
+            handle: Some(handle),
+        })
+    }
+
+    /// Grabs `key` while exactly `modifiers` are held. Left and right modifiers are the same to
+    /// X11.
+    pub fn register(&mut self, key: Key, modifiers: &[Key]) -> Result<(), HotkeyError> {
+        let (keycode, mask) = combination(key, modifiers)?;
+        let display = self.connection.display.lock().unwrap();
+        self.connection.sync(*display);
+        take_error(*display);
+        for ignored in IGNORED_MODIFIERS {
+            unsafe {
+                xlib::XGrabKey(
+                    *display,
+                    keycode as c_int,
+                    mask | ignored,
+                    self.root,
+                    TRUE,
+                    xlib::GrabModeAsync,
+                    xlib::GrabModeAsync,
+                )
+            };
+        }
+        self.connection.sync(*display);
+        let error = take_error(*display);
+        if error != 0 {
+            ungrab(*display, self.root, keycode, mask);
+            return Err(if error == xlib::BadAccess {
+                HotkeyError::AlreadyGrabbed
+            } else {
+                HotkeyError::XError(error)
+            });
+        }
+        self.hotkeys.lock().unwrap().insert(
+            (keycode, mask),
+            Hotkey {
+                key,
+                modifiers: modifiers.to_vec(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Releases a hotkey. Returns whether it was registered.
+    pub fn unregister(&mut self, key: Key, modifiers: &[Key]) -> bool {
+        let Ok((keycode, mask)) = combination(key, modifiers) else {
+            return false;
+        };
+        if self
+            .hotkeys
+            .lock()
+            .unwrap()
+            .remove(&(keycode, mask))
+            .is_none()
+        {
+            return false;
+        }
+        let display = self.connection.display.lock().unwrap();
+        ungrab(*display, self.root, keycode, mask);
+        self.connection.sync(*display);
+        true
+    }
+
+    /// The registered hotkeys.
+    pub fn hotkeys(&self) -> Vec<Hotkey> {
+        self.hotkeys.lock().unwrap().values().cloned().collect()
+    }
+}
+
+impl Drop for HotkeyGrabber {
+    fn drop(&mut self) {
+        self.handle.take();
+        let display = self.connection.display.lock().unwrap();
+        for (keycode, mask) in self.hotkeys.lock().unwrap().drain().map(|(k, _)| k) {
+            ungrab(*display, self.root, keycode, mask);
+        }
+        self.connection.sync(*display);
+    }
+}
+
+fn ungrab(display: *mut xlib::Display, root: xlib::Window, keycode: u32, mask: c_uint) {
+    for ignored in IGNORED_MODIFIERS {
+        unsafe { xlib::XUngrabKey(display, keycode as c_int, mask | ignored, root) };
+    }
+}
+
+/// Delivers hotkey presses until `stop` is signaled.
+fn run<T>(
+    connection: &Connection,
+    hotkeys: &Hotkeys,
+    stop: &StopFd,
+    mut callback: T,
+) -> std::io::Result<()>
+where
+    T: FnMut(&Hotkey, Event),
+{
+    let mut fds = [
+        libc::pollfd {
+            fd: unsafe { xlib::XConnectionNumber(*connection.display.lock().unwrap()) },
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: stop.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: connection.wake.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    loop {
+        let mut pressed = Vec::new();
+        {
+            let display = connection.display.lock().unwrap();
+            connection.wake.clear();
+            while unsafe { xlib::XPending(*display) } > 0 {
+                let mut x_event: xlib::XEvent = unsafe { std::mem::zeroed() };
+                unsafe { xlib::XNextEvent(*display, &mut x_event) };
+                if x_event.get_type() == xlib::KeyPress {
+                    let key = unsafe { x_event.key };
+                    let keys = keymap(*display);
+                    let modifiers = held_modifiers(key.state, |modifier| {
+                        code_from_key(modifier)
+                            .is_some_and(|code| keys[code as usize / 8] & 1 << (code % 8) != 0)
+                    });
+                    pressed.push((key.keycode, key.state, modifiers));
+                }
+            }
+        }
+        // The lock is released so that the callback may register hotkeys.
+        for (keycode, x_state, modifiers) in pressed {
+            let hotkey = hotkeys
+                .lock()
+                .unwrap()
+                .get(&(keycode, x_state & MODIFIER_MASK))
+                .cloned();
+            if let Some(hotkey) = hotkey {
+                let event = Event {
+                    event_type: EventType::KeyPress(hotkey.key),
+                    time: SystemTime::now(),
+                    unicode: None,
+                    platform_code: keycode as _,
+                    position_code: keycode as _,
+                    usb_hid: 0,
+                    device: None,
+                    modifiers,
+                    origin: Origin::Unknown,
+                };
+                callback(&hotkey, event);
+            }
+        }
+
+        // Callbacks that register hotkeys may have queued events, which the socket won't tell.
+        let queued =
+            unsafe { xlib::XEventsQueued(*connection.display.lock().unwrap(), QUEUED_ALREADY) };
+        if queued > 0 {
+            continue;
+        }
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if fds[1].revents != 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modifier_mask() {
+        assert_eq!(
+            modifier_mask(Key::ControlRight).unwrap(),
+            modifier_mask(Key::ControlLeft).unwrap()
+        );
+        assert!(matches!(
+            modifier_mask(Key::CapsLock),
+            Err(HotkeyError::NotAModifier(Key::CapsLock))
+        ));
+        for ignored in IGNORED_MODIFIERS {
+            assert_eq!(ignored & MODIFIER_MASK, 0);
+        }
+    }
+
+    #[test]
+    fn test_held_modifiers() {
+        let x_state = xlib::ControlMask | xlib::ShiftMask | xlib::LockMask;
+        assert_eq!(
+            held_modifiers(x_state, |key| key == Key::ControlRight),
+            ModifierState::CONTROL_RIGHT | ModifierState::SHIFT_LEFT | ModifierState::CAPS_LOCK
+        );
+        assert_eq!(
+            held_modifiers(xlib::Mod1Mask | xlib::Mod4Mask, |key| key != Key::Alt),
+            ModifierState::ALT | ModifierState::META_LEFT | ModifierState::META_RIGHT
+        );
+        assert_eq!(held_modifiers(0, |_| true), ModifierState::empty());
+    }
+}
@@ -204,7 +204,7 @@ impl Keyboard {
         );
 
         let keysym = xlookup_string(&mut key);
-        self.keysym = Box::new(keysym);
+        *self.keysym = keysym;
         if self.is_dead() {
             return Some(UnicodeInfo {
                 name: None,
@@ -1,19 +1,19 @@
 extern crate libc;
 extern crate x11;
-use crate::linux::common::{convert, FALSE, KEYBOARD};
+use crate::handle::ListenHandle;
+use crate::linux::common::{convert, StopFd, FALSE};
 use crate::linux::keyboard::Keyboard;
-use crate::rdevin::Event;
+use crate::rdevin::{Event, ModifierState};
 use std::convert::TryInto;
 use std::ffi::CStr;
-use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong};
-use std::ptr::null;
+use std::os::raw::{c_char, c_int, c_uchar, c_uint};
+use std::os::unix::io::AsRawFd;
+use std::ptr::{null, null_mut};
+use std::sync::Arc;
 use thiserror::Error;
 use x11::xlib;
 use x11::xrecord;
 
-static mut RECORD_ALL_CLIENTS: c_ulong = xrecord::XRecordAllClients;
-static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event)>> = None;
-
 #[derive(Debug, Error)]
 /// Errors that occur when trying to capture OS events.
 pub enum ListenError {
@@ -25,61 +25,165 @@ pub enum ListenError {
     CreateRecordContext,
     #[error("Failed to initialize X11 extension")]
     InitExtension,
+    #[error("The X server doesn't support XInput 2.1")]
+    NoXInput2,
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
 }
 
-pub fn listen<T>(callback: T) -> Result<(), ListenError>
-where
-    T: FnMut(Event) + 'static,
-{
-    let keyboard = Keyboard::new().ok_or(ListenError::NoDisplays)?;
-
-    unsafe {
-        KEYBOARD = Some(keyboard);
-        GLOBAL_CALLBACK = Some(Box::new(callback));
-        // Open displays
-        let dpy_control = xlib::XOpenDisplay(null());
-        if dpy_control.is_null() {
-            return Err(ListenError::NoDisplays);
-        }
-        let extension_name = CStr::from_bytes_with_nul(b"RECORD\0").unwrap();
-        let extension = xlib::XInitExtension(dpy_control, extension_name.as_ptr());
-        if extension.is_null() {
-            return Err(ListenError::InitExtension);
+/// The state of one listener, reached from the record callback through its closure pointer.
+struct State {
+    keyboard: Option<Keyboard>,
+    modifiers: ModifierState,
+    callback: Box<dyn FnMut(Event) + Send>,
+}
+
+/// An enabled recording context and the two connections it needs: replies arrive on `data`,
+/// while `control` is used to manage the context.
+struct Recorder {
+    control: *mut xlib::Display,
+    data: *mut xlib::Display,
+    context: xrecord::XRecordContext,
+    state: *mut State,
+}
+
+impl Recorder {
+    fn new(state: State) -> Result<Recorder, ListenError> {
+        let state = Box::into_raw(Box::new(state));
+        let mut recorder = Recorder {
+            control: null_mut(),
+            data: null_mut(),
+            context: 0,
+            state,
+        };
+        unsafe {
+            recorder.control = xlib::XOpenDisplay(null());
+            recorder.data = xlib::XOpenDisplay(null());
+            if recorder.control.is_null() || recorder.data.is_null() {
+                return Err(ListenError::NoDisplays);
+            }
+            let extension_name = CStr::from_bytes_with_nul(b"RECORD\0").unwrap();
+            let extension = xlib::XInitExtension(recorder.control, extension_name.as_ptr());
+            if extension.is_null() {
+                return Err(ListenError::InitExtension);
+            }
+
+            // Prepare record range
+            let range = xrecord::XRecordAllocRange();
+            if range.is_null() {
+                return Err(ListenError::CreateRecordContext);
+            }
+            (*range).device_events.first = xlib::KeyPress as c_uchar;
+            (*range).device_events.last = if crate::keyboard_only() {
+                xlib::KeyRelease
+            } else {
+                xlib::MotionNotify
+            } as c_uchar;
+
+            // Create context
+            let mut clients = xrecord::XRecordAllClients;
+            let mut ranges = [range];
+            recorder.context = xrecord::XRecordCreateContext(
+                recorder.control,
+                0,
+                &mut clients,
+                1,
+                ranges.as_mut_ptr(),
+                1,
+            );
+            xlib::XFree(range as *mut _);
+            if recorder.context == 0 {
+                return Err(ListenError::CreateRecordContext);
+            }
+
+            xlib::XSync(recorder.control, FALSE);
+            let result = xrecord::XRecordEnableContextAsync(
+                recorder.data,
+                recorder.context,
+                Some(record_callback),
+                recorder.state as *mut c_char,
+            );
+            if result == 0 {
+                return Err(ListenError::EnableRecordContext);
+            }
         }
+        Ok(recorder)
+    }
 
-        // Prepare record range
-        let mut record_range: xrecord::XRecordRange = *xrecord::XRecordAllocRange();
-        record_range.device_events.first = xlib::KeyPress as c_uchar;
-        record_range.device_events.last = if crate::keyboard_only() {
-            xlib::KeyRelease
-        } else {
-            xlib::MotionNotify
-        } as c_uchar;
-
-        // Create context
-        let context = xrecord::XRecordCreateContext(
-            dpy_control,
-            0,
-            &mut RECORD_ALL_CLIENTS,
-            1,
-            &mut &mut record_range as *mut &mut xrecord::XRecordRange
-                as *mut *mut xrecord::XRecordRange,
-            1,
-        );
-
-        if context == 0 {
-            return Err(ListenError::CreateRecordContext);
+    /// Delivers events until `stop` is signaled.
+    fn run(&self, stop: &StopFd) -> Result<(), ListenError> {
+        let mut fds = [
+            libc::pollfd {
+                fd: unsafe { xlib::XConnectionNumber(self.data) },
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: stop.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        loop {
+            unsafe { xrecord::XRecordProcessReplies(self.data) };
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e.into());
+            }
+            if fds[1].revents != 0 {
+                return Ok(());
+            }
         }
+    }
+}
 
-        xlib::XSync(dpy_control, FALSE);
-        // Run
-        let result =
-            xrecord::XRecordEnableContext(dpy_control, context, Some(record_callback), &mut 0);
-        if result == 0 {
-            return Err(ListenError::EnableRecordContext);
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        unsafe {
+            if self.context != 0 {
+                xrecord::XRecordDisableContext(self.control, self.context);
+                xrecord::XRecordFreeContext(self.control, self.context);
+                xlib::XSync(self.control, FALSE);
+            }
+            if !self.data.is_null() {
+                xlib::XCloseDisplay(self.data);
+            }
+            if !self.control.is_null() {
+                xlib::XCloseDisplay(self.control);
+            }
+            drop(Box::from_raw(self.state));
         }
     }
-    Ok(())
+}
+
+pub fn listen<T>(callback: T) -> Result<ListenHandle, ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenHandle::spawn(move |started| {
+        let setup = || -> Result<(Recorder, Arc<StopFd>), ListenError> {
+            let keyboard = Keyboard::new().ok_or(ListenError::NoDisplays)?;
+            let recorder = Recorder::new(State {
+                keyboard: Some(keyboard),
+                modifiers: ModifierState::empty(),
+                callback: Box::new(callback),
+            })?;
+            Ok((recorder, Arc::new(StopFd::new()?)))
+        };
+        let (recorder, stop) = match setup() {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return Ok(());
+            }
+        };
+        let signal = stop.clone();
+        let _ = started.send(Ok(Box::new(move || signal.signal())));
+        recorder.run(&stop)
+    })
 }
 
 // No idea how to do that properly relevant doc lives here:
@@ -103,14 +207,19 @@ struct XRecordDatum {
 }
 
 unsafe extern "C" fn record_callback(
-    _null: *mut c_char,
+    closure: *mut c_char,
     raw_data: *mut xrecord::XRecordInterceptData,
 ) {
     let Some(data) = raw_data.as_ref() else {
         return;
     };
+    let Some(state) = (closure as *mut State).as_mut() else {
+        xrecord::XRecordFreeData(raw_data);
+        return;
+    };
 
     if data.category != xrecord::XRecordFromServer {
+        xrecord::XRecordFreeData(raw_data);
         return;
     }
 
@@ -123,15 +232,21 @@ unsafe extern "C" fn record_callback(
 
     let code: c_uint = xdatum.code.into();
     let type_: c_int = xdatum.type_.into();
-    // let state = xdatum.state;
+    let x_state: c_uint = xdatum.state.into();
 
     let x = xdatum.root_x as f64;
     let y = xdatum.root_y as f64;
 
-    if let Some(event) = convert(&mut KEYBOARD, code, type_, x, y) {
-        if let Some(callback) = &mut GLOBAL_CALLBACK {
-            callback(event);
-        }
+    if let Some(event) = convert(
+        &mut state.keyboard,
+        &mut state.modifiers,
+        code,
+        type_,
+        x_state,
+        x,
+        y,
+    ) {
+        (state.callback)(event);
     }
     xrecord::XRecordFreeData(raw_data);
 }
@@ -7,15 +7,22 @@ extern crate x11;
 
 mod common;
 mod display;
+pub mod evdev;
 mod grab;
+mod hotkey;
 mod keyboard;
 mod listen;
 mod simulate;
+pub(crate) mod typing;
+mod unicode;
+pub mod xinput;
 
-pub use crate::linux::display::display_size;
+pub use crate::linux::display::{cursor_position, display_size, displays};
 pub use crate::linux::grab::{
     disable_grab, enable_grab, exit_grab_listen, is_grabbed, start_grab_listen, Error as GrabError,
 };
+pub use crate::linux::hotkey::{Hotkey, HotkeyError, HotkeyGrabber};
 pub use crate::linux::keyboard::Keyboard;
 pub use crate::linux::listen::{listen, ListenError};
 pub use crate::linux::simulate::{simulate, simulate_char, simulate_unicode};
+pub use crate::linux::unicode::{restore_keymap, UnicodeInjector};
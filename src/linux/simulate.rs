@@ -1,11 +1,15 @@
-use crate::keycodes::linux::code_from_key;
+use crate::keycodes::linux::{code_from_button, code_from_key};
 use crate::linux::common::{FALSE, TRUE};
-use crate::rdevin::{Button, EventType, RawKey, SimulateError};
+use crate::linux::unicode::inject_shared;
+use crate::rdevin::{EventType, RawKey, SimulateError, WheelRemainder, WheelUnit};
 use std::os::raw::c_int;
 use std::ptr::null;
+use std::sync::Mutex;
 use x11::xlib;
 use x11::xtest;
 
+static WHEEL_REMAINDER: Mutex<WheelRemainder> = Mutex::new(WheelRemainder::new());
+
 unsafe fn send_native(
     event_type: &EventType,
     display: *mut xlib::Display,
@@ -43,18 +47,14 @@ unsafe fn send_native(
                 xtest::XTestFakeKeyEvent(display, code, FALSE, 0)
             }
         },
-        EventType::ButtonPress(button) => match button {
-            Button::Left => xtest::XTestFakeButtonEvent(display, 1, TRUE, 0),
-            Button::Middle => xtest::XTestFakeButtonEvent(display, 2, TRUE, 0),
-            Button::Right => xtest::XTestFakeButtonEvent(display, 3, TRUE, 0),
-            Button::Unknown(code) => xtest::XTestFakeButtonEvent(display, (*code).into(), TRUE, 0),
-        },
-        EventType::ButtonRelease(button) => match button {
-            Button::Left => xtest::XTestFakeButtonEvent(display, 1, FALSE, 0),
-            Button::Middle => xtest::XTestFakeButtonEvent(display, 2, FALSE, 0),
-            Button::Right => xtest::XTestFakeButtonEvent(display, 3, FALSE, 0),
-            Button::Unknown(code) => xtest::XTestFakeButtonEvent(display, (*code).into(), FALSE, 0),
-        },
+        EventType::ButtonPress(button) => {
+            let code = code_from_button(*button).ok_or(SimulateError::NoCode)?;
+            xtest::XTestFakeButtonEvent(display, code.into(), TRUE, 0)
+        }
+        EventType::ButtonRelease(button) => {
+            let code = code_from_button(*button).ok_or(SimulateError::NoCode)?;
+            xtest::XTestFakeButtonEvent(display, code.into(), FALSE, 0)
+        }
         EventType::MouseMove { x, y } => {
             //TODO: replace with clamp if it is stabalized
             let x = if x.is_finite() {
@@ -70,9 +70,33 @@ unsafe fn send_native(
             xtest::XTestFakeMotionEvent(display, 0, x, y, 0)
             //     xlib::XWarpPointer(display, 0, root, 0, 0, 0, 0, *x as i32, *y as i32);
         }
-        EventType::Wheel { delta_x, delta_y } => {
-            let code_x = if *delta_x > 0 { 7 } else { 6 };
-            let code_y = if *delta_y > 0 { 4 } else { 5 };
+        EventType::MouseMoveRelative { dx, dy } => {
+            let dx = if dx.is_finite() {
+                dx.round() as c_int
+            } else {
+                0
+            };
+            let dy = if dy.is_finite() {
+                dy.round() as c_int
+            } else {
+                0
+            };
+            // -1 is the screen the cursor is on.
+            xtest::XTestFakeRelativeMotionEvent(display, -1, dx, dy, 0)
+        }
+        EventType::Wheel {
+            delta_x,
+            delta_y,
+            unit,
+        } => {
+            // XTest can only click the wheel buttons, so fractions wait until they make a notch.
+            let (delta_x, delta_y) =
+                WHEEL_REMAINDER
+                    .lock()
+                    .unwrap()
+                    .take(*delta_x, *delta_y, *unit, WheelUnit::Lines);
+            let code_x = if delta_x > 0 { 7 } else { 6 };
+            let code_y = if delta_y > 0 { 4 } else { 5 };
 
             let mut result: c_int = 1;
             for _ in 0..delta_x.abs() {
@@ -87,6 +111,8 @@ unsafe fn send_native(
             }
             result
         }
+        // XTest only has a core pointer and keyboard.
+        _ => return Err(SimulateError::Unsupported),
     };
     if res == 0 {
         Err(SimulateError::SendInput)
@@ -116,56 +142,21 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
     }
 }
 
-unsafe fn send_native_char(chr: char, pressed: bool, display: *mut xlib::Display) -> Option<()> {
-    // unuse keycode: F24 -> 194
-    let keycode: u32 = 194;
-
-    // char to keysym
-    let ordinal: u32 = chr.into();
-    let mut keysym = if ordinal < 0x100 {
-        ordinal
-    } else {
-        ordinal | 0x01000000
-    } as libc::c_ulong;
-
-    // remap keycode to keysym
-    x11::xlib::XChangeKeyboardMapping(display, keycode as _, 1, &mut keysym, 1);
-
-    let res = if pressed {
-        xtest::XTestFakeKeyEvent(display, keycode as _, TRUE, 0)
-    } else {
-        xtest::XTestFakeKeyEvent(display, keycode as _, FALSE, 0)
-    };
-
-    if res == 0 {
-        None
-    } else {
-        Some(())
-    }
-}
-
+/// Types `chr` on press through a [`UnicodeInjector`](super::UnicodeInjector) shared between
+/// calls, so releasing does nothing. The keys it borrows are given back by
+/// [`restore_keymap`](super::restore_keymap) or at exit.
 pub fn simulate_char(chr: char, pressed: bool) -> Result<(), SimulateError> {
-    unsafe {
-        let dpy = xlib::XOpenDisplay(null());
-        if dpy.is_null() {
-            return Err(SimulateError::NoDisplay);
-        }
-        match send_native_char(chr, pressed, dpy) {
-            Some(_) => {
-                xlib::XFlush(dpy);
-                xlib::XSync(dpy, 0);
-                xlib::XCloseDisplay(dpy);
-                Ok(())
-            }
-            None => {
-                xlib::XCloseDisplay(dpy);
-                Err(SimulateError::SendInput)
-            }
-        }
+    if pressed {
+        inject_shared(chr)
+    } else {
+        Ok(())
     }
 }
 
-// TODO: wtf
-pub fn simulate_unicode(_unicode: u16) -> Result<(), SimulateError> {
-    Err(SimulateError::SendInput)
+/// Types a UTF-16 code unit like [`simulate_char`]. Surrogates can't be typed one at a time; use
+/// [`UnicodeInjector::inject`](super::UnicodeInjector::inject) or [`type_text`](crate::type_text)
+/// for characters outside the Basic Multilingual Plane.
+pub fn simulate_unicode(unicode: u16) -> Result<(), SimulateError> {
+    let chr = char::from_u32(unicode.into()).ok_or(SimulateError::NoCorrespondingKey)?;
+    inject_shared(chr)
 }
@@ -0,0 +1,106 @@
+use crate::keycodes::linux::code_from_key;
+use crate::linux::common::{FALSE, TRUE};
+use crate::linux::unicode::{inject_shared, keysym_from_char};
+use crate::rdevin::{Key, SimulateError};
+use std::convert::TryFrom;
+use std::mem::zeroed;
+use std::os::raw::{c_int, c_uint};
+use std::ptr::null;
+use x11::xlib::{self, KeySym};
+use x11::xtest;
+
+/// `XkbUseCoreKbd`, which the x11 crate lacks.
+const XKB_USE_CORE_KBD: c_uint = 0x0100;
+
+/// The modifiers that select a shift level.
+fn level_modifiers(level: c_int) -> &'static [Key] {
+    match level {
+        0 => &[],
+        1 => &[Key::ShiftLeft],
+        2 => &[Key::AltGr],
+        _ => &[Key::ShiftLeft, Key::AltGr],
+    }
+}
+
+/// Types characters through XTest.
+pub(crate) struct Typist {
+    display: *mut xlib::Display,
+}
+
+impl Typist {
+    pub(crate) fn new() -> Result<Typist, SimulateError> {
+        let display = unsafe { xlib::XOpenDisplay(null()) };
+        if display.is_null() {
+            return Err(SimulateError::NoDisplay);
+        }
+        Ok(Typist { display })
+    }
+
+    /// Types `chr`, returning whether it could.
+    pub(crate) fn type_char(&mut self, chr: char, fallback: bool) -> Result<bool, SimulateError> {
+        let keysym = keysym_from_char(chr);
+        if let Some((keycode, level)) = self.lookup(keysym) {
+            let modifiers = level_modifiers(level)
+                .iter()
+                .map(|&key| code_from_key(key).ok_or(SimulateError::NoCode))
+                .collect::<Result<Vec<_>, _>>()?;
+            for &modifier in &modifiers {
+                self.send(modifier, true)?;
+            }
+            let result = self.tap(keycode.into());
+            for &modifier in modifiers.iter().rev() {
+                self.send(modifier, false)?;
+            }
+            result?;
+        } else if fallback {
+            inject_shared(chr)?;
+        } else {
+            return Ok(false);
+        }
+        unsafe { xlib::XSync(self.display, FALSE) };
+        Ok(true)
+    }
+
+    /// The keycode and shift level that type `keysym` in the active group.
+    fn lookup(&self, keysym: KeySym) -> Option<(xlib::KeyCode, c_int)> {
+        let group = self.group();
+        let (mut min, mut max) = (0, 0);
+        unsafe { xlib::XDisplayKeycodes(self.display, &mut min, &mut max) };
+        let keysym_at = |keycode, level| unsafe {
+            xlib::XkbKeycodeToKeysym(self.display, keycode, group, level)
+        };
+        (min..=max)
+            .filter_map(|keycode| xlib::KeyCode::try_from(keycode).ok())
+            .flat_map(|keycode| (0..4).map(move |level| (keycode, level)))
+            .find(|&(keycode, level)| keysym_at(keycode, level) == keysym)
+    }
+
+    /// The layout group in use, which is the first one if XKB can't tell.
+    fn group(&self) -> c_int {
+        let mut state: xlib::XkbStateRec = unsafe { zeroed() };
+        if unsafe { xlib::XkbGetState(self.display, XKB_USE_CORE_KBD, &mut state) } != 0 {
+            return 0;
+        }
+        state.group.into()
+    }
+
+    fn tap(&self, keycode: u32) -> Result<(), SimulateError> {
+        self.send(keycode, true)?;
+        self.send(keycode, false)
+    }
+
+    fn send(&self, keycode: u32, pressed: bool) -> Result<(), SimulateError> {
+        let pressed = if pressed { TRUE } else { FALSE };
+        if unsafe { xtest::XTestFakeKeyEvent(self.display, keycode, pressed, 0) } == 0 {
+            Err(SimulateError::SendInput)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for Typist {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
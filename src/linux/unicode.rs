@@ -0,0 +1,324 @@
+use crate::linux::common::{FALSE, TRUE};
+use crate::rdevin::SimulateError;
+use std::convert::TryFrom;
+use std::os::raw::c_int;
+use std::os::unix::io::RawFd;
+use std::ptr::null;
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+use x11::xlib::{self, KeySym};
+use x11::xtest;
+
+/// How many keycodes are borrowed at most. A keycode isn't remapped again until every other one
+/// has been used, so that clients still translating its last keystroke see the right keysym.
+const POOL_SIZE: usize = 8;
+
+/// Borrowed when the keymap has no unused keycode. Nothing is bound to F24 on most layouts.
+const FALLBACK_KEYCODE: xlib::KeyCode = 194;
+
+/// How long to wait for the server to announce a remap.
+const MAPPING_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The injector that [`simulate_char`](super::simulate_char),
+/// [`simulate_unicode`](super::simulate_unicode) and [`type_text`](crate::type_text) share, so
+/// that its keys keep being used in turn from one call to the next.
+static SHARED: Mutex<Option<UnicodeInjector>> = Mutex::new(None);
+static RESTORE_AT_EXIT: Once = Once::new();
+
+/// Types `chr` with the shared injector, which is created on first use.
+pub(crate) fn inject_shared(chr: char) -> Result<(), SimulateError> {
+    let mut shared = SHARED.lock().unwrap_or_else(|e| e.into_inner());
+    let injector = match shared.as_mut() {
+        Some(injector) => injector,
+        None => {
+            // Statics are never dropped, so the keys are given back at exit.
+            RESTORE_AT_EXIT.call_once(|| unsafe {
+                libc::atexit(restore_at_exit);
+            });
+            shared.insert(UnicodeInjector::new()?)
+        }
+    };
+    injector.inject(chr)
+}
+
+/// Gives back the keys borrowed to type characters the layout lacks with
+/// [`simulate_char`](super::simulate_char), [`simulate_unicode`](super::simulate_unicode) and
+/// [`type_text`](crate::type_text). Otherwise, that happens when the process exits.
+pub fn restore_keymap() {
+    SHARED.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+extern "C" fn restore_at_exit() {
+    // Another thread may be typing as the process exits.
+    if let Ok(mut shared) = SHARED.try_lock() {
+        shared.take();
+    }
+}
+
+/// The keysym X11 uses for a character.
+pub(crate) fn keysym_from_char(chr: char) -> KeySym {
+    match chr {
+        '\u{8}' => 0xff08,     // XK_BackSpace
+        '\t' => 0xff09,        // XK_Tab
+        '\n' | '\r' => 0xff0d, // XK_Return
+        '\u{1b}' => 0xff1b,    // XK_Escape
+        '\u{7f}' => 0xffff,    // XK_Delete
+        // Latin-1 keysyms are the code points themselves.
+        chr if u32::from(chr) < 0x100 => u32::from(chr).into(),
+        // Including the astral planes, which 24 bits are plenty for.
+        chr => (0x0100_0000 | u32::from(chr)).into(),
+    }
+}
+
+/// The keycodes with no keysyms in a keymap that starts at `min_keycode`, highest first.
+fn spare_keycodes(
+    min_keycode: xlib::KeyCode,
+    keysyms: &[KeySym],
+    per_keycode: usize,
+) -> Vec<xlib::KeyCode> {
+    if per_keycode == 0 {
+        return Vec::new();
+    }
+    keysyms
+        .chunks(per_keycode)
+        .enumerate()
+        .filter(|(_, keysyms)| keysyms.iter().all(|&keysym| keysym == 0))
+        .filter_map(|(offset, _)| u8::try_from(offset).ok()?.checked_add(min_keycode))
+        .rev()
+        .take(POOL_SIZE)
+        .collect()
+}
+
+/// A keycode lent to the injector.
+struct Borrowed {
+    keycode: xlib::KeyCode,
+    /// The keysyms to give back, or `None` while it's untouched.
+    original: Option<Vec<KeySym>>,
+}
+
+/// Types any character, whether the keyboard layout has it or not, by binding it to an unused key
+/// for a moment.
+///
+/// The keys come from a small pool of keycodes that have nothing bound to them, used in turn so
+/// that typing quickly doesn't remap a key before applications have read it. Each remap is waited
+/// for before the key is pressed, and every borrowed key is unbound again when the injector is
+/// dropped.
+///
+/// ```no_run
+/// use rdevin::linux::UnicodeInjector;
+///
+/// let mut injector = UnicodeInjector::new()?;
+/// for chr in "→ 🦀".chars() {
+///     injector.inject(chr)?;
+/// }
+/// # Ok::<(), rdevin::SimulateError>(())
+/// ```
+pub struct UnicodeInjector {
+    display: *mut xlib::Display,
+    pool: Vec<Borrowed>,
+    next: usize,
+}
+
+impl UnicodeInjector {
+    pub fn new() -> Result<UnicodeInjector, SimulateError> {
+        let display = unsafe { xlib::XOpenDisplay(null()) };
+        if display.is_null() {
+            return Err(SimulateError::NoDisplay);
+        }
+        let mut keycodes = unsafe { find_spare_keycodes(display) };
+        if keycodes.is_empty() {
+            keycodes.push(FALLBACK_KEYCODE);
+        }
+        Ok(UnicodeInjector {
+            display,
+            pool: keycodes
+                .into_iter()
+                .map(|keycode| Borrowed {
+                    keycode,
+                    original: None,
+                })
+                .collect(),
+            next: 0,
+        })
+    }
+
+    /// Presses and releases a key bound to `chr`.
+    pub fn inject(&mut self, chr: char) -> Result<(), SimulateError> {
+        let display = self.display;
+        let index = self.next;
+        self.next = (index + 1) % self.pool.len();
+        let borrowed = &mut self.pool[index];
+        let keycode = borrowed.keycode;
+        unsafe {
+            if borrowed.original.is_none() {
+                borrowed.original = Some(keysyms_of(display, keycode)?);
+            }
+            let mut keysym = keysym_from_char(chr);
+            xlib::XChangeKeyboardMapping(display, keycode.into(), 1, &mut keysym, 1);
+            wait_for_mapping(display, keycode)?;
+            let pressed = xtest::XTestFakeKeyEvent(display, keycode.into(), TRUE, 0);
+            let released = xtest::XTestFakeKeyEvent(display, keycode.into(), FALSE, 0);
+            xlib::XSync(display, FALSE);
+            if pressed == 0 || released == 0 {
+                return Err(SimulateError::SendInput);
+            }
+        }
+        Ok(())
+    }
+}
+
+// The connection is only used by whoever holds the injector.
+unsafe impl Send for UnicodeInjector {}
+
+impl Drop for UnicodeInjector {
+    fn drop(&mut self) {
+        unsafe {
+            for borrowed in &mut self.pool {
+                if let Some(mut original) = borrowed.original.take() {
+                    xlib::XChangeKeyboardMapping(
+                        self.display,
+                        borrowed.keycode.into(),
+                        original.len() as c_int,
+                        original.as_mut_ptr(),
+                        1,
+                    );
+                }
+            }
+            xlib::XSync(self.display, FALSE);
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+unsafe fn find_spare_keycodes(display: *mut xlib::Display) -> Vec<xlib::KeyCode> {
+    let (mut min, mut max) = (0, 0);
+    xlib::XDisplayKeycodes(display, &mut min, &mut max);
+    let (Ok(min_keycode), Ok(_)) = (u8::try_from(min), u8::try_from(max)) else {
+        return Vec::new();
+    };
+    let count = max - min + 1;
+    let mut per_keycode = 0;
+    let keysyms = xlib::XGetKeyboardMapping(display, min_keycode, count, &mut per_keycode);
+    if keysyms.is_null() {
+        return Vec::new();
+    }
+    let per_keycode = per_keycode.max(0) as usize;
+    let spare = spare_keycodes(
+        min_keycode,
+        std::slice::from_raw_parts(keysyms, count as usize * per_keycode),
+        per_keycode,
+    );
+    xlib::XFree(keysyms.cast());
+    spare
+}
+
+/// The keysyms bound to `keycode`, at least one so they can be put back.
+unsafe fn keysyms_of(
+    display: *mut xlib::Display,
+    keycode: xlib::KeyCode,
+) -> Result<Vec<KeySym>, SimulateError> {
+    let mut per_keycode = 0;
+    let keysyms = xlib::XGetKeyboardMapping(display, keycode, 1, &mut per_keycode);
+    if keysyms.is_null() {
+        return Err(SimulateError::SendInput);
+    }
+    let mut original = std::slice::from_raw_parts(keysyms, per_keycode.max(0) as usize).to_vec();
+    xlib::XFree(keysyms.cast());
+    if original.is_empty() {
+        original.push(0); // NoSymbol
+    }
+    Ok(original)
+}
+
+/// Waits for the `MappingNotify` the server sends every client once `keycode` is remapped, so
+/// that the key isn't pressed before it means the new keysym.
+unsafe fn wait_for_mapping(
+    display: *mut xlib::Display,
+    keycode: xlib::KeyCode,
+) -> Result<(), SimulateError> {
+    let deadline = Instant::now() + MAPPING_TIMEOUT;
+    // Flushes the request and reads whatever the server has answered since.
+    xlib::XSync(display, FALSE);
+    loop {
+        let mut x_event: xlib::XEvent = std::mem::zeroed();
+        while xlib::XCheckTypedEvent(display, xlib::MappingNotify, &mut x_event) != 0 {
+            let mut mapping = x_event.mapping;
+            xlib::XRefreshKeyboardMapping(&mut mapping);
+            let first = mapping.first_keycode;
+            if mapping.request == xlib::MappingKeyboard
+                && (first..first + mapping.count).contains(&keycode.into())
+            {
+                return Ok(());
+            }
+        }
+        let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+            return Err(SimulateError::SendInput);
+        };
+        wait_readable(xlib::XConnectionNumber(display), timeout);
+        // Reads the events that arrived.
+        xlib::XPending(display);
+    }
+}
+
+fn wait_readable(fd: RawFd, timeout: Duration) {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().clamp(1, c_int::MAX as u128) as c_int;
+    unsafe { libc::poll(&mut pollfd, 1, timeout) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keysym_from_char() {
+        assert_eq!(keysym_from_char('a'), 0x61);
+        assert_eq!(keysym_from_char('é'), 0xe9);
+        assert_eq!(keysym_from_char('\n'), 0xff0d);
+        assert_eq!(keysym_from_char('€'), 0x0100_20ac);
+        assert_eq!(keysym_from_char('🦀'), 0x0101_f980);
+    }
+
+    #[test]
+    fn test_spare_keycodes() {
+        #[rustfmt::skip]
+        let keysyms = [
+            0x61, 0x41, // 8: a A
+            0, 0,       // 9
+            0xff0d, 0,  // 10: Return
+            0, 0,       // 11
+        ];
+        assert_eq!(spare_keycodes(8, &keysyms, 2), [11, 9]);
+        assert!(spare_keycodes(8, &keysyms, 0).is_empty());
+
+        let keysyms = vec![0; 2 * 20];
+        assert_eq!(spare_keycodes(8, &keysyms, 2).len(), POOL_SIZE);
+        assert_eq!(spare_keycodes(8, &keysyms, 2)[0], 27);
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn test_shared_keycodes_rotate() {
+        let mut used = Vec::new();
+        for chr in "→←↑↓".chars() {
+            inject_shared(chr).unwrap();
+            let shared = SHARED.lock().unwrap();
+            let injector = shared.as_ref().unwrap();
+            let last = (injector.next + injector.pool.len() - 1) % injector.pool.len();
+            used.push((injector.pool[last].keycode, injector.pool.len()));
+        }
+        restore_keymap();
+        let pool_size = used[0].1;
+        for (i, (keycode, _)) in used.iter().enumerate() {
+            // Each character got a different key than the ones before it, until the pool wraps.
+            for (earlier, _) in &used[i.saturating_sub(pool_size - 1)..i] {
+                assert_ne!(keycode, earlier);
+            }
+        }
+        assert!(SHARED.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,505 @@
+//! Listening through XInput 2 raw events.
+//!
+//! Unlike the default X11 listener, which records core events, this one tells which device each
+//! event came from, reports the cursor position to the sub-pixel, follows each motion of a mouse
+//! with a [`MouseMoveRelative`](crate::EventType::MouseMoveRelative) of its deltas and reads
+//! touchpad scrolling from its smooth-scroll valuators, in fractions of a notch. It needs an X
+//! server with XInput 2.1, which every current one has, Xvfb included.
+//!
+//! ```no_run
+//! use rdevin::linux::xinput;
+//!
+//! let handle = xinput::listen(|event| {
+//!     println!("{:?} from device {:?}", event.event_type, event.device);
+//! })?;
+//! handle.join()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+extern crate libc;
+extern crate x11;
+use crate::handle::ListenHandle;
+use crate::linux::common::{convert_event, update_modifiers, StopFd, FALSE};
+use crate::linux::keyboard::Keyboard;
+use crate::linux::listen::ListenError;
+use crate::rdevin::{Event, EventType, KeyboardState, ModifierState, Origin, WheelUnit};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_uchar};
+use std::os::unix::io::AsRawFd;
+use std::ptr::{null, null_mut};
+use std::sync::Arc;
+use std::time::SystemTime;
+use x11::xinput2 as xi;
+use x11::xlib;
+
+/// The XInput version smooth scrolling came with.
+const VERSION: (c_int, c_int) = (2, 1);
+
+/// A smooth-scroll valuator of a device.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ScrollAxis {
+    number: c_int,
+    horizontal: bool,
+    /// The valuator change that makes one notch.
+    increment: f64,
+}
+
+/// What a listener keeps about a slave device.
+#[derive(Debug)]
+struct Device {
+    origin: Origin,
+    /// Whether the first two valuators are motion deltas rather than positions.
+    relative: bool,
+    scroll: Vec<ScrollAxis>,
+}
+
+impl ScrollAxis {
+    /// Converts a valuator change to notches, positive up or right.
+    fn notches(self, value: f64) -> f64 {
+        let notches = value / self.increment;
+        if self.horizontal {
+            notches
+        } else {
+            -notches
+        }
+    }
+}
+
+/// Yields the valuators a raw event has, with their values, which are packed in mask order.
+fn valuators<'a>(
+    mask: &'a [c_uchar],
+    values: &'a [f64],
+) -> impl Iterator<Item = (c_int, f64)> + 'a {
+    (0..mask.len() as c_int * 8)
+        .filter(move |&number| xi::XIMaskIsSet(mask, number))
+        .zip(values.iter().copied())
+}
+
+/// Reads the slave devices and their scroll valuators.
+unsafe fn query_devices(display: *mut xlib::Display) -> HashMap<c_int, Device> {
+    let mut devices = HashMap::new();
+    let mut count = 0;
+    let info = xi::XIQueryDevice(display, xi::XIAllDevices, &mut count);
+    if info.is_null() {
+        return devices;
+    }
+    for info in std::slice::from_raw_parts(info, count as usize) {
+        let name = CStr::from_ptr(info.name).to_string_lossy();
+        // XTest input comes from the server's virtual XTEST devices. Those of uinput are no
+        // different from real ones to X.
+        let origin = if name.contains("XTEST") {
+            Origin::Synthetic { tag: 0 }
+        } else {
+            Origin::Hardware
+        };
+        let mut relative = false;
+        let mut scroll = Vec::new();
+        for &class in std::slice::from_raw_parts(info.classes, info.num_classes as usize) {
+            if (*class)._type == xi::XIValuatorClass {
+                let class = &*(class as *const xi::XIValuatorClassInfo);
+                if class.number == 0 {
+                    relative = class.mode == xi::XIModeRelative;
+                }
+            } else if (*class)._type == xi::XIScrollClass {
+                let class = &*(class as *const xi::XIScrollClassInfo);
+                if class.increment != 0.0 {
+                    scroll.push(ScrollAxis {
+                        number: class.number,
+                        horizontal: class.scroll_type == xi::XIScrollTypeHorizontal,
+                        increment: class.increment,
+                    });
+                }
+            }
+        }
+        devices.insert(
+            info.deviceid,
+            Device {
+                origin,
+                relative,
+                scroll,
+            },
+        );
+    }
+    xi::XIFreeDeviceInfo(info);
+    devices
+}
+
+/// Checks that the server has XInput 2.1, returning the extension's major opcode.
+unsafe fn opcode(display: *mut xlib::Display) -> Result<c_int, ListenError> {
+    let name = CStr::from_bytes_with_nul(b"XInputExtension\0").unwrap();
+    let (mut opcode, mut event, mut error) = (0, 0, 0);
+    if xlib::XQueryExtension(display, name.as_ptr(), &mut opcode, &mut event, &mut error) == FALSE {
+        return Err(ListenError::NoXInput2);
+    }
+    let (mut major, mut minor) = VERSION;
+    if xi::XIQueryVersion(display, &mut major, &mut minor) != xlib::Success as c_int
+        || (major, minor) < VERSION
+    {
+        return Err(ListenError::NoXInput2);
+    }
+    Ok(opcode)
+}
+
+/// Checks that there is an X server with XInput 2.1.
+pub(crate) fn check() -> Result<(), ListenError> {
+    unsafe {
+        let display = xlib::XOpenDisplay(null());
+        if display.is_null() {
+            return Err(ListenError::NoDisplays);
+        }
+        let result = opcode(display).map(|_| ());
+        xlib::XCloseDisplay(display);
+        result
+    }
+}
+
+/// The state of one listener.
+struct State {
+    keyboard: Option<Keyboard>,
+    modifiers: ModifierState,
+    devices: HashMap<c_int, Device>,
+    callback: Box<dyn FnMut(Event) + Send>,
+}
+
+/// A connection with raw events selected on the root window.
+struct Connection {
+    display: *mut xlib::Display,
+    opcode: c_int,
+    state: State,
+}
+
+impl Connection {
+    fn new(state: State) -> Result<Connection, ListenError> {
+        let display = unsafe { xlib::XOpenDisplay(null()) };
+        if display.is_null() {
+            return Err(ListenError::NoDisplays);
+        }
+        let mut connection = Connection {
+            display,
+            opcode: 0,
+            state,
+        };
+        unsafe {
+            connection.opcode = opcode(display)?;
+            let root = xlib::XDefaultRootWindow(display);
+            // Raw events of master devices come once per input, with the slave it came from as
+            // their source. Device changes are only reported for all devices.
+            let mut raw = [0; (xi::XI_LASTEVENT as usize >> 3) + 1];
+            xi::XISetMask(&mut raw, xi::XI_RawKeyPress);
+            xi::XISetMask(&mut raw, xi::XI_RawKeyRelease);
+            if !crate::keyboard_only() {
+                xi::XISetMask(&mut raw, xi::XI_RawButtonPress);
+                xi::XISetMask(&mut raw, xi::XI_RawButtonRelease);
+                xi::XISetMask(&mut raw, xi::XI_RawMotion);
+            }
+            let mut changes = [0; (xi::XI_LASTEVENT as usize >> 3) + 1];
+            xi::XISetMask(&mut changes, xi::XI_HierarchyChanged);
+            xi::XISetMask(&mut changes, xi::XI_DeviceChanged);
+            let mut masks = [
+                xi::XIEventMask {
+                    deviceid: xi::XIAllMasterDevices,
+                    mask_len: raw.len() as c_int,
+                    mask: raw.as_mut_ptr(),
+                },
+                xi::XIEventMask {
+                    deviceid: xi::XIAllDevices,
+                    mask_len: changes.len() as c_int,
+                    mask: changes.as_mut_ptr(),
+                },
+            ];
+            xi::XISelectEvents(display, root, masks.as_mut_ptr(), masks.len() as c_int);
+
+            // Raw events don't carry the modifier state, so the lock keys start from the
+            // pointer's and the rest are followed from then on.
+            let (mut root_return, mut child) = (0, 0);
+            let (mut root_x, mut root_y, mut win_x, mut win_y) = (0, 0, 0, 0);
+            let mut mask = 0;
+            xlib::XQueryPointer(
+                display,
+                root,
+                &mut root_return,
+                &mut child,
+                &mut root_x,
+                &mut root_y,
+                &mut win_x,
+                &mut win_y,
+                &mut mask,
+            );
+            update_modifiers(
+                &mut connection.state.modifiers,
+                mask,
+                &EventType::MouseMove { x: 0.0, y: 0.0 },
+            );
+            connection.state.devices = query_devices(display);
+            xlib::XSync(display, FALSE);
+        }
+        Ok(connection)
+    }
+
+    /// Delivers events until `stop` is signaled.
+    fn run(&mut self, stop: &StopFd) -> Result<(), ListenError> {
+        let mut fds = [
+            libc::pollfd {
+                fd: unsafe { xlib::XConnectionNumber(self.display) },
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: stop.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        loop {
+            while unsafe { xlib::XPending(self.display) } > 0 {
+                let mut event = xlib::XEvent { type_: 0 };
+                unsafe {
+                    xlib::XNextEvent(self.display, &mut event);
+                    self.dispatch(&mut event.generic_event_cookie);
+                }
+            }
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e.into());
+            }
+            if fds[1].revents != 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    unsafe fn dispatch(&mut self, cookie: &mut xlib::XGenericEventCookie) {
+        if cookie.type_ != xlib::GenericEvent
+            || cookie.extension != self.opcode
+            || xlib::XGetEventData(self.display, cookie) == FALSE
+        {
+            return;
+        }
+        match cookie.evtype {
+            xi::XI_HierarchyChanged | xi::XI_DeviceChanged => {
+                self.state.devices = query_devices(self.display);
+            }
+            _ => {
+                let raw = &*(cookie.data as *const xi::XIRawEvent);
+                for event_type in self.convert(raw) {
+                    let event = self.event(raw, event_type);
+                    (self.state.callback)(event);
+                }
+            }
+        }
+        xlib::XFreeEventData(self.display, cookie);
+    }
+
+    unsafe fn convert(&mut self, raw: &xi::XIRawEvent) -> Vec<EventType> {
+        let code = raw.detail as c_uchar;
+        match raw.evtype {
+            xi::XI_RawKeyPress => convert_event(code, xlib::KeyPress, 0.0, 0.0)
+                .into_iter()
+                .collect(),
+            xi::XI_RawKeyRelease => convert_event(code, xlib::KeyRelease, 0.0, 0.0)
+                .into_iter()
+                .collect(),
+            // Buttons 4 to 7 are emulated from the smooth-scroll valuators, which are read
+            // instead.
+            xi::XI_RawButtonPress | xi::XI_RawButtonRelease
+                if raw.flags & xi::XIPointerEmulated != 0 =>
+            {
+                vec![]
+            }
+            xi::XI_RawButtonPress => convert_event(code, xlib::ButtonPress, 0.0, 0.0)
+                .into_iter()
+                .collect(),
+            xi::XI_RawButtonRelease => convert_event(code, xlib::ButtonRelease, 0.0, 0.0)
+                .into_iter()
+                .collect(),
+            xi::XI_RawMotion => self.motion(raw),
+            _ => vec![],
+        }
+    }
+
+    unsafe fn motion(&mut self, raw: &xi::XIRawEvent) -> Vec<EventType> {
+        let mask = std::slice::from_raw_parts(raw.valuators.mask, raw.valuators.mask_len as usize);
+        let count = (0..raw.valuators.mask_len * 8)
+            .filter(|&number| xi::XIMaskIsSet(mask, number))
+            .count();
+        let values = std::slice::from_raw_parts(raw.valuators.values, count);
+
+        // Scrolling emulated from buttons 4 to 7 is already reported as those buttons.
+        let emulated = raw.flags & xi::XIPointerEmulated != 0;
+        let mut moved = false;
+        let (mut dx, mut dy) = (0.0, 0.0);
+        let (mut delta_x, mut delta_y) = (0.0, 0.0);
+        let mut device = self.state.devices.get_mut(&raw.sourceid);
+        for (number, value) in valuators(mask, values) {
+            let Some(device) = device.as_deref_mut() else {
+                moved = true;
+                continue;
+            };
+            match device
+                .scroll
+                .iter()
+                .find(|axis| axis.number == number)
+                .copied()
+            {
+                Some(_) if emulated => {}
+                Some(axis) if axis.horizontal => delta_x += axis.notches(value),
+                Some(axis) => delta_y += axis.notches(value),
+                None => {
+                    moved = true;
+                    match number {
+                        0 if device.relative => dx = value,
+                        1 if device.relative => dy = value,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut event_types = vec![];
+        if moved {
+            if let Some((x, y)) = self.pointer(raw.deviceid) {
+                event_types.push(EventType::MouseMove { x, y });
+            }
+        }
+        if dx != 0.0 || dy != 0.0 {
+            event_types.push(EventType::MouseMoveRelative { dx, dy });
+        }
+        if delta_x != 0.0 || delta_y != 0.0 {
+            event_types.push(EventType::Wheel {
+                delta_x,
+                delta_y,
+                unit: WheelUnit::Lines,
+            });
+        }
+        event_types
+    }
+
+    /// The position of a master pointer on the root window.
+    unsafe fn pointer(&self, deviceid: c_int) -> Option<(f64, f64)> {
+        let (mut root, mut child) = (0, 0);
+        let (mut root_x, mut root_y, mut win_x, mut win_y) = (0.0, 0.0, 0.0, 0.0);
+        let mut buttons = xi::XIButtonState {
+            mask_len: 0,
+            mask: null_mut(),
+        };
+        let mut mods = std::mem::zeroed();
+        let mut group = std::mem::zeroed();
+        let found = xi::XIQueryPointer(
+            self.display,
+            deviceid,
+            xlib::XDefaultRootWindow(self.display),
+            &mut root,
+            &mut child,
+            &mut root_x,
+            &mut root_y,
+            &mut win_x,
+            &mut win_y,
+            &mut buttons,
+            &mut mods,
+            &mut group,
+        );
+        if !buttons.mask.is_null() {
+            xlib::XFree(buttons.mask as *mut _);
+        }
+        (found != FALSE).then_some((root_x, root_y))
+    }
+
+    fn event(&mut self, raw: &xi::XIRawEvent, event_type: EventType) -> Event {
+        let state = &mut self.state;
+        state.modifiers.update(&event_type);
+        let unicode = state
+            .keyboard
+            .as_mut()
+            .and_then(|keyboard| keyboard.add(&event_type));
+        Event {
+            time: SystemTime::now(),
+            unicode,
+            event_type,
+            platform_code: raw.detail as _,
+            position_code: raw.detail as _,
+            usb_hid: 0,
+            device: Some(raw.sourceid as u32),
+            modifiers: state.modifiers,
+            origin: state
+                .devices
+                .get(&raw.sourceid)
+                .map_or(Origin::Unknown, |device| device.origin),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
+
+/// Listens to the raw events of every device through XInput 2.
+///
+/// Events have [`device`](Event::device) set to the X device ID of the slave they came from, as
+/// listed by `xinput list`, and those simulated through XTest have a
+/// [`Synthetic`](Origin::Synthetic) origin.
+pub fn listen<T>(callback: T) -> Result<ListenHandle, ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenHandle::spawn(move |started| {
+        let setup = || -> Result<(Connection, Arc<StopFd>), ListenError> {
+            let keyboard = Keyboard::new().ok_or(ListenError::NoDisplays)?;
+            let connection = Connection::new(State {
+                keyboard: Some(keyboard),
+                modifiers: ModifierState::empty(),
+                devices: HashMap::new(),
+                callback: Box::new(callback),
+            })?;
+            Ok((connection, Arc::new(StopFd::new()?)))
+        };
+        let (mut connection, stop) = match setup() {
+            Ok(setup) => setup,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return Ok(());
+            }
+        };
+        let signal = stop.clone();
+        let _ = started.send(Ok(Box::new(move || signal.signal())));
+        connection.run(&stop)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valuators() {
+        // Valuators 0, 3 and 9.
+        let mask = [0b0000_1001, 0b0000_0010];
+        let values = [1.5, -2.0, 120.0];
+        assert_eq!(
+            valuators(&mask, &values).collect::<Vec<_>>(),
+            [(0, 1.5), (3, -2.0), (9, 120.0)]
+        );
+    }
+
+    #[test]
+    fn test_scroll() {
+        let vertical = ScrollAxis {
+            number: 3,
+            horizontal: false,
+            increment: 4.0,
+        };
+        let horizontal = ScrollAxis {
+            number: 2,
+            horizontal: true,
+            increment: -4.0,
+        };
+        // Positive valuators scroll down, unless the increment is a natural scrolling one.
+        assert_eq!(vertical.notches(3.0), -0.75);
+        assert_eq!(vertical.notches(-8.0), 2.0);
+        assert_eq!(horizontal.notches(2.0), -0.5);
+    }
+}
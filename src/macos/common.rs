@@ -1,7 +1,7 @@
 #![allow(clippy::upper_case_acronyms)]
 use crate::keycodes::macos::virtual_keycodes::*;
 use crate::macos::keyboard::Keyboard;
-use crate::rdevin::{Button, Event, EventType, Key};
+use crate::rdevin::{Button, Event, EventType, Key, ModifierState, Origin, WheelUnit};
 use cocoa::base::id;
 use core_graphics::{
     event::{CGEvent, CGEventFlags, CGEventTapLocation, CGEventType, CGKeyCode, EventField},
@@ -13,7 +13,7 @@ use std::os::raw::c_void;
 use std::sync::Mutex;
 use std::time::SystemTime;
 
-use crate::keycodes::macos::key_from_code;
+use crate::keycodes::macos::{button_from_code, key_from_code};
 
 pub type CFMachPortRef = *const c_void;
 pub type CFIndex = u64;
@@ -64,6 +64,8 @@ pub const kCGEventMaskForAllEvents: u64 = (1 << CGEventType::LeftMouseDown as u6
     + (1 << CGEventType::LeftMouseUp as u64)
     + (1 << CGEventType::RightMouseDown as u64)
     + (1 << CGEventType::RightMouseUp as u64)
+    + (1 << CGEventType::OtherMouseDown as u64)
+    + (1 << CGEventType::OtherMouseUp as u64)
     + (1 << CGEventType::MouseMoved as u64)
     + (1 << CGEventType::LeftMouseDragged as u64)
     + (1 << CGEventType::RightMouseDragged as u64)
@@ -95,9 +97,19 @@ extern "C" {
     pub fn CFRunLoopGetMain() -> CFRunLoopRef;
     pub fn CGEventTapEnable(tap: CFMachPortRef, enable: bool);
     pub fn CFRunLoopRun();
+    pub fn CFRunLoopRunInMode(
+        mode: CFRunLoopMode,
+        seconds: f64,
+        returnAfterSourceHandled: bool,
+    ) -> i32;
     pub fn CFRunLoopStop(rl: CFRunLoopRef);
+    pub fn CFRunLoopRemoveSource(rl: CFRunLoopRef, source: CFRunLoopSourceRef, mode: CFRunLoopMode);
+    pub fn CFMachPortInvalidate(port: CFMachPortRef);
+    pub fn CFRetain(cf: *const c_void) -> *const c_void;
+    pub fn CFRelease(cf: *const c_void);
 
     pub static kCFRunLoopCommonModes: CFRunLoopMode;
+    pub static kCFRunLoopDefaultMode: CFRunLoopMode;
 }
 
 #[allow(improper_ctypes)]
@@ -158,6 +170,47 @@ unsafe fn get_code(cg_event: &CGEvent) -> Option<CGKeyCode> {
         .ok()
 }
 
+/// The device dependent flag of each modifier, which tells left from right.
+const DEVICE_FLAGS: [(u64, ModifierState); 8] = [
+    (0x0000_0001, ModifierState::CONTROL_LEFT),
+    (0x0000_2000, ModifierState::CONTROL_RIGHT),
+    (0x0000_0002, ModifierState::SHIFT_LEFT),
+    (0x0000_0004, ModifierState::SHIFT_RIGHT),
+    (0x0000_0020, ModifierState::ALT),
+    (0x0000_0040, ModifierState::ALT_GR),
+    (0x0000_0008, ModifierState::META_LEFT),
+    (0x0000_0010, ModifierState::META_RIGHT),
+];
+
+fn modifiers_from_flags(flags: CGEventFlags) -> ModifierState {
+    let mut modifiers = DEVICE_FLAGS
+        .iter()
+        .filter(|(flag, _)| flags.bits() & flag != 0)
+        .map(|&(_, modifier)| modifier)
+        .collect::<ModifierState>();
+    modifiers.set(
+        ModifierState::CAPS_LOCK,
+        flags.contains(CGEventFlags::CGEventFlagAlphaShift),
+    );
+    modifiers
+}
+
+/// Hardware events come from the kernel, process 0; anything else was posted by a process.
+fn origin(cg_event: &CGEvent, extra_data: i64) -> Origin {
+    if cg_event.get_integer_value_field(EventField::EVENT_SOURCE_UNIX_PROCESS_ID) == 0 {
+        Origin::Hardware
+    } else {
+        Origin::Synthetic {
+            tag: extra_data as u64,
+        }
+    }
+}
+
+fn get_button(cg_event: &CGEvent) -> Button {
+    let code = cg_event.get_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER);
+    button_from_code(code as u8)
+}
+
 pub unsafe fn convert(
     _type: CGEventType,
     cg_event: &CGEvent,
@@ -169,6 +222,8 @@ pub unsafe fn convert(
         CGEventType::LeftMouseUp => Some(EventType::ButtonRelease(Button::Left)),
         CGEventType::RightMouseDown => Some(EventType::ButtonPress(Button::Right)),
         CGEventType::RightMouseUp => Some(EventType::ButtonRelease(Button::Right)),
+        CGEventType::OtherMouseDown => Some(EventType::ButtonPress(get_button(cg_event))),
+        CGEventType::OtherMouseUp => Some(EventType::ButtonRelease(get_button(cg_event))),
         CGEventType::MouseMoved => {
             let point = cg_event.location();
             Some(EventType::MouseMove {
@@ -196,11 +251,26 @@ pub unsafe fn convert(
             }
         }
         CGEventType::ScrollWheel => {
-            let delta_y =
-                cg_event.get_integer_value_field(EventField::SCROLL_WHEEL_EVENT_POINT_DELTA_AXIS_1);
-            let delta_x =
-                cg_event.get_integer_value_field(EventField::SCROLL_WHEEL_EVENT_POINT_DELTA_AXIS_2);
-            Some(EventType::Wheel { delta_x, delta_y })
+            // Touchpads and Magic Mice scroll continuously, in pixels, and wheels by lines.
+            if cg_event.get_integer_value_field(EventField::SCROLL_WHEEL_EVENT_IS_CONTINUOUS) != 0 {
+                Some(EventType::Wheel {
+                    delta_x: cg_event
+                        .get_double_value_field(EventField::SCROLL_WHEEL_EVENT_POINT_DELTA_AXIS_2),
+                    delta_y: cg_event
+                        .get_double_value_field(EventField::SCROLL_WHEEL_EVENT_POINT_DELTA_AXIS_1),
+                    unit: WheelUnit::Pixels,
+                })
+            } else {
+                Some(EventType::Wheel {
+                    delta_x: cg_event.get_double_value_field(
+                        EventField::SCROLL_WHEEL_EVENT_FIXED_POINT_DELTA_AXIS_2,
+                    ),
+                    delta_y: cg_event.get_double_value_field(
+                        EventField::SCROLL_WHEEL_EVENT_FIXED_POINT_DELTA_AXIS_1,
+                    ),
+                    unit: WheelUnit::Lines,
+                })
+            }
         }
         _ => None,
     };
@@ -224,6 +294,7 @@ pub unsafe fn convert(
             EventType::KeyRelease(..) => None,
             _ => None,
         };
+        let extra_data = cg_event.get_integer_value_field(EventField::EVENT_SOURCE_USER_DATA);
         return Some(Event {
             event_type,
             time: SystemTime::now(),
@@ -231,7 +302,10 @@ pub unsafe fn convert(
             platform_code: code as _,
             position_code: 0 as _,
             usb_hid: 0,
-            extra_data: cg_event.get_integer_value_field(EventField::EVENT_SOURCE_USER_DATA),
+            device: None,
+            modifiers: modifiers_from_flags(cg_event.get_flags()),
+            origin: origin(cg_event, extra_data),
+            extra_data,
         });
     }
     None
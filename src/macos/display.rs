@@ -1,7 +1,44 @@
-use crate::rdevin::DisplayError;
+use crate::rdevin::{DisplayError, DisplayInfo};
 use core_graphics::display::CGDisplay;
+use core_graphics::event::CGEvent;
+use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
 
 pub fn display_size() -> Result<(u64, u64), DisplayError> {
     let main = CGDisplay::main();
     Ok((main.pixels_wide(), main.pixels_high()))
 }
+
+pub fn cursor_position() -> Result<(f64, f64), DisplayError> {
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+        .map_err(|_| DisplayError::NoDisplay)?;
+    let event = CGEvent::new(source).map_err(|_| DisplayError::NoDisplay)?;
+    let location = event.location();
+    Ok((location.x, location.y))
+}
+
+pub fn displays() -> Result<Vec<DisplayInfo>, DisplayError> {
+    let ids = CGDisplay::active_displays().map_err(|_| DisplayError::NoDisplay)?;
+    Ok(ids
+        .into_iter()
+        .map(|id| {
+            let display = CGDisplay::new(id);
+            let bounds = display.bounds();
+            // Bounds are in points, and the mode tells the pixels behind them.
+            let scale = match display.display_mode() {
+                Some(mode) if bounds.size.width > 0.0 => {
+                    mode.pixel_width() as f64 / bounds.size.width
+                }
+                _ => 1.0,
+            };
+            DisplayInfo {
+                name: id.to_string(),
+                x: bounds.origin.x as i32,
+                y: bounds.origin.y as i32,
+                width: bounds.size.width as u32,
+                height: bounds.size.height as u32,
+                scale,
+                primary: display.is_main(),
+            }
+        })
+        .collect())
+}
@@ -1,13 +1,19 @@
 #![allow(improper_ctypes_definitions)]
 use super::listen::ListenError;
 use crate::macos::common::*;
+use crate::macos::simulate::simulate_with_user_data;
 use crate::rdevin::{Event, GrabError};
+use crate::rewrite::{rewriter, Outcome};
+use crate::GrabAction;
 use cocoa::base::nil;
 use cocoa::foundation::NSAutoreleasePool;
 use core_graphics::event::{CGEventTapLocation, CGEventType};
 use std::os::raw::c_void;
 
-static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event) -> Option<Event>>> = None;
+static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event) -> Outcome>> = None;
+
+/// The source user data of the events the grab injects, which it lets through without a look.
+const INJECTED_USER_DATA: i64 = 0x7264_6576; // "rdev"
 
 unsafe extern "C" fn raw_callback(
     _proxy: CGEventTapProxy,
@@ -20,9 +26,23 @@ unsafe extern "C" fn raw_callback(
     if let Ok(mut state) = KEYBOARD_STATE.lock() {
         if let Some(keyboard) = state.as_mut() {
             if let Some(event) = convert(_type, &cg_event, keyboard) {
+                if event.extra_data == INJECTED_USER_DATA {
+                    return cg_event;
+                }
                 if let Some(callback) = &mut GLOBAL_CALLBACK {
-                    if callback(event).is_none() {
-                        cg_event.set_type(CGEventType::Null);
+                    match callback(event) {
+                        Outcome::Pass => {}
+                        Outcome::Drop => cg_event.set_type(CGEventType::Null),
+                        Outcome::Inject(events) => {
+                            for event_type in &events {
+                                if let Err(e) =
+                                    simulate_with_user_data(event_type, INJECTED_USER_DATA)
+                                {
+                                    log::warn!("Failed to inject {:?}: {:?}", event_type, e);
+                                }
+                            }
+                            cg_event.set_type(CGEventType::Null);
+                        }
                     }
                 }
             }
@@ -40,14 +60,14 @@ pub fn is_grabbed() -> bool {
 
 pub fn grab<T>(callback: T) -> Result<(), GrabError>
 where
-    T: FnMut(Event) -> Option<Event> + 'static,
+    T: FnMut(Event) -> GrabAction + 'static,
 {
     if is_grabbed() {
         return Ok(());
     }
 
     unsafe {
-        GLOBAL_CALLBACK = Some(Box::new(callback));
+        GLOBAL_CALLBACK = Some(Box::new(rewriter(callback)));
         let _pool = NSAutoreleasePool::new(nil);
         let tap = CGEventTapCreate(
             CGEventTapLocation::Session, // HID, Session, AnnotatedSession,
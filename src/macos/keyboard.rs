@@ -5,12 +5,13 @@ use core_foundation::base::{CFRelease, OSStatus};
 use core_foundation::string::UniChar;
 use core_foundation_sys::data::CFDataGetBytePtr;
 use core_graphics::event::CGEventFlags;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ffi::c_void;
 use std::os::raw::c_uint;
 
 type TISInputSourceRef = *mut c_void;
-type ModifierState = u32;
+pub(crate) type ModifierState = u32;
 type UniCharCount = usize;
 
 type OptionBits = c_uint;
@@ -238,6 +239,32 @@ impl Keyboard {
         })
     }
 
+    /// The key and modifier state that type each character on the current layout, preferring
+    /// the fewest modifiers. Dead keys are left out.
+    pub(crate) unsafe fn layout_chars(&mut self) -> HashMap<char, (u16, ModifierState)> {
+        let mut chars = HashMap::new();
+        // None, Shift, Option and Shift+Option, in the units UCKeyTranslate takes.
+        for state in [
+            0,
+            shiftKey >> 8,
+            optionKey >> 8,
+            (shiftKey | optionKey) >> 8,
+        ] {
+            for code in 0..128 {
+                let info = self.unicode_from_code(code.into(), state);
+                self.dead_state = 0;
+                let Some(name) = info.and_then(|info| info.name) else {
+                    continue;
+                };
+                let mut name = name.chars();
+                if let (Some(chr), None) = (name.next(), name.next()) {
+                    chars.entry(chr).or_insert((code, state));
+                }
+            }
+        }
+        chars
+    }
+
     pub fn is_dead(&self) -> bool {
         self.dead_state != 0
     }
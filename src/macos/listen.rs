@@ -1,25 +1,28 @@
 #![allow(improper_ctypes_definitions)]
+use crate::handle::ListenHandle;
 use crate::macos::common::*;
 use crate::rdevin::Event;
-use cocoa::base::nil;
+use cocoa::base::{id, nil};
 use cocoa::foundation::NSAutoreleasePool;
 use core_graphics::event::{CGEventTapLocation, CGEventType};
 use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event)>> = None;
+type Callback = Box<dyn FnMut(Event) + Send>;
 
 unsafe extern "C" fn raw_callback(
     _proxy: CGEventTapProxy,
     _type: CGEventType,
     cg_event: CGEventRef,
-    _user_info: *mut c_void,
+    user_info: *mut c_void,
 ) -> CGEventRef {
     // println!("Event ref {:?}", cg_event_ptr);
     // let cg_event: CGEvent = transmute_copy::<*mut c_void, CGEvent>(&cg_event_ptr);
     if let Ok(mut state) = KEYBOARD_STATE.lock() {
         if let Some(keyboard) = state.as_mut() {
             if let Some(event) = convert(_type, &cg_event, keyboard) {
-                if let Some(callback) = &mut GLOBAL_CALLBACK {
+                if let Some(callback) = (user_info as *mut Callback).as_mut() {
                     callback(event);
                 }
             }
@@ -43,9 +46,15 @@ pub enum ListenError {
     LoopSourceError,
 }
 
-pub fn listen<T>(callback: T) -> Result<(), ListenError>
+/// A run loop reference that can be stopped from another thread.
+struct RunLoop(CFRunLoopRef);
+
+// CFRunLoopStop is thread safe.
+unsafe impl Send for RunLoop {}
+
+pub fn listen<T>(callback: T) -> Result<ListenHandle, ListenError>
 where
-    T: FnMut(Event) + 'static,
+    T: FnMut(Event) + Send + 'static,
 {
     let mut types = kCGEventMaskForAllEvents;
     if crate::keyboard_only() {
@@ -53,8 +62,8 @@ where
             + (1 << CGEventType::KeyUp as u64)
             + (1 << CGEventType::FlagsChanged as u64);
     }
-    unsafe {
-        GLOBAL_CALLBACK = Some(Box::new(callback));
+    ListenHandle::spawn(move |started| unsafe {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(callback)));
         let _pool = NSAutoreleasePool::new(nil);
         let tap = CGEventTapCreate(
             CGEventTapLocation::HID, // HID, Session, AnnotatedSession,
@@ -62,21 +71,48 @@ where
             CGEventTapOption::ListenOnly,
             types,
             raw_callback,
-            nil,
+            callback as id,
         );
         if tap.is_null() {
-            return Err(ListenError::EventTapError);
+            drop(Box::from_raw(callback));
+            let _ = started.send(Err(ListenError::EventTapError));
+            return Ok(());
         }
-        let _loop = CFMachPortCreateRunLoopSource(nil, tap, 0);
-        if _loop.is_null() {
-            return Err(ListenError::LoopSourceError);
+        let source = CFMachPortCreateRunLoopSource(nil, tap, 0);
+        if source.is_null() {
+            CFMachPortInvalidate(tap);
+            CFRelease(tap);
+            drop(Box::from_raw(callback));
+            let _ = started.send(Err(ListenError::LoopSourceError));
+            return Ok(());
         }
 
-        let current_loop = CFRunLoopGetMain();
-        CFRunLoopAddSource(current_loop, _loop, kCFRunLoopCommonModes);
-
+        let current_loop = CFRunLoopGetCurrent();
+        CFRunLoopAddSource(current_loop, source, kCFRunLoopCommonModes);
         CGEventTapEnable(tap, true);
-        CFRunLoopRun();
-    }
-    Ok(())
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let run_loop = RunLoop(CFRetain(current_loop as *const c_void) as CFRunLoopRef);
+        {
+            let stopped = stopped.clone();
+            let _ = started.send(Ok(Box::new(move || {
+                let run_loop = run_loop;
+                stopped.store(true, Ordering::SeqCst);
+                CFRunLoopStop(run_loop.0);
+                CFRelease(run_loop.0 as *const c_void);
+            })));
+        }
+        // The loop is run in slices so that a stop that comes before it starts isn't missed.
+        while !stopped.load(Ordering::SeqCst) {
+            CFRunLoopRunInMode(kCFRunLoopDefaultMode, 1.0, false);
+        }
+
+        CGEventTapEnable(tap, false);
+        CFRunLoopRemoveSource(current_loop, source, kCFRunLoopCommonModes);
+        CFRelease(source as *const c_void);
+        CFMachPortInvalidate(tap);
+        CFRelease(tap);
+        drop(Box::from_raw(callback));
+        Ok(())
+    })
 }
@@ -6,9 +6,10 @@ mod grab;
 mod keyboard;
 mod listen;
 mod simulate;
+pub(crate) mod typing;
 
 pub use crate::macos::common::{map_keycode, set_is_main_thread};
-pub use crate::macos::display::display_size;
+pub use crate::macos::display::{cursor_position, display_size, displays};
 pub use crate::macos::grab::{exit_grab, grab, is_grabbed};
 pub use crate::macos::keyboard::Keyboard;
 pub use crate::macos::listen::{listen, ListenError};
@@ -1,6 +1,6 @@
-use crate::keycodes::macos::{code_from_key, virtual_keycodes::*};
+use crate::keycodes::macos::{code_from_button, code_from_key, virtual_keycodes::*};
 use crate::macos::common::CGEventSourceKeyState;
-use crate::rdevin::{Button, EventType, RawKey, SimulateError};
+use crate::rdevin::{Button, EventType, RawKey, SimulateError, WheelRemainder, WheelUnit};
 use core_graphics::{
     event::{
         CGEvent, CGEventFlags, CGEventTapLocation, CGEventType, CGKeyCode, CGMouseButton,
@@ -10,9 +10,11 @@ use core_graphics::{
     geometry::CGPoint,
 };
 use std::convert::TryInto;
+use std::sync::Mutex;
 
 static mut MOUSE_EXTRA_INFO: i64 = 0;
 static mut KEYBOARD_EXTRA_INFO: i64 = 0;
+static WHEEL_REMAINDER: Mutex<WheelRemainder> = Mutex::new(WheelRemainder::new());
 
 pub fn set_mouse_extra_info(extra: i64) {
     unsafe { MOUSE_EXTRA_INFO = extra }
@@ -105,51 +107,80 @@ unsafe fn convert_native_with_source(
         },
         EventType::ButtonPress(button) => {
             let point = get_current_mouse_location()?;
-            let event = match button {
-                Button::Left => CGEventType::LeftMouseDown,
-                Button::Right => CGEventType::RightMouseDown,
-                _ => return None,
+            let (event, mouse_button) = match button {
+                Button::Left => (CGEventType::LeftMouseDown, CGMouseButton::Left),
+                Button::Right => (CGEventType::RightMouseDown, CGMouseButton::Right),
+                _ => (CGEventType::OtherMouseDown, CGMouseButton::Center),
             };
-            CGEvent::new_mouse_event(
-                source,
-                event,
-                point,
-                CGMouseButton::Left, // ignored because we don't use OtherMouse EventType
-            )
-            .ok()
+            let code = code_from_button(*button)?;
+            let event = CGEvent::new_mouse_event(source, event, point, mouse_button).ok()?;
+            event.set_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER, code.into());
+            Some(event)
         }
         EventType::ButtonRelease(button) => {
             let point = get_current_mouse_location()?;
-            let event = match button {
-                Button::Left => CGEventType::LeftMouseUp,
-                Button::Right => CGEventType::RightMouseUp,
-                _ => return None,
+            let (event, mouse_button) = match button {
+                Button::Left => (CGEventType::LeftMouseUp, CGMouseButton::Left),
+                Button::Right => (CGEventType::RightMouseUp, CGMouseButton::Right),
+                _ => (CGEventType::OtherMouseUp, CGMouseButton::Center),
             };
-            CGEvent::new_mouse_event(
-                source,
-                event,
-                point,
-                CGMouseButton::Left, // ignored because we don't use OtherMouse EventType
-            )
-            .ok()
+            let code = code_from_button(*button)?;
+            let event = CGEvent::new_mouse_event(source, event, point, mouse_button).ok()?;
+            event.set_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER, code.into());
+            Some(event)
         }
         EventType::MouseMove { x, y } => {
             let point = CGPoint { x: (*x), y: (*y) };
             CGEvent::new_mouse_event(source, CGEventType::MouseMoved, point, CGMouseButton::Left)
                 .ok()
         }
-        EventType::Wheel { delta_x, delta_y } => {
+        EventType::MouseMoveRelative { dx, dy } => {
+            let current = get_current_mouse_location()?;
+            let (dx, dy) = (dx.round(), dy.round());
+            let point = CGPoint {
+                x: current.x + dx,
+                y: current.y + dy,
+            };
+            let event = CGEvent::new_mouse_event(
+                source,
+                CGEventType::MouseMoved,
+                point,
+                CGMouseButton::Left,
+            )
+            .ok()?;
+            // Games read the deltas rather than the position.
+            event.set_integer_value_field(EventField::MOUSE_EVENT_DELTA_X, dx as i64);
+            event.set_integer_value_field(EventField::MOUSE_EVENT_DELTA_Y, dy as i64);
+            Some(event)
+        }
+        EventType::Wheel {
+            delta_x,
+            delta_y,
+            unit,
+        } => {
             let wheel_count = 2;
+            // Whole lines go as lines, like a wheel, and anything finer as pixels, like a touchpad.
+            let (scroll_unit, target) = match unit {
+                WheelUnit::Lines if delta_x.fract() == 0.0 && delta_y.fract() == 0.0 => {
+                    (ScrollEventUnit::LINE, WheelUnit::Lines)
+                }
+                _ => (ScrollEventUnit::PIXEL, WheelUnit::Pixels),
+            };
+            let (delta_x, delta_y) = WHEEL_REMAINDER
+                .lock()
+                .ok()?
+                .take(*delta_x, *delta_y, *unit, target);
             CGEvent::new_scroll_event(
                 source,
-                ScrollEventUnit::PIXEL,
+                scroll_unit,
                 wheel_count,
-                (*delta_y).try_into().ok()?,
-                (*delta_x).try_into().ok()?,
+                delta_y.try_into().ok()?,
+                delta_x.try_into().ok()?,
                 0,
             )
             .ok()
         }
+        _ => None,
     }
 }
 
@@ -166,9 +197,17 @@ unsafe fn get_current_mouse_location() -> Option<CGPoint> {
 }
 
 pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    simulate_with_user_data(event_type, unsafe { MOUSE_EXTRA_INFO })
+}
+
+/// Simulates an event carrying `user_data` in its source user data field.
+pub(crate) fn simulate_with_user_data(
+    event_type: &EventType,
+    user_data: i64,
+) -> Result<(), SimulateError> {
     unsafe {
         if let Some(cg_event) = convert_native(event_type) {
-            cg_event.set_integer_value_field(EventField::EVENT_SOURCE_USER_DATA, MOUSE_EXTRA_INFO);
+            cg_event.set_integer_value_field(EventField::EVENT_SOURCE_USER_DATA, user_data);
             cg_event.post(CGEventTapLocation::HID);
             Ok(())
         } else {
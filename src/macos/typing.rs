@@ -0,0 +1,64 @@
+use crate::macos::keyboard::{Keyboard, ModifierState};
+use crate::rdevin::SimulateError;
+use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation, CGKeyCode};
+use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+use std::collections::HashMap;
+
+/// The Shift bit of a [`ModifierState`].
+const SHIFT: ModifierState = 2;
+/// The Option bit of a [`ModifierState`].
+const OPTION: ModifierState = 8;
+
+/// Types characters through Quartz events.
+pub(crate) struct Typist {
+    source: CGEventSource,
+    /// Where each character is on the current layout.
+    layout: HashMap<char, (CGKeyCode, ModifierState)>,
+}
+
+impl Typist {
+    pub(crate) fn new() -> Result<Typist, SimulateError> {
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+            .map_err(|()| SimulateError::SendInput)?;
+        let mut keyboard = Keyboard::new().ok_or(SimulateError::SendInput)?;
+        let layout = unsafe { keyboard.layout_chars() };
+        Ok(Typist { source, layout })
+    }
+
+    /// Types `chr`, returning whether it could.
+    pub(crate) fn type_char(&mut self, chr: char, fallback: bool) -> Result<bool, SimulateError> {
+        let chr = if chr == '\n' { '\r' } else { chr };
+        if let Some(&(code, state)) = self.layout.get(&chr) {
+            let mut flags = CGEventFlags::CGEventFlagNull;
+            if state & SHIFT != 0 {
+                flags |= CGEventFlags::CGEventFlagShift;
+            }
+            if state & OPTION != 0 {
+                flags |= CGEventFlags::CGEventFlagAlternate;
+            }
+            for pressed in [true, false] {
+                let event = self.key_event(code, pressed)?;
+                event.set_flags(flags);
+                event.post(CGEventTapLocation::HID);
+            }
+            return Ok(true);
+        }
+        if !fallback {
+            return Ok(false);
+        }
+        // A key event carrying its own string types that string whatever the key is.
+        let mut buffer = [0; 2];
+        let units: &[u16] = chr.encode_utf16(&mut buffer);
+        for pressed in [true, false] {
+            let event = self.key_event(0, pressed)?;
+            event.set_string_from_utf16_unchecked(units);
+            event.post(CGEventTapLocation::HID);
+        }
+        Ok(true)
+    }
+
+    fn key_event(&self, code: CGKeyCode, pressed: bool) -> Result<CGEvent, SimulateError> {
+        CGEvent::new_keyboard_event(self.source.clone(), code, pressed)
+            .map_err(|()| SimulateError::SendInput)
+    }
+}
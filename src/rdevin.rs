@@ -36,6 +36,48 @@ pub enum DisplayError {
     ConversionError(#[from] std::num::TryFromIntError),
 }
 
+/// A monitor, as listed by [`displays`](crate::displays).
+///
+/// Positions and sizes are in the coordinates of [`EventType::MouseMove`]: pixels on Linux and
+/// Windows, points on macOS.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DisplayInfo {
+    /// The output name on Linux, like `DP-1`, the device name on Windows, like `\\.\DISPLAY1`,
+    /// and the display ID on macOS.
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// How many physical pixels make one logical pixel. On Linux, this comes from `Xft.dpi` and
+    /// is the same for every monitor.
+    pub scale: f64,
+    pub primary: bool,
+}
+
+impl DisplayInfo {
+    /// Whether a position falls on this monitor.
+    ///
+    /// ```
+    /// use rdevin::DisplayInfo;
+    ///
+    /// let right = DisplayInfo {
+    ///     x: 1920,
+    ///     width: 1280,
+    ///     height: 1024,
+    ///     ..Default::default()
+    /// };
+    /// assert!(right.contains(1920.0, 0.0));
+    /// assert!(!right.contains(1919.5, 0.0));
+    /// assert!(!right.contains(3200.0, 0.0));
+    /// ```
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (left, top) = (self.x as f64, self.y as f64);
+        x >= left && x < left + self.width as f64 && y >= top && y < top + self.height as f64
+    }
+}
+
 /// Error simulating an event
 #[derive(Debug, Error)]
 pub enum SimulateError {
@@ -56,6 +98,10 @@ pub enum SimulateError {
     NoCode,
     #[error("No key corresponding to given character")]
     NoCorrespondingKey,
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+    #[error("This event can't be simulated")]
+    Unsupported,
 }
 
 /// Key names here assume a QWERTY layout. If you want to detect what actual character was created
@@ -240,14 +286,21 @@ impl Default for RawKey {
 
 /// Standard mouse buttons.
 ///
-/// Some mice have more than 3 buttons. These are not defined, and different
-/// OSs will give different `Button::Unknown` values.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Every platform numbers these the same way. The per-platform numbers are in the
+/// `code_from_button` and `button_from_code` functions of [`keycodes`](crate::keycodes).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Button {
     Left,
     Right,
     Middle,
+    /// The first side button, X11 button 8 and `XBUTTON1` on Windows.
+    Back,
+    /// The second side button, X11 button 9 and `XBUTTON2` on Windows.
+    Forward,
+    /// Extra buttons past `Forward`, counting from 1. Windows has none.
+    Side(u8),
+    /// A platform button number with no name, which is only meaningful on that platform.
     Unknown(u8),
 }
 
@@ -267,11 +320,159 @@ pub enum EventType {
         x: f64,
         y: f64,
     },
-    /// Positive delta is up and right.
+    /// Positive delta is up and right, in `unit`s. Touchpads and high-resolution wheels scroll by
+    /// fractions of a notch.
     Wheel {
-        delta_x: i64,
-        delta_y: i64,
+        delta_x: f64,
+        delta_y: f64,
+        #[cfg_attr(feature = "serde", serde(default))]
+        unit: WheelUnit,
+    },
+    /// Moves the cursor by this many pixels, with positive deltas right and down. Unlike
+    /// [`MouseMove`](EventType::MouseMove), it doesn't depend on where the cursor is or how the
+    /// screens are laid out, which is what games and remote desktops want.
+    ///
+    /// Simulated ones are rounded to whole pixels, and on Windows they go through the pointer
+    /// acceleration.
+    MouseMoveRelative {
+        dx: f64,
+        dy: f64,
+    },
+    /// A finger touched a touchscreen, at a position in pixels like
+    /// [`MouseMove`](EventType::MouseMove). `slot` tells apart the fingers touching at once, and
+    /// is reused once its finger lifts.
+    TouchBegin {
+        slot: u32,
+        x: f64,
+        y: f64,
+    },
+    /// A finger on a touchscreen moved.
+    TouchUpdate {
+        slot: u32,
+        x: f64,
+        y: f64,
+    },
+    /// A finger lifted off a touchscreen.
+    TouchEnd {
+        slot: u32,
+    },
+    /// A tablet tool came within range of the tablet, or left it.
+    PenProximity {
+        tool: PenTool,
+        in_range: bool,
     },
+    /// A tablet tool in range moved or changed pressure or tilt. `pressure` goes from 0 to 1,
+    /// and the tilts are in degrees from upright, positive to the right and towards the user.
+    PenMove {
+        x: f64,
+        y: f64,
+        pressure: f64,
+        tilt_x: f64,
+        tilt_y: f64,
+    },
+    PenButtonPress(PenButton),
+    PenButtonRelease(PenButton),
+}
+
+/// The tool of a [`PenProximity`](EventType::PenProximity) event, as tablets tell them apart.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PenTool {
+    #[default]
+    Pen,
+    /// The other end of a pen.
+    Eraser,
+    Brush,
+    Pencil,
+    Airbrush,
+}
+
+/// Buttons of a tablet tool.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PenButton {
+    /// The tip touching the tablet.
+    Tip,
+    /// The barrel buttons, from the one nearest the tip.
+    Primary,
+    Secondary,
+    Tertiary,
+}
+
+/// What the deltas of [`EventType::Wheel`] count.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum WheelUnit {
+    /// Notches of a wheel, which scroll a few lines each.
+    #[default]
+    Lines,
+    /// Pixels, as touchpads scroll on macOS.
+    Pixels,
+    /// 120ths of a notch, as Windows and high-resolution wheels on Linux count.
+    HighRes,
+}
+
+impl WheelUnit {
+    /// How many of this unit make a notch. A notch is taken as 15 pixels, like libinput does.
+    ///
+    /// ```
+    /// use rdevin::WheelUnit;
+    ///
+    /// assert_eq!(WheelUnit::HighRes.per_notch(), 120.0);
+    /// let delta = 30.0;
+    /// assert_eq!(delta / WheelUnit::Pixels.per_notch(), 2.0);
+    /// ```
+    pub fn per_notch(self) -> f64 {
+        match self {
+            WheelUnit::Lines => 1.0,
+            WheelUnit::Pixels => 15.0,
+            WheelUnit::HighRes => 120.0,
+        }
+    }
+}
+
+/// Scrolling short of what a simulator can send, kept for the next event so that small deltas add
+/// up instead of being lost.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[derive(Debug, Default)]
+pub(crate) struct WheelRemainder {
+    x: f64,
+    y: f64,
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+impl WheelRemainder {
+    pub(crate) const fn new() -> WheelRemainder {
+        WheelRemainder { x: 0.0, y: 0.0 }
+    }
+
+    /// Adds deltas and takes what they make in `target` units, whole.
+    pub(crate) fn take(
+        &mut self,
+        delta_x: f64,
+        delta_y: f64,
+        unit: WheelUnit,
+        target: WheelUnit,
+    ) -> (i64, i64) {
+        let scale = target.per_notch() / unit.per_notch();
+        let take = |remainder: &mut f64, delta: f64| {
+            let total = *remainder
+                + if delta.is_finite() {
+                    delta * scale
+                } else {
+                    0.0
+                };
+            // Don't lose a step to rounding errors, like 0.1 * 3.0 < 0.3.
+            let whole = if (total - total.round()).abs() < 1e-9 {
+                total.round()
+            } else {
+                total.trunc()
+            };
+            *remainder = total - whole;
+            whole as i64
+        };
+        (take(&mut self.x, delta_x), take(&mut self.y, delta_y))
+    }
 }
 
 /// The Unicode information of input.
@@ -283,6 +484,23 @@ pub struct UnicodeInfo {
     pub is_dead: bool,
 }
 
+/// Where an event came from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Origin {
+    /// A physical device.
+    Hardware,
+    /// Input injected by software, like [`simulate`](crate::simulate).
+    ///
+    /// On Windows and macOS, `tag` is the extra info the event was simulated with, as set with
+    /// `set_keyboard_extra_info` and `set_mouse_extra_info`. Linux has nowhere to carry it, so
+    /// it's always 0 there.
+    Synthetic { tag: u64 },
+    /// The backend can't tell, like the X11 listener.
+    #[default]
+    Unknown,
+}
+
 /// An input event received from the OS.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -301,12 +519,157 @@ pub struct Event {
     pub platform_code: u32,
     pub position_code: u32,
     pub usb_hid: u32,
+    /// Identifies the input device that produced the event, when the backend can tell.
+    ///
+    /// With the Linux evdev backend, this is the `N` of `/dev/input/eventN`.
+    pub device: Option<u32>,
+    /// The modifiers held, and the lock keys on, once the event happened. A modifier's own press
+    /// includes it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub modifiers: ModifierState,
+    /// Whether the event came from a device or was injected, so that programs can ignore their
+    /// own input.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub origin: Origin,
     #[cfg(target_os = "windows")]
     pub extra_data: winapi::shared::basetsd::ULONG_PTR,
     #[cfg(target_os = "macos")]
     pub extra_data: i64,
 }
 
+#[cfg(test)]
+impl Event {
+    /// An event of `event_type` at `time`, with nothing else set.
+    pub(crate) fn for_test(event_type: EventType, time: SystemTime) -> Event {
+        Event {
+            time,
+            unicode: None,
+            event_type,
+            platform_code: 0,
+            position_code: 0,
+            usb_hid: 0,
+            device: None,
+            modifiers: ModifierState::empty(),
+            origin: Origin::Unknown,
+            #[cfg(any(target_os = "windows", target_os = "macos"))]
+            extra_data: 0,
+        }
+    }
+
+    /// Like [`for_test`](Event::for_test), `millis` after the epoch.
+    pub(crate) fn at_millis(event_type: EventType, millis: u64) -> Event {
+        Event::for_test(
+            event_type,
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(millis),
+        )
+    }
+}
+
+/// Hands `handle` an event of each type at its time in milliseconds, and collects what it returns.
+#[cfg(test)]
+pub(crate) fn feed<T, F>(events: &[(EventType, u64)], mut handle: F) -> Vec<T>
+where
+    F: FnMut(&Event) -> T,
+{
+    events
+        .iter()
+        .map(|&(event_type, millis)| Event::at_millis(event_type, millis))
+        .map(|event| handle(&event))
+        .collect()
+}
+
+bitflags::bitflags! {
+    /// Modifier keys held down and lock keys turned on, with left and right kept apart.
+    ///
+    /// ```
+    /// use rdevin::{EventType, Key, ModifierState};
+    ///
+    /// let mut modifiers = ModifierState::empty();
+    /// modifiers.update(&EventType::KeyPress(Key::ShiftRight));
+    /// assert!(modifiers.shift());
+    /// assert_eq!(modifiers, ModifierState::SHIFT_RIGHT);
+    ///
+    /// modifiers.update(&EventType::KeyPress(Key::CapsLock));
+    /// modifiers.update(&EventType::KeyRelease(Key::CapsLock));
+    /// modifiers.update(&EventType::KeyRelease(Key::ShiftRight));
+    /// assert_eq!(modifiers, ModifierState::CAPS_LOCK);
+    /// ```
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ModifierState: u16 {
+        const SHIFT_LEFT = 1;
+        const SHIFT_RIGHT = 1 << 1;
+        const CONTROL_LEFT = 1 << 2;
+        const CONTROL_RIGHT = 1 << 3;
+        /// [`Key::Alt`], the left Alt key.
+        const ALT = 1 << 4;
+        const ALT_GR = 1 << 5;
+        const META_LEFT = 1 << 6;
+        const META_RIGHT = 1 << 7;
+        const CAPS_LOCK = 1 << 8;
+        const NUM_LOCK = 1 << 9;
+    }
+}
+
+impl ModifierState {
+    /// The flag `key` holds or toggles, if it is a modifier or lock key.
+    pub fn from_key(key: Key) -> ModifierState {
+        match key {
+            Key::ShiftLeft => ModifierState::SHIFT_LEFT,
+            Key::ShiftRight => ModifierState::SHIFT_RIGHT,
+            Key::ControlLeft => ModifierState::CONTROL_LEFT,
+            Key::ControlRight => ModifierState::CONTROL_RIGHT,
+            Key::Alt => ModifierState::ALT,
+            Key::AltGr => ModifierState::ALT_GR,
+            Key::MetaLeft => ModifierState::META_LEFT,
+            Key::MetaRight => ModifierState::META_RIGHT,
+            Key::CapsLock => ModifierState::CAPS_LOCK,
+            Key::NumLock => ModifierState::NUM_LOCK,
+            _ => ModifierState::empty(),
+        }
+    }
+
+    /// Follows a key event: modifiers are held between their press and release, while lock keys
+    /// toggle on press.
+    pub fn update(&mut self, event_type: &EventType) {
+        let locks = ModifierState::CAPS_LOCK | ModifierState::NUM_LOCK;
+        match *event_type {
+            EventType::KeyPress(key) => {
+                let flag = ModifierState::from_key(key);
+                if locks.contains(flag) {
+                    self.toggle(flag);
+                } else {
+                    self.insert(flag);
+                }
+            }
+            EventType::KeyRelease(key) => {
+                let flag = ModifierState::from_key(key);
+                if !locks.contains(flag) {
+                    self.remove(flag);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.intersects(ModifierState::SHIFT_LEFT | ModifierState::SHIFT_RIGHT)
+    }
+
+    pub fn control(&self) -> bool {
+        self.intersects(ModifierState::CONTROL_LEFT | ModifierState::CONTROL_RIGHT)
+    }
+
+    /// Whether either Alt key is held, counting AltGr.
+    pub fn alt(&self) -> bool {
+        self.intersects(ModifierState::ALT | ModifierState::ALT_GR)
+    }
+
+    pub fn meta(&self) -> bool {
+        self.intersects(ModifierState::META_LEFT | ModifierState::META_RIGHT)
+    }
+}
+
 // TODO: doc
 /// We can define a dummy Keyboard, that we will use to detect
 /// what kind of EventType trigger some String. We get the currently used
@@ -333,3 +696,30 @@ pub trait KeyboardState {
     // Resets the keyboard state as if we never touched it (no shift, caps_lock and so on)
     // fn reset(&mut self);
 }
+
+#[cfg(all(test, not(any(target_os = "android", target_os = "ios"))))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wheel_remainder() {
+        let mut remainder = WheelRemainder::new();
+        let lines = WheelUnit::Lines;
+        assert_eq!(remainder.take(0.0, 0.4, lines, lines), (0, 0));
+        assert_eq!(remainder.take(0.0, 0.4, lines, lines), (0, 0));
+        assert_eq!(remainder.take(0.0, 0.4, lines, lines), (0, 1));
+        assert_eq!(remainder.take(-2.5, -0.2, lines, lines), (-2, 0));
+        assert_eq!(remainder.take(-0.5, 0.0, lines, lines), (-1, 0));
+
+        let mut remainder = WheelRemainder::new();
+        assert_eq!(
+            remainder.take(15.0, -30.0, WheelUnit::Pixels, WheelUnit::HighRes),
+            (120, -240)
+        );
+        assert_eq!(
+            remainder.take(60.0, f64::NAN, WheelUnit::HighRes, lines),
+            (0, 0)
+        );
+        assert_eq!(remainder.take(60.0, 0.0, WheelUnit::HighRes, lines), (1, 0));
+    }
+}
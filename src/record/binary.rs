@@ -0,0 +1,1109 @@
+//! A compact binary encoding of recordings, for long sessions where JSON gets too large.
+//!
+//! A stream starts with the magic bytes `RDEV` and the format version as a varint. Records follow
+//! until the end of the stream, one per [`RecordedEvent`]:
+//!
+//! | Field          | Encoding                                                        |
+//! |----------------|-----------------------------------------------------------------|
+//! | kind           | byte: see [`EventType`] variants, in order                      |
+//! | flags          | byte: which optional fields follow                              |
+//! | offset         | varint nanoseconds since the previous record's offset           |
+//! | time           | zigzag varint nanoseconds the time moved past the offset delta  |
+//! | payload        | depends on the kind, see below                                  |
+//! | modifiers      | varint bits of [`ModifierState`], if they changed               |
+//! | codes          | varint platform code, position code and USB HID code, if any is set |
+//! | device         | varint, if set                                                  |
+//! | unicode        | optional name, UTF-16 units and dead key flag, if set           |
+//! | extra data     | varint, if set (Windows and macOS)                              |
+//! | tag            | varint [`Origin::Synthetic`] tag, if synthetic                  |
+//!
+//! Keys are a varint: 0 for [`Key::Unknown`] followed by its code, 1 for [`Key::RawKey`]
+//! followed by its kind and code, and 2 onwards for the other keys in declaration order. Buttons
+//! are a byte, followed by the number for [`Button::Side`] and the code for [`Button::Unknown`].
+//! Cursor positions are zigzag varint deltas from the previous position, unless one of them isn't
+//! a whole number, in which case both are written as little endian `f64`. Relative motion is
+//! written the same way, but not as a delta. Wheels are a byte for the [`WheelUnit`] followed by
+//! their deltas, as zigzag varints when whole and `f64`s otherwise. Version 2 only had whole
+//! deltas, with no unit. Touches are a varint slot, followed by their position like relative
+//! motion. Pen proximity is a byte for the [`PenTool`] and one for whether it's in range, pen
+//! motion five `f64`s, and pen buttons a byte. The origin is in the flags: hardware, synthetic, or
+//! neither when unknown.
+//!
+//! Version 4 added relative motion, 5 the back, forward and side buttons, and 6 touches and pens.
+//! Every extension of the format bumps the version, and readers reject records that the version of
+//! a stream didn't have yet.
+//!
+//! Varints are unsigned LEB128, and zigzag maps signed numbers to unsigned ones so that small
+//! magnitudes stay short.
+
+use super::{RecordError, RecordedEvent};
+use crate::rdevin::{
+    Button, Event, EventType, Key, KeyCode, ModifierState, Origin, PenButton, PenTool, RawKey,
+    UnicodeInfo, WheelUnit,
+};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime};
+
+/// The version of the binary format written by this crate.
+pub const BINARY_VERSION: u32 = 6;
+
+const MAGIC: &[u8; 4] = b"RDEV";
+
+const FLAG_UNICODE: u8 = 1;
+const FLAG_DEVICE: u8 = 1 << 1;
+const FLAG_MODIFIERS: u8 = 1 << 2;
+const FLAG_CODES: u8 = 1 << 3;
+const FLAG_EXTRA_DATA: u8 = 1 << 4;
+const FLAG_FRACTIONAL: u8 = 1 << 5;
+const FLAG_HARDWARE: u8 = 1 << 6;
+const FLAG_SYNTHETIC: u8 = 1 << 7;
+
+const KIND_KEY_PRESS: u8 = 0;
+const KIND_KEY_RELEASE: u8 = 1;
+const KIND_BUTTON_PRESS: u8 = 2;
+const KIND_BUTTON_RELEASE: u8 = 3;
+const KIND_MOUSE_MOVE: u8 = 4;
+const KIND_WHEEL: u8 = 5;
+const KIND_MOUSE_MOVE_RELATIVE: u8 = 6;
+const KIND_TOUCH_BEGIN: u8 = 7;
+const KIND_TOUCH_UPDATE: u8 = 8;
+const KIND_TOUCH_END: u8 = 9;
+const KIND_PEN_PROXIMITY: u8 = 10;
+const KIND_PEN_MOVE: u8 = 11;
+const KIND_PEN_BUTTON_PRESS: u8 = 12;
+const KIND_PEN_BUTTON_RELEASE: u8 = 13;
+
+const KEY_UNKNOWN: u64 = 0;
+const KEY_RAW: u64 = 1;
+const KEY_FIRST: u64 = 2;
+
+/// Every key without data. A key is written as [`KEY_FIRST`] plus its position here, so recordings
+/// depend on this order: never reorder or remove keys, and add new ones at the end.
+#[rustfmt::skip]
+const KEYS: [Key; 145] = [
+    Key::Alt, Key::AltGr, Key::Backspace, Key::CapsLock, Key::ControlLeft, Key::ControlRight,
+    Key::Delete, Key::DownArrow, Key::End, Key::Escape, Key::F1, Key::F10, Key::F11, Key::F12,
+    Key::F13, Key::F14, Key::F15, Key::F16, Key::F17, Key::F18, Key::F19, Key::F20, Key::F21,
+    Key::F22, Key::F23, Key::F24, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8,
+    Key::F9, Key::Home, Key::LeftArrow, Key::MetaLeft, Key::MetaRight, Key::PageDown,
+    Key::PageUp, Key::Return, Key::RightArrow, Key::ShiftLeft, Key::ShiftRight, Key::Space,
+    Key::Tab, Key::UpArrow, Key::PrintScreen, Key::ScrollLock, Key::Pause, Key::NumLock,
+    Key::BackQuote, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7,
+    Key::Num8, Key::Num9, Key::Num0, Key::Minus, Key::Equal, Key::KeyQ, Key::KeyW, Key::KeyE,
+    Key::KeyR, Key::KeyT, Key::KeyY, Key::KeyU, Key::KeyI, Key::KeyO, Key::KeyP,
+    Key::LeftBracket, Key::RightBracket, Key::KeyA, Key::KeyS, Key::KeyD, Key::KeyF, Key::KeyG,
+    Key::KeyH, Key::KeyJ, Key::KeyK, Key::KeyL, Key::SemiColon, Key::Quote, Key::BackSlash,
+    Key::IntlBackslash, Key::IntlRo, Key::IntlYen, Key::KanaMode, Key::KeyZ, Key::KeyX,
+    Key::KeyC, Key::KeyV, Key::KeyB, Key::KeyN, Key::KeyM, Key::Comma, Key::Dot, Key::Slash,
+    Key::Insert, Key::KpReturn, Key::KpMinus, Key::KpPlus, Key::KpMultiply, Key::KpDivide,
+    Key::KpDecimal, Key::KpEqual, Key::KpComma, Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3,
+    Key::Kp4, Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8, Key::Kp9, Key::VolumeUp, Key::VolumeDown,
+    Key::VolumeMute, Key::Lang1, Key::Lang2, Key::Lang3, Key::Lang4, Key::Lang5, Key::Function,
+    Key::Apps, Key::Cancel, Key::Clear, Key::Kana, Key::Hangul, Key::Junja, Key::Final,
+    Key::Hanja, Key::Hanji, Key::Print, Key::Select, Key::Execute, Key::Help, Key::Sleep,
+    Key::Separator,
+];
+
+lazy_static::lazy_static! {
+    static ref KEY_INDICES: HashMap<Key, u64> = KEYS
+        .iter()
+        .enumerate()
+        .map(|(i, &key)| (key, i as u64))
+        .collect();
+}
+
+fn invalid(what: &'static str) -> RecordError {
+    RecordError::InvalidBinary(what)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    let mut buffer = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer[len] = byte;
+            len += 1;
+            break;
+        }
+        buffer[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&buffer[..len])
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<u64, RecordError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(reader)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<u8, RecordError> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, RecordError> {
+    u32::try_from(read_varint(reader)?).map_err(|_| invalid("number out of range"))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64, RecordError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+/// Nanoseconds since the Unix epoch, negative before it.
+fn nanos(time: SystemTime) -> i128 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => after.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128),
+    }
+}
+
+fn from_nanos(nanos: i128) -> Option<SystemTime> {
+    let duration = |nanos: u128| {
+        let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+        Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+    };
+    if nanos >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(duration(nanos as u128)?)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(duration(nanos.unsigned_abs())?)
+    }
+}
+
+/// Whether a position can be delta coded without losing anything.
+fn is_whole(value: f64) -> bool {
+    value.fract() == 0.0 && value.abs() < (1u64 << 52) as f64
+}
+
+fn raw_key_kind(raw: RawKey) -> (u8, KeyCode) {
+    match raw {
+        RawKey::ScanCode(code) => (0, code),
+        RawKey::WinVirtualKeycode(code) => (1, code),
+        RawKey::LinuxXorgKeycode(code) => (2, code),
+        RawKey::LinuxConsoleKeycode(code) => (3, code),
+        RawKey::MacVirtualKeycode(code) => (4, code),
+    }
+}
+
+fn raw_key(kind: u8, code: KeyCode) -> Option<RawKey> {
+    Some(match kind {
+        0 => RawKey::ScanCode(code),
+        1 => RawKey::WinVirtualKeycode(code),
+        2 => RawKey::LinuxXorgKeycode(code),
+        3 => RawKey::LinuxConsoleKeycode(code),
+        4 => RawKey::MacVirtualKeycode(code),
+        _ => return None,
+    })
+}
+
+fn wheel_unit_code(unit: WheelUnit) -> u8 {
+    match unit {
+        WheelUnit::Lines => 0,
+        WheelUnit::Pixels => 1,
+        WheelUnit::HighRes => 2,
+    }
+}
+
+fn wheel_unit(code: u8) -> Option<WheelUnit> {
+    Some(match code {
+        0 => WheelUnit::Lines,
+        1 => WheelUnit::Pixels,
+        2 => WheelUnit::HighRes,
+        _ => return None,
+    })
+}
+
+fn pen_tool_code(tool: PenTool) -> u8 {
+    match tool {
+        PenTool::Pen => 0,
+        PenTool::Eraser => 1,
+        PenTool::Brush => 2,
+        PenTool::Pencil => 3,
+        PenTool::Airbrush => 4,
+    }
+}
+
+fn pen_tool(code: u8) -> Option<PenTool> {
+    Some(match code {
+        0 => PenTool::Pen,
+        1 => PenTool::Eraser,
+        2 => PenTool::Brush,
+        3 => PenTool::Pencil,
+        4 => PenTool::Airbrush,
+        _ => return None,
+    })
+}
+
+fn pen_button_code(button: PenButton) -> u8 {
+    match button {
+        PenButton::Tip => 0,
+        PenButton::Primary => 1,
+        PenButton::Secondary => 2,
+        PenButton::Tertiary => 3,
+    }
+}
+
+fn pen_button(code: u8) -> Option<PenButton> {
+    Some(match code {
+        0 => PenButton::Tip,
+        1 => PenButton::Primary,
+        2 => PenButton::Secondary,
+        3 => PenButton::Tertiary,
+        _ => return None,
+    })
+}
+
+/// What records are coded relative to.
+#[derive(Debug, Default)]
+struct State {
+    offset: Duration,
+    time: i128,
+    modifiers: ModifierState,
+    x: f64,
+    y: f64,
+}
+
+/// Writes recorded events in the binary format, one at a time.
+///
+/// ```
+/// use rdevin::record::binary::{BinaryReader, BinaryWriter};
+/// # use rdevin::record::RecordedEvent;
+/// # let events: Vec<RecordedEvent> = Vec::new();
+///
+/// let mut writer = BinaryWriter::new(Vec::new())?;
+/// for event in &events {
+///     writer.write(event)?;
+/// }
+/// let bytes = writer.into_inner();
+///
+/// for event in BinaryReader::new(&bytes[..])? {
+///     dbg!(event?);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct BinaryWriter<W: Write> {
+    writer: W,
+    state: State,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    /// Writes the header.
+    pub fn new(mut writer: W) -> io::Result<BinaryWriter<W>> {
+        writer.write_all(MAGIC)?;
+        write_varint(&mut writer, BINARY_VERSION.into())?;
+        Ok(BinaryWriter {
+            writer,
+            state: State::default(),
+        })
+    }
+
+    /// Writes an event. Offsets before the previous event's are written as the same offset.
+    pub fn write(&mut self, recorded: &RecordedEvent) -> io::Result<()> {
+        let event = &recorded.event;
+        let w = &mut self.writer;
+        let (kind, mut flags) = match event.event_type {
+            EventType::KeyPress(_) => (KIND_KEY_PRESS, 0),
+            EventType::KeyRelease(_) => (KIND_KEY_RELEASE, 0),
+            EventType::ButtonPress(_) => (KIND_BUTTON_PRESS, 0),
+            EventType::ButtonRelease(_) => (KIND_BUTTON_RELEASE, 0),
+            EventType::MouseMove { x, y } if is_whole(x) && is_whole(y) => (KIND_MOUSE_MOVE, 0),
+            EventType::MouseMove { .. } => (KIND_MOUSE_MOVE, FLAG_FRACTIONAL),
+            EventType::Wheel {
+                delta_x, delta_y, ..
+            } if is_whole(delta_x) && is_whole(delta_y) => (KIND_WHEEL, 0),
+            EventType::Wheel { .. } => (KIND_WHEEL, FLAG_FRACTIONAL),
+            EventType::MouseMoveRelative { dx, dy } if is_whole(dx) && is_whole(dy) => {
+                (KIND_MOUSE_MOVE_RELATIVE, 0)
+            }
+            EventType::MouseMoveRelative { .. } => (KIND_MOUSE_MOVE_RELATIVE, FLAG_FRACTIONAL),
+            EventType::TouchBegin { x, y, .. } if is_whole(x) && is_whole(y) => {
+                (KIND_TOUCH_BEGIN, 0)
+            }
+            EventType::TouchBegin { .. } => (KIND_TOUCH_BEGIN, FLAG_FRACTIONAL),
+            EventType::TouchUpdate { x, y, .. } if is_whole(x) && is_whole(y) => {
+                (KIND_TOUCH_UPDATE, 0)
+            }
+            EventType::TouchUpdate { .. } => (KIND_TOUCH_UPDATE, FLAG_FRACTIONAL),
+            EventType::TouchEnd { .. } => (KIND_TOUCH_END, 0),
+            EventType::PenProximity { .. } => (KIND_PEN_PROXIMITY, 0),
+            EventType::PenMove { .. } => (KIND_PEN_MOVE, 0),
+            EventType::PenButtonPress(_) => (KIND_PEN_BUTTON_PRESS, 0),
+            EventType::PenButtonRelease(_) => (KIND_PEN_BUTTON_RELEASE, 0),
+        };
+        let codes = [event.platform_code, event.position_code, event.usb_hid];
+        let extra_data = extra_data(event);
+        for (set, flag) in [
+            (event.unicode.is_some(), FLAG_UNICODE),
+            (event.device.is_some(), FLAG_DEVICE),
+            (event.modifiers != self.state.modifiers, FLAG_MODIFIERS),
+            (codes.iter().any(|&code| code != 0), FLAG_CODES),
+            (extra_data != 0, FLAG_EXTRA_DATA),
+            (event.origin == Origin::Hardware, FLAG_HARDWARE),
+            (
+                matches!(event.origin, Origin::Synthetic { .. }),
+                FLAG_SYNTHETIC,
+            ),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        w.write_all(&[kind, flags])?;
+
+        let offset = recorded.offset.max(self.state.offset);
+        let delta = offset - self.state.offset;
+        write_varint(w, u64::try_from(delta.as_nanos()).unwrap_or(u64::MAX))?;
+        self.state.offset = offset;
+        let time = nanos(event.time);
+        let expected = self.state.time + delta.as_nanos() as i128;
+        let time_delta = i64::try_from(time - expected)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "event time out of range"))?;
+        write_varint(w, zigzag(time_delta))?;
+        self.state.time = time;
+
+        match event.event_type {
+            EventType::KeyPress(key) | EventType::KeyRelease(key) => match key {
+                Key::Unknown(code) => {
+                    write_varint(w, KEY_UNKNOWN)?;
+                    write_varint(w, code.into())?;
+                }
+                Key::RawKey(raw) => {
+                    let (kind, code) = raw_key_kind(raw);
+                    write_varint(w, KEY_RAW)?;
+                    w.write_all(&[kind])?;
+                    write_varint(w, code.into())?;
+                }
+                key => {
+                    let index = KEY_INDICES.get(&key).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "key missing from KEYS")
+                    })?;
+                    write_varint(w, KEY_FIRST + index)?
+                }
+            },
+            EventType::ButtonPress(button) | EventType::ButtonRelease(button) => match button {
+                Button::Left => w.write_all(&[0])?,
+                Button::Right => w.write_all(&[1])?,
+                Button::Middle => w.write_all(&[2])?,
+                Button::Unknown(code) => w.write_all(&[3, code])?,
+                Button::Back => w.write_all(&[4])?,
+                Button::Forward => w.write_all(&[5])?,
+                Button::Side(n) => w.write_all(&[6, n])?,
+            },
+            EventType::MouseMove { x, y } => {
+                if flags & FLAG_FRACTIONAL != 0 {
+                    w.write_all(&x.to_le_bytes())?;
+                    w.write_all(&y.to_le_bytes())?;
+                } else {
+                    write_varint(w, zigzag(x as i64 - self.state.x as i64))?;
+                    write_varint(w, zigzag(y as i64 - self.state.y as i64))?;
+                }
+                self.state.x = if is_whole(x) { x } else { 0.0 };
+                self.state.y = if is_whole(y) { y } else { 0.0 };
+            }
+            EventType::Wheel {
+                delta_x,
+                delta_y,
+                unit,
+            } => {
+                w.write_all(&[wheel_unit_code(unit)])?;
+                if flags & FLAG_FRACTIONAL != 0 {
+                    w.write_all(&delta_x.to_le_bytes())?;
+                    w.write_all(&delta_y.to_le_bytes())?;
+                } else {
+                    write_varint(w, zigzag(delta_x as i64))?;
+                    write_varint(w, zigzag(delta_y as i64))?;
+                }
+            }
+            EventType::MouseMoveRelative { dx, dy } => {
+                if flags & FLAG_FRACTIONAL != 0 {
+                    w.write_all(&dx.to_le_bytes())?;
+                    w.write_all(&dy.to_le_bytes())?;
+                } else {
+                    write_varint(w, zigzag(dx as i64))?;
+                    write_varint(w, zigzag(dy as i64))?;
+                }
+            }
+            EventType::TouchBegin { slot, x, y } | EventType::TouchUpdate { slot, x, y } => {
+                write_varint(w, slot.into())?;
+                if flags & FLAG_FRACTIONAL != 0 {
+                    w.write_all(&x.to_le_bytes())?;
+                    w.write_all(&y.to_le_bytes())?;
+                } else {
+                    write_varint(w, zigzag(x as i64))?;
+                    write_varint(w, zigzag(y as i64))?;
+                }
+            }
+            EventType::TouchEnd { slot } => write_varint(w, slot.into())?,
+            EventType::PenProximity { tool, in_range } => {
+                w.write_all(&[pen_tool_code(tool), in_range.into()])?
+            }
+            EventType::PenMove {
+                x,
+                y,
+                pressure,
+                tilt_x,
+                tilt_y,
+            } => {
+                for value in [x, y, pressure, tilt_x, tilt_y] {
+                    w.write_all(&value.to_le_bytes())?;
+                }
+            }
+            EventType::PenButtonPress(button) | EventType::PenButtonRelease(button) => {
+                w.write_all(&[pen_button_code(button)])?
+            }
+        }
+
+        if flags & FLAG_MODIFIERS != 0 {
+            write_varint(w, event.modifiers.bits().into())?;
+            self.state.modifiers = event.modifiers;
+        }
+        if flags & FLAG_CODES != 0 {
+            for code in codes {
+                write_varint(w, code.into())?;
+            }
+        }
+        if let Some(device) = event.device {
+            write_varint(w, device.into())?;
+        }
+        if let Some(unicode) = &event.unicode {
+            match &unicode.name {
+                Some(name) => {
+                    write_varint(w, name.len() as u64 + 1)?;
+                    w.write_all(name.as_bytes())?;
+                }
+                None => write_varint(w, 0)?,
+            }
+            write_varint(w, unicode.unicode.len() as u64)?;
+            for &unit in &unicode.unicode {
+                write_varint(w, unit.into())?;
+            }
+            w.write_all(&[unicode.is_dead.into()])?;
+        }
+        if flags & FLAG_EXTRA_DATA != 0 {
+            write_varint(w, extra_data)?;
+        }
+        if let Origin::Synthetic { tag } = event.origin {
+            write_varint(w, tag)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads recorded events in the binary format, one at a time.
+#[derive(Debug)]
+pub struct BinaryReader<R: Read> {
+    reader: R,
+    version: u32,
+    state: State,
+    failed: bool,
+}
+
+impl<R: Read> BinaryReader<R> {
+    /// Reads and checks the header.
+    pub fn new(mut reader: R) -> Result<BinaryReader<R>, RecordError> {
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| RecordError::NotARecording)?;
+        if &magic != MAGIC {
+            return Err(RecordError::NotARecording);
+        }
+        let version = read_u32(&mut reader)?;
+        if version > BINARY_VERSION {
+            return Err(RecordError::UnsupportedVersion(version));
+        }
+        Ok(BinaryReader {
+            reader,
+            version,
+            state: State::default(),
+            failed: false,
+        })
+    }
+
+    /// Reads the next event, or `None` at the end of the stream.
+    pub fn read(&mut self) -> Result<Option<RecordedEvent>, RecordError> {
+        let mut header = [0; 2];
+        match self.reader.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut header[1..])?,
+        }
+        let [kind, flags] = header;
+        let r = &mut self.reader;
+
+        let delta = Duration::from_nanos(read_varint(r)?);
+        self.state.offset = self
+            .state
+            .offset
+            .checked_add(delta)
+            .ok_or_else(|| invalid("offset out of range"))?;
+        let time = self.state.time + delta.as_nanos() as i128 + unzigzag(read_varint(r)?) as i128;
+        self.state.time = time;
+
+        // Touches and pens came in version 6.
+        if kind >= KIND_TOUCH_BEGIN && self.version < 6 {
+            return Err(invalid("event kind"));
+        }
+        let event_type = match kind {
+            KIND_KEY_PRESS | KIND_KEY_RELEASE => {
+                let key = match read_varint(r)? {
+                    KEY_UNKNOWN => Key::Unknown(read_u32(r)?),
+                    KEY_RAW => {
+                        let kind = read_byte(r)?;
+                        let code = read_u32(r)?;
+                        Key::RawKey(raw_key(kind, code).ok_or_else(|| invalid("raw key kind"))?)
+                    }
+                    index => *usize::try_from(index - KEY_FIRST)
+                        .ok()
+                        .and_then(|index| KEYS.get(index))
+                        .ok_or_else(|| invalid("key"))?,
+                };
+                if kind == KIND_KEY_PRESS {
+                    EventType::KeyPress(key)
+                } else {
+                    EventType::KeyRelease(key)
+                }
+            }
+            KIND_BUTTON_PRESS | KIND_BUTTON_RELEASE => {
+                let button = match read_byte(r)? {
+                    0 => Button::Left,
+                    1 => Button::Right,
+                    2 => Button::Middle,
+                    3 => Button::Unknown(read_byte(r)?),
+                    4 if self.version >= 5 => Button::Back,
+                    5 if self.version >= 5 => Button::Forward,
+                    6 if self.version >= 5 => Button::Side(read_byte(r)?),
+                    _ => return Err(invalid("button")),
+                };
+                if kind == KIND_BUTTON_PRESS {
+                    EventType::ButtonPress(button)
+                } else {
+                    EventType::ButtonRelease(button)
+                }
+            }
+            KIND_MOUSE_MOVE => {
+                let (x, y) = if flags & FLAG_FRACTIONAL != 0 {
+                    (read_f64(r)?, read_f64(r)?)
+                } else {
+                    (
+                        (self.state.x as i64 + unzigzag(read_varint(r)?)) as f64,
+                        (self.state.y as i64 + unzigzag(read_varint(r)?)) as f64,
+                    )
+                };
+                self.state.x = if is_whole(x) { x } else { 0.0 };
+                self.state.y = if is_whole(y) { y } else { 0.0 };
+                EventType::MouseMove { x, y }
+            }
+            KIND_WHEEL => {
+                // Version 2 only had whole notches.
+                let unit = if self.version < 3 {
+                    WheelUnit::Lines
+                } else {
+                    wheel_unit(read_byte(r)?).ok_or_else(|| invalid("wheel unit"))?
+                };
+                let (delta_x, delta_y) = if flags & FLAG_FRACTIONAL != 0 {
+                    (read_f64(r)?, read_f64(r)?)
+                } else {
+                    (
+                        unzigzag(read_varint(r)?) as f64,
+                        unzigzag(read_varint(r)?) as f64,
+                    )
+                };
+                EventType::Wheel {
+                    delta_x,
+                    delta_y,
+                    unit,
+                }
+            }
+            KIND_MOUSE_MOVE_RELATIVE if self.version >= 4 => {
+                let (dx, dy) = if flags & FLAG_FRACTIONAL != 0 {
+                    (read_f64(r)?, read_f64(r)?)
+                } else {
+                    (
+                        unzigzag(read_varint(r)?) as f64,
+                        unzigzag(read_varint(r)?) as f64,
+                    )
+                };
+                EventType::MouseMoveRelative { dx, dy }
+            }
+            KIND_TOUCH_BEGIN | KIND_TOUCH_UPDATE => {
+                let slot = read_u32(r)?;
+                let (x, y) = if flags & FLAG_FRACTIONAL != 0 {
+                    (read_f64(r)?, read_f64(r)?)
+                } else {
+                    (
+                        unzigzag(read_varint(r)?) as f64,
+                        unzigzag(read_varint(r)?) as f64,
+                    )
+                };
+                if kind == KIND_TOUCH_BEGIN {
+                    EventType::TouchBegin { slot, x, y }
+                } else {
+                    EventType::TouchUpdate { slot, x, y }
+                }
+            }
+            KIND_TOUCH_END => EventType::TouchEnd { slot: read_u32(r)? },
+            KIND_PEN_PROXIMITY => EventType::PenProximity {
+                tool: pen_tool(read_byte(r)?).ok_or_else(|| invalid("pen tool"))?,
+                in_range: match read_byte(r)? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(invalid("pen proximity")),
+                },
+            },
+            KIND_PEN_MOVE => EventType::PenMove {
+                x: read_f64(r)?,
+                y: read_f64(r)?,
+                pressure: read_f64(r)?,
+                tilt_x: read_f64(r)?,
+                tilt_y: read_f64(r)?,
+            },
+            KIND_PEN_BUTTON_PRESS | KIND_PEN_BUTTON_RELEASE => {
+                let button = pen_button(read_byte(r)?).ok_or_else(|| invalid("pen button"))?;
+                if kind == KIND_PEN_BUTTON_PRESS {
+                    EventType::PenButtonPress(button)
+                } else {
+                    EventType::PenButtonRelease(button)
+                }
+            }
+            _ => return Err(invalid("event kind")),
+        };
+
+        if flags & FLAG_MODIFIERS != 0 {
+            let bits = u16::try_from(read_varint(r)?).map_err(|_| invalid("modifiers"))?;
+            self.state.modifiers =
+                ModifierState::from_bits(bits).ok_or_else(|| invalid("modifiers"))?;
+        }
+        let mut codes = [0; 3];
+        if flags & FLAG_CODES != 0 {
+            for code in &mut codes {
+                *code = read_u32(r)?;
+            }
+        }
+        let device = if flags & FLAG_DEVICE != 0 {
+            Some(read_u32(r)?)
+        } else {
+            None
+        };
+        let unicode = if flags & FLAG_UNICODE != 0 {
+            let name = match read_varint(r)? {
+                0 => None,
+                len => {
+                    let len = usize::try_from(len - 1).map_err(|_| invalid("name"))?;
+                    let mut name = Vec::new();
+                    r.take(len as u64).read_to_end(&mut name)?;
+                    if name.len() != len {
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    Some(String::from_utf8(name).map_err(|_| invalid("name"))?)
+                }
+            };
+            let len = read_varint(r)?;
+            let unicode = (0..len)
+                .map(|_| u16::try_from(read_varint(r)?).map_err(|_| invalid("number out of range")))
+                .collect::<Result<_, _>>()?;
+            let is_dead = read_byte(r)? != 0;
+            Some(UnicodeInfo {
+                name,
+                unicode,
+                is_dead,
+            })
+        } else {
+            None
+        };
+        let extra_data = if flags & FLAG_EXTRA_DATA != 0 {
+            read_varint(r)?
+        } else {
+            0
+        };
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let _ = extra_data;
+        let origin = match (flags & FLAG_HARDWARE != 0, flags & FLAG_SYNTHETIC != 0) {
+            (false, false) => Origin::Unknown,
+            (true, false) => Origin::Hardware,
+            (false, true) => Origin::Synthetic {
+                tag: read_varint(r)?,
+            },
+            (true, true) => return Err(invalid("origin")),
+        };
+
+        let event = Event {
+            time: from_nanos(time).ok_or_else(|| invalid("time out of range"))?,
+            unicode,
+            event_type,
+            platform_code: codes[0],
+            position_code: codes[1],
+            usb_hid: codes[2],
+            device,
+            modifiers: self.state.modifiers,
+            origin,
+            #[cfg(target_os = "windows")]
+            extra_data: extra_data as _,
+            #[cfg(target_os = "macos")]
+            extra_data: unzigzag(extra_data),
+        };
+        Ok(Some(RecordedEvent {
+            offset: self.state.offset,
+            event,
+        }))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Stops after the first error.
+impl<R: Read> Iterator for BinaryReader<R> {
+    type Item = Result<RecordedEvent, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn extra_data(event: &Event) -> u64 {
+    event.extra_data as u64
+}
+
+#[cfg(target_os = "macos")]
+fn extra_data(event: &Event) -> u64 {
+    zigzag(event.extra_data)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn extra_data(_event: &Event) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Recording;
+    use strum::IntoEnumIterator;
+
+    fn recording() -> Recording {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut events = vec![
+            Event::for_test(EventType::KeyPress(Key::ShiftLeft), start),
+            Event::for_test(
+                EventType::KeyPress(Key::KeyA),
+                start + Duration::from_nanos(1_234_567),
+            ),
+            Event::for_test(
+                EventType::KeyRelease(Key::Unknown(4242)),
+                start + Duration::from_millis(5),
+            ),
+            Event::for_test(
+                EventType::KeyPress(Key::RawKey(RawKey::LinuxXorgKeycode(191))),
+                start + Duration::from_millis(5),
+            ),
+            Event::for_test(
+                EventType::ButtonPress(Button::Unknown(9)),
+                start + Duration::from_millis(6),
+            ),
+            Event::for_test(
+                EventType::ButtonRelease(Button::Middle),
+                start + Duration::from_millis(6),
+            ),
+            Event::for_test(
+                EventType::ButtonPress(Button::Back),
+                start + Duration::from_millis(6),
+            ),
+            Event::for_test(
+                EventType::ButtonRelease(Button::Side(2)),
+                start + Duration::from_millis(6),
+            ),
+            Event::for_test(
+                EventType::Wheel {
+                    delta_x: -3.0,
+                    delta_y: 120.0,
+                    unit: WheelUnit::HighRes,
+                },
+                start + Duration::from_millis(7),
+            ),
+            Event::for_test(
+                EventType::Wheel {
+                    delta_x: 0.0,
+                    delta_y: -0.25,
+                    unit: WheelUnit::Lines,
+                },
+                start + Duration::from_millis(7),
+            ),
+            Event::for_test(
+                EventType::MouseMoveRelative { dx: -4.0, dy: 2.0 },
+                start + Duration::from_millis(7),
+            ),
+            Event::for_test(
+                EventType::MouseMoveRelative { dx: 0.25, dy: 1.0 },
+                start + Duration::from_millis(7),
+            ),
+            Event::for_test(
+                EventType::TouchBegin {
+                    slot: 0,
+                    x: 300.0,
+                    y: 200.0,
+                },
+                start + Duration::from_millis(8),
+            ),
+            Event::for_test(
+                EventType::TouchUpdate {
+                    slot: 0,
+                    x: 302.5,
+                    y: 199.0,
+                },
+                start + Duration::from_millis(8),
+            ),
+            Event::for_test(
+                EventType::TouchEnd { slot: 0 },
+                start + Duration::from_millis(9),
+            ),
+            Event::for_test(
+                EventType::PenProximity {
+                    tool: PenTool::Eraser,
+                    in_range: true,
+                },
+                start + Duration::from_millis(9),
+            ),
+            Event::for_test(
+                EventType::PenMove {
+                    x: 10.5,
+                    y: 20.0,
+                    pressure: 0.75,
+                    tilt_x: -12.0,
+                    tilt_y: 30.0,
+                },
+                start + Duration::from_millis(9),
+            ),
+            Event::for_test(
+                EventType::PenButtonPress(PenButton::Tip),
+                start + Duration::from_millis(9),
+            ),
+            Event::for_test(
+                EventType::PenButtonRelease(PenButton::Secondary),
+                start + Duration::from_millis(9),
+            ),
+        ];
+        events[0].modifiers = ModifierState::SHIFT_LEFT;
+        events[1].modifiers = ModifierState::SHIFT_LEFT;
+        events[1].unicode = Some(UnicodeInfo {
+            name: Some("A".to_string()),
+            unicode: vec![65],
+            is_dead: false,
+        });
+        events[1].platform_code = 38;
+        events[1].position_code = 38;
+        events[1].device = Some(3);
+        events[1].origin = Origin::Hardware;
+        events[2].origin = Origin::Synthetic { tag: 0x7264 };
+        for (i, &(x, y)) in [
+            (0.0, 0.0),
+            (10.0, 1.0),
+            (9.0, -1.0),
+            (9.5, 2.25),
+            (12.0, 3.0),
+        ]
+        .iter()
+        .enumerate()
+        {
+            events.push(Event::for_test(
+                EventType::MouseMove { x, y },
+                start + Duration::from_millis(8 + i as u64),
+            ));
+        }
+        Recording::from_events(start, events)
+    }
+
+    fn encode(recording: &Recording) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        for event in &recording.events {
+            writer.write(event).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn decode(bytes: &[u8]) -> Recording {
+        Recording {
+            events: BinaryReader::new(bytes)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u32::MAX.into(), u64::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value).unwrap();
+            assert_eq!(read_varint(&mut &bytes[..]).unwrap(), value);
+        }
+        for value in [0, -1, 1, i64::MIN, i64::MAX] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+        assert_eq!(zigzag(-1), 1);
+    }
+
+    #[test]
+    fn test_round_trip_json() {
+        let recording = recording();
+        let mut json = Vec::new();
+        recording.write(&mut json).unwrap();
+        let binary = encode(&recording);
+
+        assert_eq!(decode(&binary), recording);
+        assert_eq!(decode(&binary), Recording::read(&json[..]).unwrap());
+        assert!(binary.len() * 10 < json.len());
+    }
+
+    #[test]
+    fn test_mouse_moves_are_small() {
+        let start = SystemTime::UNIX_EPOCH;
+        let recording = Recording::from_events(
+            start,
+            (0..1000).map(|i| {
+                Event::for_test(
+                    EventType::MouseMove {
+                        x: (i % 100) as f64,
+                        y: 500.0,
+                    },
+                    start + Duration::from_millis(i),
+                )
+            }),
+        );
+        let binary = encode(&recording);
+        assert_eq!(decode(&binary), recording);
+        assert!(binary.len() < 10 * recording.events.len());
+    }
+
+    #[test]
+    fn test_version_2_wheel() {
+        // A wheel record with no offset, time or flags, and deltas 1 and -1 zigzag coded.
+        let bytes = b"RDEV\x02\x05\x00\x00\x00\x02\x01";
+        let events = BinaryReader::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            events[0].event.event_type,
+            EventType::Wheel {
+                delta_x: 1.0,
+                delta_y: -1.0,
+                unit: WheelUnit::Lines,
+            }
+        );
+    }
+
+    #[test]
+    fn test_older_versions() {
+        let first = |bytes: &[u8]| BinaryReader::new(bytes).unwrap().next().unwrap();
+        // A relative motion record with no offset, time or flags, and deltas 1 and -1.
+        assert!(first(b"RDEV\x03\x06\x00\x00\x00\x02\x01").is_err());
+        assert_eq!(
+            first(b"RDEV\x04\x06\x00\x00\x00\x02\x01")
+                .unwrap()
+                .event
+                .event_type,
+            EventType::MouseMoveRelative { dx: 1.0, dy: -1.0 }
+        );
+        // A press of the back button.
+        assert!(first(b"RDEV\x04\x02\x00\x00\x00\x04").is_err());
+        assert_eq!(
+            first(b"RDEV\x05\x02\x00\x00\x00\x04")
+                .unwrap()
+                .event
+                .event_type,
+            EventType::ButtonPress(Button::Back)
+        );
+        // The end of a touch in slot 1.
+        assert!(first(b"RDEV\x05\x09\x00\x00\x00\x01").is_err());
+        assert_eq!(
+            first(b"RDEV\x06\x09\x00\x00\x00\x01")
+                .unwrap()
+                .event
+                .event_type,
+            EventType::TouchEnd { slot: 1 }
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            BinaryReader::new(&b"JSON"[..]),
+            Err(RecordError::NotARecording)
+        ));
+        assert!(matches!(
+            BinaryReader::new(&b"RDEV\x07"[..]),
+            Err(RecordError::UnsupportedVersion(7))
+        ));
+        let binary = encode(&recording());
+        let mut reader = BinaryReader::new(&binary[..binary.len() - 1]).unwrap();
+        assert!(reader.by_ref().any(|event| event.is_err()));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_key_codes_are_frozen() {
+        // The codes of recordings already written. Keys added since may only follow these.
+        let frozen = concat!(
+            "Alt AltGr Backspace CapsLock ControlLeft ControlRight Delete DownArrow End ",
+            "Escape F1 F10 F11 F12 F13 F14 F15 F16 F17 F18 F19 F20 F21 F22 F23 F24 F2 F3 F4 ",
+            "F5 F6 F7 F8 F9 Home LeftArrow MetaLeft MetaRight PageDown PageUp Return ",
+            "RightArrow ShiftLeft ShiftRight Space Tab UpArrow PrintScreen ScrollLock Pause ",
+            "NumLock BackQuote Num1 Num2 Num3 Num4 Num5 Num6 Num7 Num8 Num9 Num0 Minus Equal ",
+            "KeyQ KeyW KeyE KeyR KeyT KeyY KeyU KeyI KeyO KeyP LeftBracket RightBracket KeyA ",
+            "KeyS KeyD KeyF KeyG KeyH KeyJ KeyK KeyL SemiColon Quote BackSlash IntlBackslash ",
+            "IntlRo IntlYen KanaMode KeyZ KeyX KeyC KeyV KeyB KeyN KeyM Comma Dot Slash ",
+            "Insert KpReturn KpMinus KpPlus KpMultiply KpDivide KpDecimal KpEqual KpComma Kp0 ",
+            "Kp1 Kp2 Kp3 Kp4 Kp5 Kp6 Kp7 Kp8 Kp9 VolumeUp VolumeDown VolumeMute Lang1 Lang2 ",
+            "Lang3 Lang4 Lang5 Function Apps Cancel Clear Kana Hangul Junja Final Hanja Hanji ",
+            "Print Select Execute Help Sleep Separator ",
+        );
+        let frozen: Vec<_> = frozen.split_whitespace().collect();
+        let names: Vec<_> = KEYS.iter().map(|key| format!("{:?}", key)).collect();
+        assert_eq!(names[..frozen.len()], frozen[..]);
+        assert_eq!(KEY_FIRST, 2);
+
+        // Every key without data has exactly one code.
+        for key in Key::iter() {
+            let count = KEYS.iter().filter(|&&other| other == key).count();
+            let expected = !matches!(key, Key::Unknown(_) | Key::RawKey(_)) as usize;
+            assert_eq!(count, expected, "{:?}", key);
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! Recording input to a file and playing it back.
+//!
+//! A [`Recorder`] listens for events and keeps them along with when they happened. The resulting
+//! [`Recording`] is saved as versioned JSON, or in the compact [`binary`] format, and replayed
+//! with the original timing by a [`Player`].
+//!
+//! ```no_run
+//! use rdevin::record::{Player, Recorder, Recording};
+//! use std::{thread, time::Duration};
+//!
+//! let recorder = Recorder::start()?;
+//! thread::sleep(Duration::from_secs(5));
+//! recorder.stop().save("recording.json")?;
+//!
+//! let recording = Recording::load("recording.json")?;
+//! Player::new(recording).speed(2.0).play().join()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Requires the `record` feature.
+
+use crate::rdevin::{Event, EventType, SimulateError};
+use crate::{ListenError, Listener, Simulator};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+
+pub mod binary;
+
+/// The version of the recording format written by this crate. Recordings of a later version are
+/// refused, since they may hold events this one doesn't know.
+///
+/// Version 2 added relative motion, 3 fractional wheel deltas with their unit, 4 the back, forward
+/// and side buttons, and 5 touches and pens.
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Identifies recording files.
+const FORMAT_NAME: &str = "rdevin-recording";
+
+/// Errors that occur when saving or loading a recording.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RecordError {
+    #[error("IO error")]
+    Io(#[from] io::Error),
+    #[error("Invalid recording")]
+    Json(#[from] serde_json::Error),
+    #[error("Not a recording")]
+    NotARecording,
+    #[error("Unsupported recording version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid binary recording: bad {0}")]
+    InvalidBinary(&'static str),
+}
+
+/// An event and when it happened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Time since the recording started.
+    pub offset: Duration,
+    pub event: Event,
+}
+
+/// Recorded events, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct RecordingFileRef<'a> {
+    format: &'static str,
+    version: u32,
+    events: &'a [RecordedEvent],
+}
+
+#[derive(Deserialize)]
+struct RecordingFile {
+    events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    /// Times events from [`Event::time`], relative to `start`. Events from before `start` are
+    /// placed at its beginning.
+    pub fn from_events<I>(start: SystemTime, events: I) -> Recording
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        let mut offset = Duration::ZERO;
+        let events = events
+            .into_iter()
+            .map(|event| {
+                // The clock may go backwards, but playing back can't.
+                offset = offset.max(event.time.duration_since(start).unwrap_or_default());
+                RecordedEvent { offset, event }
+            })
+            .collect();
+        Recording { events }
+    }
+
+    /// How long playing back takes at normal speed.
+    pub fn duration(&self) -> Duration {
+        self.events.last().map(|e| e.offset).unwrap_or_default()
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), RecordError> {
+        serde_json::to_writer(
+            writer,
+            &RecordingFileRef {
+                format: FORMAT_NAME,
+                version: FORMAT_VERSION,
+                events: &self.events,
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Recording, RecordError> {
+        // The header is checked first, so that newer recordings are refused for their version
+        // rather than for whatever changed in them.
+        let mut json = Vec::new();
+        reader.read_to_end(&mut json)?;
+        let header: Header =
+            serde_json::from_slice(&json).map_err(|_| RecordError::NotARecording)?;
+        if header.format != FORMAT_NAME {
+            return Err(RecordError::NotARecording);
+        }
+        if header.version > FORMAT_VERSION {
+            return Err(RecordError::UnsupportedVersion(header.version));
+        }
+        let file: RecordingFile = serde_json::from_slice(&json)?;
+        Ok(Recording {
+            events: file.events,
+        })
+    }
+
+    /// Like [`write`](Recording::write), in the [`binary`] format.
+    pub fn write_binary<W: Write>(&self, writer: W) -> Result<(), RecordError> {
+        let mut writer = binary::BinaryWriter::new(writer)?;
+        for event in &self.events {
+            writer.write(event)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`read`](Recording::read), in the [`binary`] format.
+    pub fn read_binary<R: Read>(reader: R) -> Result<Recording, RecordError> {
+        Ok(Recording {
+            events: binary::BinaryReader::new(reader)?.collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), RecordError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Recording, RecordError> {
+        Recording::read(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Records events until stopped.
+#[derive(Debug)]
+#[must_use = "recording stops when the recorder is dropped"]
+pub struct Recorder {
+    start: SystemTime,
+    events: Arc<Mutex<Vec<Event>>>,
+    handle: crate::ListenHandle,
+}
+
+impl Recorder {
+    /// Starts recording through [`listen`](crate::listen).
+    pub fn start() -> Result<Recorder, ListenError> {
+        Recorder::start_with(crate::listen)
+    }
+
+    /// Starts recording through `listener`'s backend.
+    pub fn start_with_listener(listener: &Listener) -> Result<Recorder, ListenError> {
+        Recorder::start_with(|callback| listener.listen(callback))
+    }
+
+    fn start_with<L>(listen: L) -> Result<Recorder, ListenError>
+    where
+        L: FnOnce(crate::channel::Callback) -> Result<crate::ListenHandle, ListenError>,
+    {
+        let start = SystemTime::now();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handle = {
+            let events = events.clone();
+            listen(Box::new(move |event| events.lock().unwrap().push(event)))?
+        };
+        Ok(Recorder {
+            start,
+            events,
+            handle,
+        })
+    }
+
+    /// How many events were recorded so far.
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stop(mut self) -> Recording {
+        self.handle.stop();
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        Recording::from_events(self.start, events)
+    }
+}
+
+/// How many times a [`Player`] plays its recording.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Repeat {
+    Times(u32),
+    Forever,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Repeat::Times(1)
+    }
+}
+
+/// Plays a [`Recording`] back through [`simulate`](crate::simulate).
+#[derive(Debug, Clone)]
+pub struct Player {
+    recording: Recording,
+    speed: f64,
+    repeat: Repeat,
+    simulator: Option<Simulator>,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Player {
+        Player {
+            recording,
+            speed: 1.0,
+            repeat: Repeat::default(),
+            simulator: None,
+        }
+    }
+
+    /// Plays `speed` times faster than recorded.
+    ///
+    /// # Panics
+    ///
+    /// If `speed` isn't a positive number.
+    pub fn speed(mut self, speed: f64) -> Self {
+        assert!(
+            speed > 0.0 && speed.is_finite(),
+            "speed must be positive, got {}",
+            speed
+        );
+        self.speed = speed;
+        self
+    }
+
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Simulates through `simulator`'s backend.
+    pub fn simulator(mut self, simulator: Simulator) -> Self {
+        self.simulator = Some(simulator);
+        self
+    }
+
+    /// Starts playing on a new thread.
+    pub fn play(self) -> Playback {
+        let (cancel, cancelled) = channel();
+        let thread = thread::spawn(move || {
+            let simulator = self.simulator.clone();
+            self.run(&cancelled, |event_type| match &simulator {
+                Some(simulator) => simulator.simulate(event_type),
+                None => crate::simulate(event_type),
+            })
+        });
+        Playback {
+            cancel: Some(cancel),
+            thread: Some(thread),
+        }
+    }
+
+    /// Simulates every event at its time, until done or cancelled.
+    fn run<S>(&self, cancelled: &Receiver<()>, mut simulate: S) -> Result<(), SimulateError>
+    where
+        S: FnMut(&EventType) -> Result<(), SimulateError>,
+    {
+        let mut played = 0;
+        while self.repeat == Repeat::Forever || Repeat::Times(played) != self.repeat {
+            let start = Instant::now();
+            for recorded in &self.recording.events {
+                let deadline = start + recorded.offset.div_f64(self.speed);
+                let wait = deadline.saturating_duration_since(Instant::now());
+                match cancelled.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return Ok(()),
+                }
+                simulate(&recorded.event.event_type)?;
+            }
+            played += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A recording being played back.
+#[derive(Debug)]
+#[must_use = "playing stops when the handle is dropped"]
+pub struct Playback {
+    cancel: Option<Sender<()>>,
+    thread: Option<JoinHandle<Result<(), SimulateError>>>,
+}
+
+impl Playback {
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Stops playing, before the next event.
+    pub fn cancel(&mut self) {
+        self.cancel.take();
+    }
+
+    /// Waits until playing is done.
+    pub fn join(mut self) -> Result<(), SimulateError> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, WheelUnit};
+
+    fn recording() -> Recording {
+        let start = SystemTime::UNIX_EPOCH;
+        Recording::from_events(
+            start,
+            vec![
+                Event::for_test(
+                    EventType::KeyPress(Key::KeyA),
+                    start + Duration::from_millis(10),
+                ),
+                // The clock went backwards.
+                Event::for_test(EventType::KeyRelease(Key::KeyA), start),
+                Event::for_test(
+                    EventType::MouseMove { x: 1.0, y: 2.0 },
+                    start + Duration::from_millis(30),
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_offsets() {
+        let offsets: Vec<_> = recording().events.iter().map(|e| e.offset).collect();
+        assert_eq!(
+            offsets,
+            [
+                Duration::from_millis(10),
+                Duration::from_millis(10),
+                Duration::from_millis(30)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let recording = recording();
+        let mut file = Vec::new();
+        recording.write(&mut file).unwrap();
+        assert_eq!(Recording::read(&file[..]).unwrap(), recording);
+    }
+
+    #[test]
+    fn test_versions() {
+        let newer = format!(
+            r#"{{"format":"{}","version":{},"events":[]}}"#,
+            FORMAT_NAME,
+            FORMAT_VERSION + 1
+        );
+        assert!(matches!(
+            Recording::read(newer.as_bytes()),
+            Err(RecordError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+        // Older recordings are still read.
+        let mut file = Vec::new();
+        recording().write(&mut file).unwrap();
+        let older = String::from_utf8(file).unwrap().replace(
+            &format!(r#""version":{}"#, FORMAT_VERSION),
+            r#""version":1"#,
+        );
+        assert_eq!(Recording::read(older.as_bytes()).unwrap(), recording());
+        // Before version 3, wheel deltas were whole notches with no unit.
+        let mut wheel: serde_json::Value = serde_json::from_str(&older).unwrap();
+        wheel["version"] = 2.into();
+        wheel["events"][0]["event"]["event_type"] =
+            serde_json::json!({"Wheel": {"delta_x": 0, "delta_y": -1}});
+        let wheel = wheel.to_string();
+        assert_eq!(
+            Recording::read(wheel.as_bytes()).unwrap().events[0]
+                .event
+                .event_type,
+            EventType::Wheel {
+                delta_x: 0.0,
+                delta_y: -1.0,
+                unit: WheelUnit::Lines,
+            }
+        );
+        assert!(matches!(
+            Recording::read(&b"[1, 2]"[..]),
+            Err(RecordError::NotARecording)
+        ));
+    }
+
+    #[test]
+    fn test_play() {
+        let (_cancel, cancelled) = channel();
+        let player = Player::new(recording()).speed(0.5).repeat(Repeat::Times(2));
+        let start = Instant::now();
+        let mut played = Vec::new();
+        player
+            .run(&cancelled, |event_type| {
+                played.push(*event_type);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(played.len(), 6);
+        assert_eq!(played[3], EventType::KeyPress(Key::KeyA));
+        // Twice 30ms, at half speed.
+        assert!(start.elapsed() >= Duration::from_millis(120));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (cancel, cancelled) = channel();
+        drop(cancel);
+        let mut played = 0;
+        Player::new(recording())
+            .repeat(Repeat::Forever)
+            .run(&cancelled, |_| {
+                played += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(played, 0);
+    }
+}
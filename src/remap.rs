@@ -0,0 +1,598 @@
+//! Declarative key remapping on top of [`grab`](crate::grab).
+//!
+//! A [`Remapper`] rewrites keys according to a [`RemapConfig`]: plain key-to-key maps, layers
+//! that remap further while a key is held or after a key toggles them, and dual-role keys that
+//! do one thing when tapped and another when held.
+//!
+//! ```no_run
+//! use rdevin::remap::{Action, RemapConfig, Remapper};
+//! use rdevin::Key;
+//!
+//! let mut config = RemapConfig::default();
+//! // CapsLock is Escape when tapped and Ctrl when held.
+//! config.keys.insert(
+//!     Key::CapsLock,
+//!     Action::TapHold {
+//!         tap: Box::new(Action::Key(Key::Escape)),
+//!         hold: Box::new(Action::Key(Key::ControlLeft)),
+//!         timeout_ms: None,
+//!     },
+//! );
+//! // Holding Space turns HJKL into arrows.
+//! config.keys.insert(
+//!     Key::Space,
+//!     Action::TapHold {
+//!         tap: Box::new(Action::Key(Key::Space)),
+//!         hold: Box::new(Action::HoldLayer("nav".into())),
+//!         timeout_ms: None,
+//!     },
+//! );
+//! config.layers.insert(
+//!     "nav".into(),
+//!     vec![
+//!         (Key::KeyH, Action::Key(Key::LeftArrow)),
+//!         (Key::KeyJ, Action::Key(Key::DownArrow)),
+//!         (Key::KeyK, Action::Key(Key::UpArrow)),
+//!         (Key::KeyL, Action::Key(Key::RightArrow)),
+//!     ]
+//!     .into_iter()
+//!     .collect(),
+//! );
+//! Remapper::new(config)?.grab()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! With the `serde` feature, the configuration can be loaded from a file instead, with keys
+//! written by their [`Key`] names:
+//!
+//! ```json
+//! {
+//!     "keys": {
+//!         "CapsLock": { "TapHold": { "tap": { "Key": "Escape" }, "hold": { "Key": "ControlLeft" } } },
+//!         "Space": { "TapHold": { "tap": { "Key": "Space" }, "hold": { "HoldLayer": "nav" } } }
+//!     },
+//!     "layers": {
+//!         "nav": { "KeyH": { "Key": "LeftArrow" }, "KeyL": { "Key": "RightArrow" } }
+//!     },
+//!     "tap_timeout_ms": 200
+//! }
+//! ```
+
+use crate::rdevin::{Event, EventType, GrabError, Key};
+use crate::GrabAction;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+/// How long a dual-role key has to be held to count as held, by default.
+pub const DEFAULT_TAP_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// What a key does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Action {
+    /// Acts as another key.
+    Key(Key),
+    /// Activates a layer while the key is down.
+    HoldLayer(String),
+    /// Turns a layer on or off.
+    ToggleLayer(String),
+    /// Does `tap` when released within the timeout, and `hold` once another key or a mouse
+    /// button is pressed first, or the timeout passes. Neither can be another `TapHold`.
+    TapHold {
+        tap: Box<Action>,
+        hold: Box<Action>,
+        /// Overrides [`RemapConfig::tap_timeout_ms`] for this key.
+        timeout_ms: Option<u64>,
+    },
+}
+
+/// What a [`Remapper`] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RemapConfig {
+    /// The base layer, always active. Keys it leaves out stay as they are.
+    pub keys: HashMap<Key, Action>,
+    /// Layers by name. An active layer takes precedence over the base and the layers activated
+    /// before it; keys it leaves out fall through.
+    pub layers: HashMap<String, HashMap<Key, Action>>,
+    /// How long a dual-role key has to be held to count as held, in milliseconds.
+    pub tap_timeout_ms: u64,
+}
+
+impl Default for RemapConfig {
+    fn default() -> Self {
+        RemapConfig {
+            keys: HashMap::new(),
+            layers: HashMap::new(),
+            tap_timeout_ms: DEFAULT_TAP_TIMEOUT.as_millis() as u64,
+        }
+    }
+}
+
+/// Errors in a [`RemapConfig`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RemapError {
+    #[error("Unknown layer `{0}`")]
+    UnknownLayer(String),
+    #[error("The tap-hold action of {0:?} nests another")]
+    NestedTapHold(Key),
+}
+
+/// An [`Action`] with its layer looked up.
+#[derive(Debug, Clone)]
+enum Resolved {
+    Key(Key),
+    HoldLayer(usize),
+    ToggleLayer(usize),
+    TapHold {
+        tap: Box<Resolved>,
+        hold: Box<Resolved>,
+        timeout: Duration,
+    },
+}
+
+/// What a key that is down has to undo when released.
+#[derive(Debug, Clone, Copy)]
+enum Held {
+    Key(Key),
+    Layer(usize),
+    Nothing,
+}
+
+/// A dual-role key that is down but not yet decided.
+#[derive(Debug, Clone)]
+struct Undecided {
+    key: Key,
+    tap: Resolved,
+    hold: Resolved,
+    timeout: Duration,
+    since: SystemTime,
+}
+
+/// Rewrites key events. See the [module documentation](self).
+pub struct Remapper {
+    base: HashMap<Key, Resolved>,
+    layers: Vec<HashMap<Key, Resolved>>,
+    /// Active layers, the latest last.
+    active: Vec<usize>,
+    /// Keys that are down, and what they did.
+    held: HashMap<Key, Held>,
+    undecided: Option<Undecided>,
+}
+
+impl Remapper {
+    pub fn new(config: RemapConfig) -> Result<Remapper, RemapError> {
+        let mut names: Vec<&String> = config.layers.keys().collect();
+        names.sort();
+        let indices: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.as_str(), index))
+            .collect();
+        let timeout = Duration::from_millis(config.tap_timeout_ms);
+        let resolve_layer = |keys: &HashMap<Key, Action>| {
+            keys.iter()
+                .map(|(&key, action)| Ok((key, resolve(key, action, &indices, timeout, true)?)))
+                .collect::<Result<HashMap<_, _>, RemapError>>()
+        };
+        Ok(Remapper {
+            base: resolve_layer(&config.keys)?,
+            layers: names
+                .iter()
+                .map(|name| resolve_layer(&config.layers[*name]))
+                .collect::<Result<_, _>>()?,
+            active: Vec::new(),
+            held: HashMap::new(),
+            undecided: None,
+        })
+    }
+
+    /// Rewrites an event, returning what [`grab`](crate::grab) should do with it.
+    pub fn handle(&mut self, event: &Event) -> GrabAction {
+        let event_type = event.event_type;
+        let mut events = Vec::new();
+        match event_type {
+            EventType::KeyPress(key) => {
+                if let Some(undecided) = self.undecided.take() {
+                    if undecided.key == key {
+                        // Autorepeat
+                        if elapsed(undecided.since, event.time) < undecided.timeout {
+                            self.undecided = Some(undecided);
+                        } else {
+                            self.begin(key, &undecided.hold, &mut events);
+                        }
+                        return finish(event_type, events);
+                    }
+                    self.begin(undecided.key, &undecided.hold, &mut events);
+                }
+                match self.held.get(&key) {
+                    Some(Held::Key(target)) => events.push(EventType::KeyPress(*target)),
+                    Some(_) => {}
+                    None => match self.lookup(key) {
+                        Resolved::TapHold { tap, hold, timeout } => {
+                            self.undecided = Some(Undecided {
+                                key,
+                                tap: *tap,
+                                hold: *hold,
+                                timeout,
+                                since: event.time,
+                            });
+                        }
+                        action => self.begin(key, &action, &mut events),
+                    },
+                }
+            }
+            EventType::KeyRelease(key) => match self.undecided.take() {
+                Some(undecided) if undecided.key == key => {
+                    let action = if elapsed(undecided.since, event.time) < undecided.timeout {
+                        &undecided.tap
+                    } else {
+                        &undecided.hold
+                    };
+                    self.begin(key, action, &mut events);
+                    self.end(key, &mut events);
+                }
+                undecided => {
+                    self.undecided = undecided;
+                    if self.held.contains_key(&key) {
+                        self.end(key, &mut events);
+                    } else {
+                        // Pressed before the remapper started.
+                        events.push(event_type);
+                    }
+                }
+            },
+            EventType::ButtonPress(_) => {
+                if let Some(undecided) = self.undecided.take() {
+                    self.begin(undecided.key, &undecided.hold, &mut events);
+                }
+                events.push(event_type);
+            }
+            _ => events.push(event_type),
+        }
+        finish(event_type, events)
+    }
+
+    /// Rewrites [`grab`](crate::grab) events.
+    pub fn grab(mut self) -> Result<(), GrabError> {
+        crate::grab(move |event| self.handle(&event))
+    }
+
+    /// What `key` does in the active layers.
+    fn lookup(&self, key: Key) -> Resolved {
+        self.active
+            .iter()
+            .rev()
+            .map(|&layer| &self.layers[layer])
+            .chain(std::iter::once(&self.base))
+            .find_map(|layer| layer.get(&key))
+            .cloned()
+            .unwrap_or(Resolved::Key(key))
+    }
+
+    /// Starts what `key` does when pressed.
+    fn begin(&mut self, key: Key, action: &Resolved, events: &mut Vec<EventType>) {
+        let held = match *action {
+            Resolved::Key(target) => {
+                events.push(EventType::KeyPress(target));
+                Held::Key(target)
+            }
+            Resolved::HoldLayer(layer) => {
+                self.active.push(layer);
+                Held::Layer(layer)
+            }
+            Resolved::ToggleLayer(layer) => {
+                if self.active.contains(&layer) {
+                    self.active.retain(|&active| active != layer);
+                } else {
+                    self.active.push(layer);
+                }
+                Held::Nothing
+            }
+            // Ruled out by `resolve`.
+            Resolved::TapHold { .. } => Held::Nothing,
+        };
+        self.held.insert(key, held);
+    }
+
+    /// Undoes what `key` did when pressed.
+    fn end(&mut self, key: Key, events: &mut Vec<EventType>) {
+        match self.held.remove(&key) {
+            Some(Held::Key(target)) => events.push(EventType::KeyRelease(target)),
+            Some(Held::Layer(layer)) => {
+                if let Some(index) = self.active.iter().rposition(|&active| active == layer) {
+                    self.active.remove(index);
+                }
+            }
+            Some(Held::Nothing) | None => {}
+        }
+    }
+}
+
+fn resolve(
+    key: Key,
+    action: &Action,
+    layers: &HashMap<&str, usize>,
+    timeout: Duration,
+    nest: bool,
+) -> Result<Resolved, RemapError> {
+    let layer = |name: &String| {
+        layers
+            .get(name.as_str())
+            .copied()
+            .ok_or_else(|| RemapError::UnknownLayer(name.clone()))
+    };
+    Ok(match action {
+        Action::Key(target) => Resolved::Key(*target),
+        Action::HoldLayer(name) => Resolved::HoldLayer(layer(name)?),
+        Action::ToggleLayer(name) => Resolved::ToggleLayer(layer(name)?),
+        Action::TapHold { .. } if !nest => return Err(RemapError::NestedTapHold(key)),
+        Action::TapHold {
+            tap,
+            hold,
+            timeout_ms,
+        } => Resolved::TapHold {
+            tap: Box::new(resolve(key, tap, layers, timeout, false)?),
+            hold: Box::new(resolve(key, hold, layers, timeout, false)?),
+            timeout: timeout_ms.map_or(timeout, Duration::from_millis),
+        },
+    })
+}
+
+fn elapsed(since: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(since).unwrap_or_default()
+}
+
+/// Lets `event_type` through untouched if that's all there is to send.
+fn finish(event_type: EventType, events: Vec<EventType>) -> GrabAction {
+    match events.as_slice() {
+        [] => GrabAction::Drop,
+        [only] if *only == event_type => GrabAction::Pass,
+        _ => GrabAction::Replace(events),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdevin::{feed, Button};
+    use Key::*;
+
+    fn tap_hold(tap: Action, hold: Action) -> Action {
+        Action::TapHold {
+            tap: Box::new(tap),
+            hold: Box::new(hold),
+            timeout_ms: None,
+        }
+    }
+
+    fn remapper() -> Remapper {
+        let mut config = RemapConfig::default();
+        config.keys.insert(KeyA, Action::Key(KeyB));
+        config.keys.insert(
+            CapsLock,
+            tap_hold(Action::Key(Escape), Action::Key(ControlLeft)),
+        );
+        config.keys.insert(Tab, Action::HoldLayer("nav".into()));
+        config.keys.insert(F1, Action::ToggleLayer("nav".into()));
+        config.layers.insert(
+            "nav".into(),
+            vec![(KeyH, Action::Key(LeftArrow))].into_iter().collect(),
+        );
+        Remapper::new(config).unwrap()
+    }
+
+    #[test]
+    fn test_key() {
+        let mut remapper = remapper();
+        assert_eq!(
+            feed(
+                &[
+                    (EventType::KeyPress(KeyA), 0),
+                    (EventType::KeyPress(KeyA), 50),
+                    (EventType::KeyRelease(KeyA), 100),
+                    (EventType::KeyPress(KeyC), 150),
+                    (EventType::KeyRelease(KeyC), 200),
+                    // Pressed before the remapper started
+                    (EventType::KeyRelease(KeyD), 250),
+                ],
+                |event| remapper.handle(event)
+            ),
+            [
+                GrabAction::Replace(vec![EventType::KeyPress(KeyB)]),
+                GrabAction::Replace(vec![EventType::KeyPress(KeyB)]),
+                GrabAction::Replace(vec![EventType::KeyRelease(KeyB)]),
+                GrabAction::Pass,
+                GrabAction::Pass,
+                GrabAction::Pass,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tap_hold() {
+        let mut remapper = remapper();
+        // Tap
+        assert_eq!(
+            feed(
+                &[
+                    (EventType::KeyPress(CapsLock), 0),
+                    (EventType::KeyRelease(CapsLock), 100),
+                ],
+                |event| remapper.handle(event)
+            ),
+            [
+                GrabAction::Drop,
+                GrabAction::Replace(vec![
+                    EventType::KeyPress(Escape),
+                    EventType::KeyRelease(Escape)
+                ]),
+            ]
+        );
+        // Held while another key is pressed
+        assert_eq!(
+            feed(
+                &[
+                    (EventType::KeyPress(CapsLock), 1000),
+                    (EventType::KeyPress(KeyC), 1050),
+                    (EventType::KeyRelease(KeyC), 1100),
+                    (EventType::KeyRelease(CapsLock), 1150),
+                ],
+                |event| remapper.handle(event)
+            ),
+            [
+                GrabAction::Drop,
+                GrabAction::Replace(vec![
+                    EventType::KeyPress(ControlLeft),
+                    EventType::KeyPress(KeyC)
+                ]),
+                GrabAction::Pass,
+                GrabAction::Replace(vec![EventType::KeyRelease(ControlLeft)]),
+            ]
+        );
+        // Held past the timeout, noticed on autorepeat
+        assert_eq!(
+            feed(
+                &[
+                    (EventType::KeyPress(CapsLock), 2000),
+                    (EventType::KeyPress(CapsLock), 2100),
+                    (EventType::KeyPress(CapsLock), 2300),
+                    (EventType::ButtonPress(Button::Left), 2400),
+                    (EventType::KeyRelease(CapsLock), 2500),
+                ],
+                |event| remapper.handle(event)
+            ),
+            [
+                GrabAction::Drop,
+                GrabAction::Drop,
+                GrabAction::Replace(vec![EventType::KeyPress(ControlLeft)]),
+                GrabAction::Pass,
+                GrabAction::Replace(vec![EventType::KeyRelease(ControlLeft)]),
+            ]
+        );
+        // Clicked while undecided
+        assert_eq!(
+            feed(
+                &[
+                    (EventType::KeyPress(CapsLock), 3000),
+                    (EventType::ButtonPress(Button::Left), 3050),
+                    (EventType::KeyRelease(CapsLock), 3100),
+                ],
+                |event| remapper.handle(event)
+            ),
+            [
+                GrabAction::Drop,
+                GrabAction::Replace(vec![
+                    EventType::KeyPress(ControlLeft),
+                    EventType::ButtonPress(Button::Left)
+                ]),
+                GrabAction::Replace(vec![EventType::KeyRelease(ControlLeft)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut remapper = remapper();
+        assert_eq!(
+            feed(
+                &[
+                    (EventType::KeyPress(Tab), 0),
+                    (EventType::KeyPress(KeyH), 50),
+                    (EventType::KeyRelease(Tab), 100),
+                    // Released as it was pressed, whatever the layers
+                    (EventType::KeyRelease(KeyH), 150),
+                    (EventType::KeyPress(KeyH), 200),
+                    (EventType::KeyRelease(KeyH), 250),
+                ],
+                |event| remapper.handle(event)
+            ),
+            [
+                GrabAction::Drop,
+                GrabAction::Replace(vec![EventType::KeyPress(LeftArrow)]),
+                GrabAction::Drop,
+                GrabAction::Replace(vec![EventType::KeyRelease(LeftArrow)]),
+                GrabAction::Pass,
+                GrabAction::Pass,
+            ]
+        );
+
+        let toggle = [
+            (EventType::KeyPress(F1), 0),
+            (EventType::KeyRelease(F1), 50),
+            (EventType::KeyPress(KeyH), 100),
+            (EventType::KeyRelease(KeyH), 150),
+        ];
+        assert_eq!(
+            feed(&toggle, |event| remapper.handle(event))[2..],
+            [
+                GrabAction::Replace(vec![EventType::KeyPress(LeftArrow)]),
+                GrabAction::Replace(vec![EventType::KeyRelease(LeftArrow)]),
+            ]
+        );
+        assert_eq!(
+            feed(&toggle, |event| remapper.handle(event))[2..],
+            [GrabAction::Pass, GrabAction::Pass]
+        );
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let mut config = RemapConfig::default();
+        config.keys.insert(Tab, Action::HoldLayer("nav".into()));
+        assert_eq!(
+            Remapper::new(config.clone()).err(),
+            Some(RemapError::UnknownLayer("nav".into()))
+        );
+
+        config.layers.insert("nav".into(), HashMap::new());
+        config.keys.insert(
+            CapsLock,
+            tap_hold(
+                Action::Key(Escape),
+                tap_hold(Action::Key(Escape), Action::Key(ControlLeft)),
+            ),
+        );
+        assert_eq!(
+            Remapper::new(config).err(),
+            Some(RemapError::NestedTapHold(CapsLock))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize() {
+        let config: RemapConfig = serde_json::from_str(
+            r#"{
+                "keys": {
+                    "CapsLock": {
+                        "TapHold": {
+                            "tap": { "Key": "Escape" },
+                            "hold": { "Key": "ControlLeft" },
+                            "timeout_ms": 150
+                        }
+                    },
+                    "Space": { "HoldLayer": "nav" }
+                },
+                "layers": { "nav": { "KeyH": { "Key": "LeftArrow" } } }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.tap_timeout_ms, 200);
+        assert_eq!(
+            config.keys[&CapsLock],
+            Action::TapHold {
+                tap: Box::new(Action::Key(Escape)),
+                hold: Box::new(Action::Key(ControlLeft)),
+                timeout_ms: Some(150),
+            }
+        );
+        assert_eq!(config.layers["nav"][&KeyH], Action::Key(LeftArrow));
+        assert!(Remapper::new(config).is_ok());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::rdevin::{Event, EventType};
+
+/// What a [`grab`](crate::grab) callback wants done with an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrabAction {
+    /// Lets the event through, after any events still delayed.
+    Pass,
+    /// Keeps the event from applications. Delayed events stay delayed.
+    Drop,
+    /// Drops the event and any delayed ones, and sends these instead. The grab doesn't see them.
+    Replace(Vec<EventType>),
+    /// Holds the event back until a later event is passed, which lets it through first, or
+    /// replaced, which discards it.
+    ///
+    /// Touch and pen events can't be sent again later, so they aren't held back: delaying one
+    /// passes it, and passing one leaves the delayed events waiting.
+    Delay,
+}
+
+/// What a platform grab does with an event once its [`GrabAction`] is resolved.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Outcome {
+    Pass,
+    Drop,
+    /// Drop the event and inject these, without running the callback on them.
+    Inject(Vec<EventType>),
+}
+
+/// The events held back by [`GrabAction::Delay`].
+#[derive(Debug, Default)]
+struct Delayed {
+    events: Vec<EventType>,
+}
+
+impl Delayed {
+    fn resolve(&mut self, event_type: EventType, action: GrabAction) -> Outcome {
+        match action {
+            GrabAction::Pass | GrabAction::Delay if !can_inject(&event_type) => Outcome::Pass,
+            GrabAction::Pass if self.events.is_empty() => Outcome::Pass,
+            GrabAction::Pass => {
+                // The event is re-sent after the delayed ones, to keep them in order.
+                let mut events = std::mem::take(&mut self.events);
+                events.push(event_type);
+                Outcome::Inject(events)
+            }
+            GrabAction::Drop => Outcome::Drop,
+            GrabAction::Replace(events) => {
+                self.events.clear();
+                Outcome::Inject(events)
+            }
+            GrabAction::Delay => {
+                self.events.push(event_type);
+                Outcome::Drop
+            }
+        }
+    }
+}
+
+/// Whether the simulators can send events of this type, so that they can be let through late.
+fn can_inject(event_type: &EventType) -> bool {
+    !matches!(
+        event_type,
+        EventType::TouchBegin { .. }
+            | EventType::TouchUpdate { .. }
+            | EventType::TouchEnd { .. }
+            | EventType::PenProximity { .. }
+            | EventType::PenMove { .. }
+            | EventType::PenButtonPress(_)
+            | EventType::PenButtonRelease(_)
+    )
+}
+
+/// Wraps a grab callback into one that keeps track of delayed events.
+pub(crate) fn rewriter<T>(mut callback: T) -> impl FnMut(Event) -> Outcome
+where
+    T: FnMut(Event) -> GrabAction,
+{
+    let mut delayed = Delayed::default();
+    move |event| {
+        let event_type = event.event_type;
+        delayed.resolve(event_type, callback(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdevin::Key;
+
+    #[test]
+    fn test_resolve() {
+        let a = EventType::KeyPress(Key::KeyA);
+        let b = EventType::KeyPress(Key::KeyB);
+        let c = EventType::KeyPress(Key::KeyC);
+        let mut delayed = Delayed::default();
+
+        assert_eq!(delayed.resolve(a, GrabAction::Pass), Outcome::Pass);
+        assert_eq!(delayed.resolve(a, GrabAction::Drop), Outcome::Drop);
+
+        assert_eq!(delayed.resolve(a, GrabAction::Delay), Outcome::Drop);
+        assert_eq!(delayed.resolve(b, GrabAction::Drop), Outcome::Drop);
+        assert_eq!(
+            delayed.resolve(c, GrabAction::Pass),
+            Outcome::Inject(vec![a, c])
+        );
+        assert_eq!(delayed.resolve(c, GrabAction::Pass), Outcome::Pass);
+
+        assert_eq!(delayed.resolve(a, GrabAction::Delay), Outcome::Drop);
+        assert_eq!(
+            delayed.resolve(b, GrabAction::Replace(vec![c])),
+            Outcome::Inject(vec![c])
+        );
+        assert_eq!(delayed.resolve(b, GrabAction::Pass), Outcome::Pass);
+
+        // Touches pass instead of waiting, and don't let delayed events through.
+        let touch = EventType::TouchEnd { slot: 0 };
+        assert_eq!(delayed.resolve(a, GrabAction::Delay), Outcome::Drop);
+        assert_eq!(delayed.resolve(touch, GrabAction::Delay), Outcome::Pass);
+        assert_eq!(delayed.resolve(touch, GrabAction::Pass), Outcome::Pass);
+        assert_eq!(delayed.resolve(touch, GrabAction::Drop), Outcome::Drop);
+        assert_eq!(
+            delayed.resolve(c, GrabAction::Pass),
+            Outcome::Inject(vec![a, c])
+        );
+    }
+}
@@ -0,0 +1,591 @@
+use crate::rdevin::{Button, EventType, Key};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+use thiserror::Error;
+
+const HOLD_START: &str = "<hold";
+const HOLD_END: &str = "</hold>";
+
+/// Names that keys also go by, besides their variant names.
+const ALIASES: [(&str, Key); 10] = [
+    ("Ctrl", Key::ControlLeft),
+    ("Control", Key::ControlLeft),
+    ("Shift", Key::ShiftLeft),
+    ("Meta", Key::MetaLeft),
+    ("Super", Key::MetaLeft),
+    ("Win", Key::MetaLeft),
+    ("Cmd", Key::MetaLeft),
+    ("Option", Key::Alt),
+    ("Enter", Key::Return),
+    ("Esc", Key::Escape),
+];
+
+lazy_static::lazy_static! {
+    static ref KEY_NAMES: HashMap<String, Key> = Key::iter()
+        .filter(|key| !matches!(key, Key::Unknown(_) | Key::RawKey(_)))
+        .map(|key| (format!("{:?}", key), key))
+        .chain(ALIASES.iter().map(|&(name, key)| (name.to_string(), key)))
+        .collect();
+}
+
+/// The key that types `chr` without modifiers on a US layout.
+pub(crate) fn key_from_char(chr: char) -> Option<Key> {
+    let key = match chr {
+        'a'..='z' => {
+            return KEY_NAMES
+                .get(&format!("Key{}", chr.to_ascii_uppercase()))
+                .copied()
+        }
+        '0'..='9' => return KEY_NAMES.get(&format!("Num{}", chr)).copied(),
+        ' ' => Key::Space,
+        '\t' => Key::Tab,
+        '\n' => Key::Return,
+        '-' => Key::Minus,
+        '=' => Key::Equal,
+        '[' => Key::LeftBracket,
+        ']' => Key::RightBracket,
+        ';' => Key::SemiColon,
+        '\'' => Key::Quote,
+        '\\' => Key::BackSlash,
+        ',' => Key::Comma,
+        '.' => Key::Dot,
+        '/' => Key::Slash,
+        '`' => Key::BackQuote,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// What is wrong with a [`ParseError`]'s token.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Error)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    #[error("Unknown key")]
+    UnknownKey,
+    #[error("Unknown mouse button")]
+    UnknownButton,
+    #[error("No key types this character without modifiers")]
+    UnknownCharacter,
+    #[error("Missing key")]
+    Empty,
+    #[error("Mouse buttons can only end a chord")]
+    ButtonModifier,
+    #[error("Unclosed hold")]
+    UnclosedHold,
+    #[error("Nothing to release")]
+    UnexpectedRelease,
+}
+
+/// Errors that occur when parsing keys, chords and sequences.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{kind}{} at byte {position}", if token.is_empty() { String::new() } else { format!(" `{token}`") })]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// The offending text.
+    pub token: String,
+    /// Where `token` starts in the parsed string, in bytes.
+    pub position: usize,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, token: &str, position: usize) -> ParseError {
+        ParseError {
+            kind,
+            token: token.to_string(),
+            position,
+        }
+    }
+}
+
+/// Keys are written as their variant names, like `KeyA` or `ControlLeft`. `Ctrl`, `Shift`, `Meta`
+/// and a few other aliases name the left variants, and unknown keys are written `Unknown(code)`.
+/// Raw keys can be displayed but not parsed.
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Key, ParseError> {
+        if let Some(&key) = KEY_NAMES.get(s) {
+            return Ok(key);
+        }
+        s.strip_prefix("Unknown(")
+            .and_then(|code| code.strip_suffix(')'))
+            .and_then(|code| code.parse().ok())
+            .map(Key::Unknown)
+            .ok_or_else(|| ParseError::new(ParseErrorKind::UnknownKey, s, 0))
+    }
+}
+
+/// Buttons are written `MouseLeft`, `MouseRight`, `MouseMiddle`, `MouseBack`, `MouseForward`,
+/// `MouseSide1` for `Button::Side(1)`, and `Mouse20` for `Button::Unknown(20)`.
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Button::Left => f.write_str("MouseLeft"),
+            Button::Right => f.write_str("MouseRight"),
+            Button::Middle => f.write_str("MouseMiddle"),
+            Button::Back => f.write_str("MouseBack"),
+            Button::Forward => f.write_str("MouseForward"),
+            Button::Side(n) => write!(f, "MouseSide{}", n),
+            Button::Unknown(code) => write!(f, "Mouse{}", code),
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Button, ParseError> {
+        match s.strip_prefix("Mouse") {
+            Some("Left") => Ok(Button::Left),
+            Some("Right") => Ok(Button::Right),
+            Some("Middle") => Ok(Button::Middle),
+            Some("Back") => Ok(Button::Back),
+            Some("Forward") => Ok(Button::Forward),
+            Some(side) if side.starts_with("Side") => side["Side".len()..]
+                .parse()
+                .map(Button::Side)
+                .map_err(|_| ParseError::new(ParseErrorKind::UnknownButton, s, 0)),
+            Some(code) if code.bytes().all(|b| b.is_ascii_digit()) => code
+                .parse()
+                .map(Button::Unknown)
+                .map_err(|_| ParseError::new(ParseErrorKind::UnknownButton, s, 0)),
+            _ => Err(ParseError::new(ParseErrorKind::UnknownButton, s, 0)),
+        }
+    }
+}
+
+/// A key or mouse button.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Key),
+    Button(Button),
+}
+
+impl Input {
+    pub fn press(self) -> EventType {
+        match self {
+            Input::Key(key) => EventType::KeyPress(key),
+            Input::Button(button) => EventType::ButtonPress(button),
+        }
+    }
+
+    pub fn release(self) -> EventType {
+        match self {
+            Input::Key(key) => EventType::KeyRelease(key),
+            Input::Button(button) => EventType::ButtonRelease(button),
+        }
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Key(key) => key.fmt(f),
+            Input::Button(button) => button.fmt(f),
+        }
+    }
+}
+
+/// A key or button name, or a character typed by a single key. Letters name their key in either
+/// case, so that `Ctrl+T` works.
+impl FromStr for Input {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Input, ParseError> {
+        if s.starts_with("Mouse") {
+            return s.parse().map(Input::Button);
+        }
+        let mut chars = s.chars();
+        if let (Some(chr), None) = (chars.next(), chars.next()) {
+            if let Some(key) = key_from_char(chr.to_ascii_lowercase()) {
+                return Ok(Input::Key(key));
+            }
+        }
+        s.parse().map(Input::Key)
+    }
+}
+
+/// Modifier keys held while a key or button is pressed, written like `Ctrl+Shift+KeyA`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: Vec<Key>,
+    pub input: Input,
+}
+
+impl Chord {
+    /// Presses the modifiers in order, presses and releases the input, then releases the
+    /// modifiers in reverse.
+    pub fn events(&self) -> Vec<EventType> {
+        let mut events = self.press();
+        events.extend(self.release());
+        events
+    }
+
+    fn press(&self) -> Vec<EventType> {
+        self.modifiers
+            .iter()
+            .map(|&modifier| EventType::KeyPress(modifier))
+            .chain(Some(self.input.press()))
+            .collect()
+    }
+
+    fn release(&self) -> Vec<EventType> {
+        Some(self.input.release())
+            .into_iter()
+            .chain(
+                self.modifiers
+                    .iter()
+                    .rev()
+                    .map(|&modifier| EventType::KeyRelease(modifier)),
+            )
+            .collect()
+    }
+
+    /// Parses `s`, which starts at byte `offset` of the whole string.
+    pub(crate) fn parse_at(s: &str, offset: usize) -> Result<Chord, ParseError> {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        for part in s.split('+') {
+            let trimmed = part.trim();
+            let position = offset + start + (part.len() - part.trim_start().len());
+            if trimmed.is_empty() {
+                return Err(ParseError::new(ParseErrorKind::Empty, "", position));
+            }
+            let input = trimmed.parse::<Input>().map_err(|mut e| {
+                e.position = position;
+                e
+            })?;
+            parts.push((input, trimmed, position));
+            start += part.len() + 1;
+        }
+        let (input, _, _) = parts.pop().expect("split yields at least one part");
+        let modifiers = parts
+            .into_iter()
+            .map(|(input, token, position)| match input {
+                Input::Key(key) => Ok(key),
+                Input::Button(_) => Err(ParseError::new(
+                    ParseErrorKind::ButtonModifier,
+                    token,
+                    position,
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Chord { modifiers, input })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier)?;
+        }
+        self.input.fmt(f)
+    }
+}
+
+impl FromStr for Chord {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Chord, ParseError> {
+        Chord::parse_at(s, 0)
+    }
+}
+
+/// Input events described by a string, to simulate or to match against.
+///
+/// A sequence is a comma-separated list of:
+///
+/// - chords, like `Ctrl+Shift+KeyA` or `Meta+Space`, which are pressed and released;
+/// - text, like `hello world`, typed one key per character. Only lowercase letters, digits,
+///   spaces and unshifted US punctuation have keys; write `Comma` for a comma;
+/// - holds, like `<hold Shift>abc</hold>`, which keep a chord pressed while a nested sequence is
+///   typed.
+///
+/// ```
+/// use rdevin::{EventType, Key, KeySequence};
+///
+/// let sequence: KeySequence = "Ctrl+KeyC, Escape".parse()?;
+/// assert_eq!(sequence.events[0], EventType::KeyPress(Key::ControlLeft));
+/// assert_eq!(sequence.events.len(), 6);
+///
+/// let error = "Ctrl+Shfit+KeyA".parse::<KeySequence>().unwrap_err();
+/// assert_eq!((error.token.as_str(), error.position), ("Shfit", 5));
+/// # Ok::<(), rdevin::ParseError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeySequence {
+    pub events: Vec<EventType>,
+}
+
+impl KeySequence {
+    /// Sends every event with [`simulate`](crate::simulate).
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    pub fn simulate(&self) -> Result<(), crate::SimulateError> {
+        self.events.iter().try_for_each(crate::simulate)
+    }
+}
+
+impl FromStr for KeySequence {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<KeySequence, ParseError> {
+        let mut parser = Parser {
+            input: s,
+            position: 0,
+            events: Vec::new(),
+        };
+        parser.sequence(None)?;
+        Ok(KeySequence {
+            events: parser.events,
+        })
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    events: Vec<EventType>,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Parses items until the end of the input, or the end of the hold that starts at `hold`.
+    fn sequence(&mut self, hold: Option<usize>) -> Result<(), ParseError> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.is_empty() {
+                return match hold {
+                    Some(start) => Err(ParseError::new(
+                        ParseErrorKind::UnclosedHold,
+                        HOLD_START,
+                        start,
+                    )),
+                    None => Ok(()),
+                };
+            }
+            if rest.starts_with(HOLD_END) {
+                if hold.is_none() {
+                    return Err(ParseError::new(
+                        ParseErrorKind::UnexpectedRelease,
+                        HOLD_END,
+                        self.position,
+                    ));
+                }
+                self.position += HOLD_END.len();
+                return Ok(());
+            }
+            if rest.starts_with(HOLD_START) {
+                self.hold()?;
+            } else if rest.starts_with(',') {
+                return Err(ParseError::new(ParseErrorKind::Empty, "", self.position));
+            } else {
+                self.item()?;
+            }
+            self.skip_whitespace();
+            if self.rest().starts_with(',') {
+                self.position += 1;
+                self.skip_whitespace();
+                let rest = self.rest();
+                if rest.is_empty() || rest.starts_with(HOLD_END) {
+                    return Err(ParseError::new(ParseErrorKind::Empty, "", self.position));
+                }
+            }
+        }
+    }
+
+    fn hold(&mut self) -> Result<(), ParseError> {
+        let start = self.position;
+        self.position += HOLD_START.len();
+        let Some(length) = self.rest().find('>') else {
+            return Err(ParseError::new(
+                ParseErrorKind::UnclosedHold,
+                HOLD_START,
+                start,
+            ));
+        };
+        let chord = Chord::parse_at(&self.rest()[..length], self.position)?;
+        self.position += length + 1;
+        self.events.extend(chord.press());
+        self.sequence(Some(start))?;
+        self.events.extend(chord.release());
+        Ok(())
+    }
+
+    /// A chord or text, which runs until the next comma or tag.
+    fn item(&mut self) -> Result<(), ParseError> {
+        let rest = &self.input[self.position..];
+        let length = rest.find([',', '<']).unwrap_or(rest.len());
+        let token = rest[..length].trim_end();
+        let position = self.position;
+        // A `<` that doesn't start a tag.
+        if token.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::UnknownCharacter,
+                "<",
+                position,
+            ));
+        }
+        self.position += length;
+
+        // Text has no uppercase letters, so those are misspelled key names.
+        if token.contains('+') || token.starts_with(|c: char| c.is_ascii_uppercase()) {
+            self.events
+                .extend(Chord::parse_at(token, position)?.events());
+            return Ok(());
+        }
+        if let Ok(input) = token.parse::<Input>() {
+            self.events.push(input.press());
+            self.events.push(input.release());
+            return Ok(());
+        }
+        for (index, chr) in token.char_indices() {
+            let key = key_from_char(chr).ok_or_else(|| {
+                ParseError::new(
+                    ParseErrorKind::UnknownCharacter,
+                    &chr.to_string(),
+                    position + index,
+                )
+            })?;
+            self.events.push(EventType::KeyPress(key));
+            self.events.push(EventType::KeyRelease(key));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EventType::{KeyPress as P, KeyRelease as R};
+
+    #[test]
+    fn test_key_names() {
+        for key in Key::iter().filter(|key| !matches!(key, Key::RawKey(_))) {
+            assert_eq!(key.to_string().parse::<Key>(), Ok(key));
+        }
+        assert_eq!("Ctrl".parse(), Ok(Key::ControlLeft));
+        assert_eq!("Unknown(42)".parse(), Ok(Key::Unknown(42)));
+        for button in [
+            Button::Left,
+            Button::Middle,
+            Button::Back,
+            Button::Forward,
+            Button::Side(3),
+            Button::Unknown(20),
+        ] {
+            assert_eq!(button.to_string().parse::<Button>(), Ok(button));
+        }
+        assert_eq!(
+            "keya".parse::<Key>().unwrap_err().kind,
+            ParseErrorKind::UnknownKey
+        );
+    }
+
+    #[test]
+    fn test_chords() {
+        let chord: Chord = "Ctrl + Shift+KeyA".parse().unwrap();
+        assert_eq!(chord.modifiers, [Key::ControlLeft, Key::ShiftLeft]);
+        assert_eq!(chord.input, Input::Key(Key::KeyA));
+        assert_eq!(chord.to_string(), "ControlLeft+ShiftLeft+KeyA");
+        assert_eq!(
+            "Alt+MouseLeft".parse::<Chord>().unwrap().events(),
+            [
+                P(Key::Alt),
+                EventType::ButtonPress(Button::Left),
+                EventType::ButtonRelease(Button::Left),
+                R(Key::Alt),
+            ]
+        );
+        assert_eq!(
+            "Ctrl+c".parse::<Chord>().unwrap().input,
+            Input::Key(Key::KeyC)
+        );
+        assert_eq!(
+            "Ctrl+T".parse::<Chord>().unwrap().input,
+            Input::Key(Key::KeyT)
+        );
+    }
+
+    #[test]
+    fn test_sequences() {
+        let sequence: KeySequence = "Meta+Space, Escape".parse().unwrap();
+        assert_eq!(
+            sequence.events,
+            [
+                P(Key::MetaLeft),
+                P(Key::Space),
+                R(Key::Space),
+                R(Key::MetaLeft),
+                P(Key::Escape),
+                R(Key::Escape),
+            ]
+        );
+        let sequence: KeySequence = "<hold Shift>ab</hold>, c d".parse().unwrap();
+        assert_eq!(
+            sequence.events,
+            [
+                P(Key::ShiftLeft),
+                P(Key::KeyA),
+                R(Key::KeyA),
+                P(Key::KeyB),
+                R(Key::KeyB),
+                R(Key::ShiftLeft),
+                P(Key::KeyC),
+                R(Key::KeyC),
+                P(Key::Space),
+                R(Key::Space),
+                P(Key::KeyD),
+                R(Key::KeyD),
+            ]
+        );
+        let nested: KeySequence = "<hold Alt><hold Shift>Tab</hold></hold>".parse().unwrap();
+        assert_eq!(nested.events.len(), 6);
+        assert_eq!("".parse(), Ok(KeySequence::default()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |s: &str| {
+            let e = s.parse::<KeySequence>().unwrap_err();
+            (e.kind, e.token, e.position)
+        };
+        use ParseErrorKind::*;
+        assert_eq!(error("Ctrl+Shfit+KeyA"), (UnknownKey, "Shfit".into(), 5));
+        assert_eq!(error("Escape, Tabb"), (UnknownKey, "Tabb".into(), 8));
+        assert_eq!(error("héllo"), (UnknownCharacter, "é".into(), 1));
+        assert_eq!(error("Ctrl+"), (Empty, "".into(), 5));
+        assert_eq!(error("a,,b"), (Empty, "".into(), 2));
+        assert_eq!(error("a, "), (Empty, "".into(), 3));
+        assert_eq!(
+            error("MouseLeft+KeyA"),
+            (ButtonModifier, "MouseLeft".into(), 0)
+        );
+        assert_eq!(error("x <hold Shift>ab"), (UnclosedHold, "<hold".into(), 2));
+        assert_eq!(error("<hold Shift ab"), (UnclosedHold, "<hold".into(), 0));
+        assert_eq!(error("ab</hold>"), (UnexpectedRelease, "</hold>".into(), 2));
+        assert_eq!(error("<"), (UnknownCharacter, "<".into(), 0));
+        assert_eq!(error("a<b"), (UnknownCharacter, "<".into(), 1));
+        assert_eq!(error("Ctrl+KeyA, <x"), (UnknownCharacter, "<".into(), 11));
+        assert_eq!(
+            "Ctrl+Shfit".parse::<KeySequence>().unwrap_err().to_string(),
+            "Unknown key `Shfit` at byte 5"
+        );
+    }
+}
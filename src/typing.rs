@@ -0,0 +1,107 @@
+use crate::rdevin::SimulateError;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use crate::linux::typing::Typist;
+#[cfg(target_os = "macos")]
+use crate::macos::typing::Typist;
+#[cfg(target_os = "windows")]
+use crate::windows::typing::Typist;
+
+/// How [`type_text`] types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingOptions {
+    /// How long to wait after each character. Some applications drop keys sent too quickly.
+    pub delay: Duration,
+    /// Whether characters the active layout can't produce are typed anyway, by temporarily
+    /// remapping a key on X11 or injecting the character on Windows and macOS. Without it they
+    /// are skipped, as they always are on uinput.
+    pub fallback: bool,
+}
+
+impl Default for TypingOptions {
+    fn default() -> Self {
+        TypingOptions {
+            delay: Duration::ZERO,
+            fallback: true,
+        }
+    }
+}
+
+/// What [`type_text`] couldn't type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypingReport {
+    /// The characters that were skipped, with their byte offsets in the text.
+    pub skipped: Vec<(usize, char)>,
+}
+
+impl TypingReport {
+    /// Whether every character was typed.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// Types `text` into whatever has keyboard focus.
+///
+/// Each character is typed with the key and modifiers that produce it on the active layout.
+/// Characters the layout lacks go through [`TypingOptions::fallback`], and anything left is
+/// listed in the returned report. Modifiers the user is holding still apply.
+///
+/// On Linux without an X server, and through a [`Simulator`](crate::Simulator) using
+/// [`Backend::Evdev`](crate::Backend::Evdev), characters are typed on a uinput keyboard instead.
+/// That path assumes a US layout whatever the real one is, and has no fallback: anything outside
+/// US ASCII is skipped.
+///
+/// ```no_run
+/// use rdevin::{type_text, TypingOptions};
+///
+/// let report = type_text("Hello, wörld! ✓", TypingOptions::default())?;
+/// if !report.is_complete() {
+///     println!("Couldn't type {:?}", report.skipped);
+/// }
+/// # Ok::<(), rdevin::SimulateError>(())
+/// ```
+pub fn type_text(text: &str, options: TypingOptions) -> Result<TypingReport, SimulateError> {
+    let mut typist = match Typist::new() {
+        Ok(typist) => typist,
+        #[cfg(target_os = "linux")]
+        Err(SimulateError::NoDisplay) => return type_evdev(text, &options),
+        Err(e) => return Err(e),
+    };
+    // The typist gives back what it borrowed from the keymap when dropped.
+    type_with(text, &options, |chr| {
+        typist.type_char(chr, options.fallback)
+    })
+}
+
+/// Types `text` on the uinput keyboard, as if on a US layout.
+#[cfg(target_os = "linux")]
+pub(crate) fn type_evdev(
+    text: &str,
+    options: &TypingOptions,
+) -> Result<TypingReport, SimulateError> {
+    type_with(text, options, crate::linux::evdev::type_char)
+}
+
+/// Types each character of `text` with `type_char`, which returns whether it could.
+fn type_with<F>(
+    text: &str,
+    options: &TypingOptions,
+    mut type_char: F,
+) -> Result<TypingReport, SimulateError>
+where
+    F: FnMut(char) -> Result<bool, SimulateError>,
+{
+    let mut report = TypingReport::default();
+    for (index, chr) in text.char_indices() {
+        if !type_char(chr)? {
+            report.skipped.push((index, chr));
+            continue;
+        }
+        if !options.delay.is_zero() {
+            std::thread::sleep(options.delay);
+        }
+    }
+    Ok(report)
+}
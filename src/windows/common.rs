@@ -1,21 +1,23 @@
-use crate::keycodes::windows::key_from_code;
-use crate::rdevin::{Button, EventType, Key};
+use crate::keycodes::windows::{button_from_code, key_from_code};
+use crate::rdevin::{Button, EventType, Key, ModifierState, Origin, WheelUnit};
 use crate::windows::keyboard::Keyboard;
 use lazy_static::lazy_static;
 use std::convert::TryInto;
 use std::os::raw::{c_int, c_short};
 use std::ptr::null_mut;
 use std::sync::Mutex;
+use winapi::shared::basetsd::ULONG_PTR;
 use winapi::shared::minwindef::{DWORD, HIWORD, LPARAM, LRESULT, WORD, WPARAM};
 use winapi::shared::ntdef::LONG;
 use winapi::shared::windef::HHOOK;
 use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::winuser::{
-    GetForegroundWindow, GetKeyboardLayout, GetWindowThreadProcessId, MapVirtualKeyExW,
-    SetWindowsHookExA, KBDLLHOOKSTRUCT, MAPVK_VK_TO_VSC_EX, MSLLHOOKSTRUCT, VK_PACKET, WHEEL_DELTA,
-    WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP,
-    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN,
-    WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP,
+    GetForegroundWindow, GetKeyState, GetKeyboardLayout, GetWindowThreadProcessId,
+    MapVirtualKeyExW, SetWindowsHookExA, KBDLLHOOKSTRUCT, LLKHF_INJECTED, LLMHF_INJECTED,
+    MAPVK_VK_TO_VSC_EX, MSLLHOOKSTRUCT, VK_CAPITAL, VK_NUMLOCK, VK_PACKET, WH_KEYBOARD_LL,
+    WH_MOUSE_LL, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP,
+    WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SYSKEYDOWN,
+    WM_SYSKEYUP, WM_XBUTTONDOWN, WM_XBUTTONUP,
 };
 #[allow(dead_code)]
 pub const TRUE: i32 = 1;
@@ -26,6 +28,62 @@ pub static mut KEYBOARD_HOOK: HHOOK = null_mut();
 pub static mut MOUSE_HOOK: HHOOK = null_mut();
 lazy_static! {
     pub(crate) static ref KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::default());
+    // The modifier state, and the lock keys held down, since autorepeat would toggle them again.
+    static ref MODIFIERS: Mutex<(ModifierState, ModifierState)> =
+        Mutex::new((lock_state(), ModifierState::empty()));
+}
+
+/// The extra info of a hooked keyboard event, and whether it was injected.
+pub unsafe fn keyboard_extra_info(lpdata: LPARAM) -> (ULONG_PTR, bool) {
+    let data = &*(lpdata as *const KBDLLHOOKSTRUCT);
+    (data.dwExtraInfo, data.flags & LLKHF_INJECTED != 0)
+}
+
+/// The extra info of a hooked mouse event, and whether it was injected.
+pub unsafe fn mouse_extra_info(lpdata: LPARAM) -> (ULONG_PTR, bool) {
+    let data = &*(lpdata as *const MSLLHOOKSTRUCT);
+    (data.dwExtraInfo, data.flags & LLMHF_INJECTED != 0)
+}
+
+pub fn origin(extra_info: ULONG_PTR, injected: bool) -> Origin {
+    if injected {
+        Origin::Synthetic {
+            tag: extra_info as u64,
+        }
+    } else {
+        Origin::Hardware
+    }
+}
+
+fn lock_state() -> ModifierState {
+    let mut modifiers = ModifierState::empty();
+    unsafe {
+        modifiers.set(ModifierState::CAPS_LOCK, GetKeyState(VK_CAPITAL) & 1 != 0);
+        modifiers.set(ModifierState::NUM_LOCK, GetKeyState(VK_NUMLOCK) & 1 != 0);
+    }
+    modifiers
+}
+
+/// Follows `event_type` and returns the modifier state once it happened.
+pub(crate) fn update_modifiers(event_type: &EventType) -> ModifierState {
+    let mut modifiers = MODIFIERS.lock().unwrap();
+    let (state, held_locks) = &mut *modifiers;
+    match *event_type {
+        EventType::KeyPress(key) => {
+            let flag = ModifierState::from_key(key);
+            let lock = flag & (ModifierState::CAPS_LOCK | ModifierState::NUM_LOCK);
+            if lock.is_empty() || !held_locks.contains(lock) {
+                state.update(event_type);
+            }
+            held_locks.insert(lock);
+        }
+        EventType::KeyRelease(key) => {
+            held_locks.remove(ModifierState::from_key(key));
+            state.update(event_type);
+        }
+        _ => {}
+    }
+    *state
 }
 
 pub fn set_modifier(key: Key, down: bool) {
@@ -98,12 +156,12 @@ pub unsafe fn convert(param: WPARAM, lpdata: LPARAM) -> (Option<EventType>, u16)
             Ok(WM_RBUTTONDOWN) => Some(EventType::ButtonPress(Button::Right)),
             Ok(WM_RBUTTONUP) => Some(EventType::ButtonRelease(Button::Right)),
             Ok(WM_XBUTTONDOWN) => {
-                let code = get_button_code(lpdata) as u8;
-                Some(EventType::ButtonPress(Button::Unknown(code)))
+                let button = button_from_code(get_button_code(lpdata));
+                Some(EventType::ButtonPress(button))
             }
             Ok(WM_XBUTTONUP) => {
-                let code = get_button_code(lpdata) as u8;
-                Some(EventType::ButtonRelease(Button::Unknown(code)))
+                let button = button_from_code(get_button_code(lpdata));
+                Some(EventType::ButtonRelease(button))
             }
             Ok(WM_MOUSEMOVE) => {
                 let (x, y) = get_point(lpdata);
@@ -115,15 +173,17 @@ pub unsafe fn convert(param: WPARAM, lpdata: LPARAM) -> (Option<EventType>, u16)
             Ok(WM_MOUSEWHEEL) => {
                 let delta = get_delta(lpdata) as c_short;
                 Some(EventType::Wheel {
-                    delta_x: 0,
-                    delta_y: (delta / WHEEL_DELTA) as i64,
+                    delta_x: 0.0,
+                    delta_y: delta.into(),
+                    unit: WheelUnit::HighRes,
                 })
             }
             Ok(WM_MOUSEHWHEEL) => {
                 let delta = get_delta(lpdata) as c_short;
                 Some(EventType::Wheel {
-                    delta_x: (delta / WHEEL_DELTA) as i64,
-                    delta_y: 0,
+                    delta_x: delta.into(),
+                    delta_y: 0.0,
+                    unit: WheelUnit::HighRes,
                 })
             }
             _ => None,
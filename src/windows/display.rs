@@ -1,9 +1,78 @@
-use crate::rdevin::DisplayError;
+use crate::rdevin::{DisplayError, DisplayInfo};
 use std::convert::TryInto;
-use winapi::um::winuser::{GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN};
+use std::mem::size_of;
+use std::ptr::null_mut;
+use winapi::shared::minwindef::{BOOL, LPARAM, TRUE};
+use winapi::shared::windef::{HDC, HMONITOR, LPRECT, POINT};
+use winapi::shared::winerror::S_OK;
+use winapi::um::shellscalingapi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
+use winapi::um::winuser::{
+    EnumDisplayMonitors, GetCursorPos, GetMonitorInfoW, GetSystemMetrics, LPMONITORINFO,
+    MONITORINFOEXW, MONITORINFOF_PRIMARY, SM_CXSCREEN, SM_CYSCREEN,
+};
 
 pub fn display_size() -> Result<(u64, u64), DisplayError> {
     let w = unsafe { GetSystemMetrics(SM_CXSCREEN).try_into()? };
     let h = unsafe { GetSystemMetrics(SM_CYSCREEN).try_into()? };
     Ok((w, h))
 }
+
+pub fn cursor_position() -> Result<(f64, f64), DisplayError> {
+    let mut point = POINT { x: 0, y: 0 };
+    if unsafe { GetCursorPos(&mut point) } == 0 {
+        return Err(DisplayError::NoDisplay);
+    }
+    Ok((point.x.into(), point.y.into()))
+}
+
+pub fn displays() -> Result<Vec<DisplayInfo>, DisplayError> {
+    let mut displays: Vec<DisplayInfo> = Vec::new();
+    let result = unsafe {
+        EnumDisplayMonitors(
+            null_mut(),
+            null_mut(),
+            Some(monitor_callback),
+            &mut displays as *mut _ as LPARAM,
+        )
+    };
+    if result == 0 || displays.is_empty() {
+        return Err(DisplayError::NoDisplay);
+    }
+    Ok(displays)
+}
+
+unsafe extern "system" fn monitor_callback(
+    monitor: HMONITOR,
+    _hdc: HDC,
+    _rect: LPRECT,
+    data: LPARAM,
+) -> BOOL {
+    let displays = &mut *(data as *mut Vec<DisplayInfo>);
+    let mut info: MONITORINFOEXW = std::mem::zeroed();
+    info.cbSize = size_of::<MONITORINFOEXW>() as _;
+    if GetMonitorInfoW(monitor, &mut info as *mut _ as LPMONITORINFO) == 0 {
+        return TRUE;
+    }
+    let (mut dpi_x, mut dpi_y) = (0, 0);
+    let scale = if GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) == S_OK {
+        dpi_x as f64 / 96.0
+    } else {
+        1.0
+    };
+    let len = info
+        .szDevice
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(info.szDevice.len());
+    let rect = info.rcMonitor;
+    displays.push(DisplayInfo {
+        name: String::from_utf16_lossy(&info.szDevice[..len]),
+        x: rect.left,
+        y: rect.top,
+        width: (rect.right - rect.left) as u32,
+        height: (rect.bottom - rect.top) as u32,
+        scale,
+        primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+    });
+    TRUE
+}
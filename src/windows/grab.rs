@@ -1,6 +1,12 @@
 use crate::{
     rdevin::{Event, EventType, GrabError},
-    windows::common::{convert, get_scan_code, HookError, KEYBOARD},
+    rewrite::{rewriter, Outcome},
+    windows::common::{
+        convert, get_scan_code, keyboard_extra_info, mouse_extra_info, origin, update_modifiers,
+        HookError, KEYBOARD,
+    },
+    windows::simulate::simulate_with_extra_info,
+    GrabAction,
 };
 use std::{io::Error as IoError, ptr::null_mut, sync::Mutex, time::SystemTime};
 use winapi::{
@@ -15,13 +21,13 @@ use winapi::{
         processthreadsapi::GetCurrentThreadId,
         winuser::{
             CallNextHookEx, DispatchMessageA, GetMessageA, PostThreadMessageA, SetWindowsHookExA,
-            TranslateMessage, UnhookWindowsHookEx, HC_ACTION, MSG, PKBDLLHOOKSTRUCT,
-            PMOUSEHOOKSTRUCT, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_USER,
+            TranslateMessage, UnhookWindowsHookEx, HC_ACTION, MSG, WH_KEYBOARD_LL, WH_MOUSE_LL,
+            WM_USER,
         },
     },
 };
 
-static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event) -> Option<Event>>> = None;
+static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event) -> Outcome>> = None;
 static mut GET_KEY_UNICODE: bool = true;
 
 lazy_static::lazy_static! {
@@ -30,6 +36,9 @@ lazy_static::lazy_static! {
 
 const WM_USER_EXIT_HOOK: u32 = WM_USER + 1;
 
+/// The extra info of the events the grab injects, which it lets through without a look.
+const INJECTED_EXTRA_INFO: ULONG_PTR = 0x7264_6576; // "rdev"
+
 pub fn set_get_key_unicode(b: bool) {
     unsafe {
         GET_KEY_UNICODE = b;
@@ -44,9 +53,13 @@ unsafe fn raw_callback(
     code: i32,
     param: usize,
     lpdata: isize,
-    f_get_extra_data: impl FnOnce(isize) -> ULONG_PTR,
+    f_get_extra_info: impl FnOnce(isize) -> (ULONG_PTR, bool),
 ) -> isize {
     if code == HC_ACTION {
+        let (extra_data, injected) = f_get_extra_info(lpdata);
+        if extra_data == INJECTED_EXTRA_INFO {
+            return CallNextHookEx(null_mut(), code, param, lpdata);
+        }
         let (opt, code) = convert(param, lpdata);
         if let Some(event_type) = opt {
             let unicode = if GET_KEY_UNICODE {
@@ -60,6 +73,7 @@ unsafe fn raw_callback(
             } else {
                 None
             };
+            let modifiers = update_modifiers(&event_type);
             let event = Event {
                 event_type,
                 time: SystemTime::now(),
@@ -67,15 +81,31 @@ unsafe fn raw_callback(
                 platform_code: code as _,
                 position_code: get_scan_code(lpdata),
                 usb_hid: 0,
-                extra_data: f_get_extra_data(lpdata),
+                device: None,
+                modifiers,
+                origin: origin(extra_data, injected),
+                extra_data,
             };
             if let Some(callback) = &mut GLOBAL_CALLBACK {
-                if callback(event).is_none() {
-                    // https://stackoverflow.com/questions/42756284/blocking-windows-mouse-click-using-setwindowshookex
-                    // https://android.developreference.com/article/14560004/Blocking+windows+mouse+click+using+SetWindowsHookEx()
-                    // https://cboard.cprogramming.com/windows-programming/99678-setwindowshookex-wm_keyboard_ll.html
-                    // let _result = CallNextHookEx(hhk, code, param, lpdata);
-                    return 1;
+                match callback(event) {
+                    Outcome::Pass => {}
+                    Outcome::Drop => {
+                        // https://stackoverflow.com/questions/42756284/blocking-windows-mouse-click-using-setwindowshookex
+                        // https://android.developreference.com/article/14560004/Blocking+windows+mouse+click+using+SetWindowsHookEx()
+                        // https://cboard.cprogramming.com/windows-programming/99678-setwindowshookex-wm_keyboard_ll.html
+                        // let _result = CallNextHookEx(hhk, code, param, lpdata);
+                        return 1;
+                    }
+                    Outcome::Inject(events) => {
+                        for event_type in &events {
+                            if let Err(e) =
+                                simulate_with_extra_info(event_type, INJECTED_EXTRA_INFO)
+                            {
+                                log::warn!("Failed to inject {:?}: {:?}", event_type, e);
+                            }
+                        }
+                        return 1;
+                    }
                 }
             }
         }
@@ -85,19 +115,19 @@ unsafe fn raw_callback(
 
 unsafe extern "system" fn raw_callback_mouse(code: i32, param: usize, lpdata: isize) -> isize {
     raw_callback(code, param, lpdata, |data: isize| unsafe {
-        (*(data as PMOUSEHOOKSTRUCT)).dwExtraInfo
+        mouse_extra_info(data)
     })
 }
 
 unsafe extern "system" fn raw_callback_keyboard(code: i32, param: usize, lpdata: isize) -> isize {
     raw_callback(code, param, lpdata, |data: isize| unsafe {
-        (*(data as PKBDLLHOOKSTRUCT)).dwExtraInfo
+        keyboard_extra_info(data)
     })
 }
 
 fn do_hook<T>(callback: T) -> Result<(HHOOK, HHOOK), GrabError>
 where
-    T: FnMut(Event) -> Option<Event> + 'static,
+    T: FnMut(Event) -> GrabAction + 'static,
 {
     let mut cur_hook_thread_id = CUR_HOOK_THREAD_ID.lock().unwrap();
     if *cur_hook_thread_id != 0 {
@@ -108,7 +138,7 @@ where
     let hook_keyboard;
     let mut hook_mouse = null_mut();
     unsafe {
-        GLOBAL_CALLBACK = Some(Box::new(callback));
+        GLOBAL_CALLBACK = Some(Box::new(rewriter(callback)));
         hook_keyboard =
             SetWindowsHookExA(WH_KEYBOARD_LL, Some(raw_callback_keyboard), null_mut(), 0);
         if hook_keyboard.is_null() {
@@ -146,7 +176,7 @@ pub enum Error {
 
 pub fn grab<T>(callback: T) -> Result<(), GrabError>
 where
-    T: FnMut(Event) -> Option<Event> + 'static,
+    T: FnMut(Event) -> GrabAction + 'static,
 {
     if is_grabbed() {
         return Ok(());
@@ -1,28 +1,49 @@
 pub use super::common::HookError as ListenError;
 use crate::{
+    handle::ListenHandle,
     rdevin::Event,
-    windows::common::{convert, get_scan_code, set_key_hook, set_mouse_hook},
+    windows::common::{
+        convert, get_scan_code, keyboard_extra_info, mouse_extra_info, origin, update_modifiers,
+    },
+};
+use std::{
+    cell::RefCell, io::Error as IoError, mem::zeroed, os::raw::c_int, ptr::null_mut,
+    time::SystemTime,
 };
-use std::{os::raw::c_int, ptr::null_mut, time::SystemTime};
 use winapi::{
     shared::{
         basetsd::ULONG_PTR,
-        minwindef::{LPARAM, LRESULT, WPARAM},
+        minwindef::{FALSE, LPARAM, LRESULT, WPARAM},
+        windef::HHOOK,
+    },
+    um::{
+        errhandlingapi::GetLastError,
+        processthreadsapi::GetCurrentThreadId,
+        winuser::{
+            CallNextHookEx, GetMessageA, PeekMessageA, PostThreadMessageA, SetWindowsHookExA,
+            UnhookWindowsHookEx, HC_ACTION, MSG, PM_NOREMOVE, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_QUIT,
+            WM_USER,
+        },
     },
-    um::winuser::{CallNextHookEx, GetMessageA, HC_ACTION, PKBDLLHOOKSTRUCT, PMOUSEHOOKSTRUCT},
 };
 
-static mut GLOBAL_CALLBACK: Option<Box<dyn FnMut(Event)>> = None;
+thread_local! {
+    // Low-level hooks run on the thread that installed them, so each listener thread keeps its
+    // own callback.
+    static CALLBACK: RefCell<Option<Box<dyn FnMut(Event)>>> = RefCell::new(None);
+}
 
 unsafe fn raw_callback(
     code: c_int,
     param: WPARAM,
     lpdata: LPARAM,
-    f_get_extra_data: impl FnOnce(isize) -> ULONG_PTR,
+    f_get_extra_info: impl FnOnce(isize) -> (ULONG_PTR, bool),
 ) -> LRESULT {
     if code == HC_ACTION {
         let (opt, code) = convert(param, lpdata);
         if let Some(event_type) = opt {
+            let (extra_data, injected) = f_get_extra_info(lpdata);
+            let modifiers = update_modifiers(&event_type);
             let event = Event {
                 event_type,
                 time: SystemTime::now(),
@@ -30,11 +51,16 @@ unsafe fn raw_callback(
                 platform_code: code as _,
                 position_code: get_scan_code(lpdata),
                 usb_hid: 0,
-                extra_data: f_get_extra_data(lpdata),
+                device: None,
+                modifiers,
+                origin: origin(extra_data, injected),
+                extra_data,
             };
-            if let Some(callback) = &mut GLOBAL_CALLBACK {
-                callback(event);
-            }
+            CALLBACK.with(|callback| {
+                if let Some(callback) = callback.borrow_mut().as_mut() {
+                    callback(event);
+                }
+            });
         }
     }
     CallNextHookEx(null_mut(), code, param, lpdata)
@@ -42,28 +68,79 @@ unsafe fn raw_callback(
 
 unsafe extern "system" fn raw_callback_mouse(code: i32, param: usize, lpdata: isize) -> isize {
     raw_callback(code, param, lpdata, |data: isize| unsafe {
-        (*(data as PMOUSEHOOKSTRUCT)).dwExtraInfo
+        mouse_extra_info(data)
     })
 }
 
 unsafe extern "system" fn raw_callback_keyboard(code: i32, param: usize, lpdata: isize) -> isize {
     raw_callback(code, param, lpdata, |data: isize| unsafe {
-        (*(data as PKBDLLHOOKSTRUCT)).dwExtraInfo
+        keyboard_extra_info(data)
     })
 }
 
-pub fn listen<T>(callback: T) -> Result<(), ListenError>
-where
-    T: FnMut(Event) + 'static,
-{
-    unsafe {
-        GLOBAL_CALLBACK = Some(Box::new(callback));
-        set_key_hook(raw_callback_keyboard)?;
+/// The hooks of one listener thread, removed on drop.
+struct Hooks {
+    keyboard: HHOOK,
+    mouse: HHOOK,
+}
+
+impl Hooks {
+    unsafe fn set() -> Result<Hooks, ListenError> {
+        let mut hooks = Hooks {
+            keyboard: null_mut(),
+            mouse: null_mut(),
+        };
+        hooks.keyboard =
+            SetWindowsHookExA(WH_KEYBOARD_LL, Some(raw_callback_keyboard), null_mut(), 0);
+        if hooks.keyboard.is_null() {
+            return Err(ListenError::Key(GetLastError()));
+        }
         if !crate::keyboard_only() {
-            set_mouse_hook(raw_callback_mouse)?;
+            hooks.mouse = SetWindowsHookExA(WH_MOUSE_LL, Some(raw_callback_mouse), null_mut(), 0);
+            if hooks.mouse.is_null() {
+                return Err(ListenError::Mouse(GetLastError()));
+            }
         }
+        Ok(hooks)
+    }
+}
 
-        GetMessageA(null_mut(), null_mut(), 0, 0);
+impl Drop for Hooks {
+    fn drop(&mut self) {
+        for hook in [self.keyboard, self.mouse] {
+            if !hook.is_null() && unsafe { UnhookWindowsHookEx(hook) } == FALSE {
+                log::error!("Failed UnhookWindowsHookEx {}", IoError::last_os_error());
+            }
+        }
     }
-    Ok(())
+}
+
+pub fn listen<T>(callback: T) -> Result<ListenHandle, ListenError>
+where
+    T: FnMut(Event) + Send + 'static,
+{
+    ListenHandle::spawn(move |started| unsafe {
+        CALLBACK.with(|cell| *cell.borrow_mut() = Some(Box::new(callback)));
+        let hooks = match Hooks::set() {
+            Ok(hooks) => hooks,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return Ok(());
+            }
+        };
+
+        let mut msg: MSG = zeroed();
+        // Make sure the thread has a message queue before anyone posts to it.
+        PeekMessageA(&mut msg, null_mut(), WM_USER, WM_USER, PM_NOREMOVE);
+        let thread_id = GetCurrentThreadId();
+        let _ = started.send(Ok(Box::new(move || {
+            PostThreadMessageA(thread_id, WM_QUIT, 0, 0);
+        })));
+
+        // Hooks are called while waiting for messages. WM_QUIT makes GetMessage return 0.
+        while GetMessageA(&mut msg, null_mut(), 0, 0) > 0 {}
+        drop(hooks);
+        CALLBACK.with(|cell| cell.borrow_mut().take());
+        Ok(())
+    })
 }
@@ -8,9 +8,10 @@ mod grab;
 mod keyboard;
 mod listen;
 mod simulate;
+pub(crate) mod typing;
 
 pub use crate::windows::common::*;
-pub use crate::windows::display::display_size;
+pub use crate::windows::display::{cursor_position, display_size, displays};
 pub use crate::windows::grab::{
     exit_grab, grab, is_grabbed, set_event_popup, set_get_key_unicode, Error as GrabError,
 };
@@ -1,10 +1,11 @@
-use crate::keycodes::windows::{get_win_codes, scancode_from_key};
-use crate::rdevin::{Button, EventType, RawKey, SimulateError};
+use crate::keycodes::windows::{code_from_button, get_win_codes, scancode_from_key};
+use crate::rdevin::{Button, EventType, RawKey, SimulateError, WheelRemainder, WheelUnit};
 use crate::Key;
 use std::convert::TryFrom;
 use std::mem::size_of;
 use std::ptr::null_mut;
-use winapi::ctypes::{c_int, c_short};
+use std::sync::Mutex;
+use winapi::ctypes::c_int;
 use winapi::shared::minwindef::{DWORD, HKL, LOWORD, UINT, WORD};
 use winapi::shared::ntdef::LONG;
 use winapi::um::winuser::{
@@ -15,7 +16,6 @@ use winapi::um::winuser::{
     MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE,
     MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK, MOUSEEVENTF_WHEEL,
     MOUSEEVENTF_XDOWN, MOUSEEVENTF_XUP, MOUSEINPUT, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
-    WHEEL_DELTA,
 };
 /// Not defined in win32 but define here for clarity
 #[allow(dead_code)]
@@ -23,6 +23,7 @@ static KEYEVENTF_KEYDOWN: DWORD = 0;
 // KEYBDINPUT
 static mut DW_MOUSE_EXTRA_INFO: usize = 0;
 static mut DW_KEYBOARD_EXTRA_INFO: usize = 0;
+static WHEEL_REMAINDER: Mutex<WheelRemainder> = Mutex::new(WheelRemainder::new());
 
 pub fn set_mouse_extra_info(extra: usize) {
     unsafe { DW_MOUSE_EXTRA_INFO = extra }
@@ -32,7 +33,46 @@ pub fn set_keyboard_extra_info(extra: usize) {
     unsafe { DW_KEYBOARD_EXTRA_INFO = extra }
 }
 
-fn sim_mouse_event(flags: DWORD, data: DWORD, dx: LONG, dy: LONG) -> Result<(), SimulateError> {
+/// The extra info that input sent by [`simulate`] and friends carries.
+#[derive(Clone, Copy)]
+struct ExtraInfo {
+    mouse: usize,
+    keyboard: usize,
+}
+
+impl ExtraInfo {
+    /// What was set with [`set_mouse_extra_info`] and [`set_keyboard_extra_info`].
+    fn current() -> ExtraInfo {
+        unsafe {
+            ExtraInfo {
+                mouse: DW_MOUSE_EXTRA_INFO,
+                keyboard: DW_KEYBOARD_EXTRA_INFO,
+            }
+        }
+    }
+}
+
+/// Simulates an event carrying `extra` as its extra info, whatever was set otherwise.
+pub(crate) fn simulate_with_extra_info(
+    event_type: &EventType,
+    extra: usize,
+) -> Result<(), SimulateError> {
+    simulate_with(
+        event_type,
+        ExtraInfo {
+            mouse: extra,
+            keyboard: extra,
+        },
+    )
+}
+
+fn sim_mouse_event(
+    flags: DWORD,
+    data: DWORD,
+    dx: LONG,
+    dy: LONG,
+    extra: usize,
+) -> Result<(), SimulateError> {
     let mut union: INPUT_u = unsafe { std::mem::zeroed() };
     let inner_union = unsafe { union.mi_mut() };
     unsafe {
@@ -42,7 +82,7 @@ fn sim_mouse_event(flags: DWORD, data: DWORD, dx: LONG, dy: LONG) -> Result<(),
             mouseData: data,
             dwFlags: flags,
             time: 0,
-            dwExtraInfo: DW_MOUSE_EXTRA_INFO,
+            dwExtraInfo: extra,
         };
     }
     let mut input = [INPUT {
@@ -63,7 +103,12 @@ fn sim_mouse_event(flags: DWORD, data: DWORD, dx: LONG, dy: LONG) -> Result<(),
     }
 }
 
-fn sim_keyboard_event(flags: DWORD, vk: WORD, scan: WORD) -> Result<(), SimulateError> {
+fn sim_keyboard_event(
+    flags: DWORD,
+    vk: WORD,
+    scan: WORD,
+    extra: usize,
+) -> Result<(), SimulateError> {
     let mut union: INPUT_u = unsafe { std::mem::zeroed() };
     let inner_union = unsafe { union.ki_mut() };
     unsafe {
@@ -72,7 +117,7 @@ fn sim_keyboard_event(flags: DWORD, vk: WORD, scan: WORD) -> Result<(), Simulate
             wScan: scan,
             dwFlags: flags,
             time: 0,
-            dwExtraInfo: DW_KEYBOARD_EXTRA_INFO,
+            dwExtraInfo: extra,
         };
     }
     let mut input = [INPUT {
@@ -101,13 +146,17 @@ fn get_layout() -> HKL {
     }
 }
 
-fn simulate_key_event_rawkey(key: &RawKey, is_press: bool) -> Result<(), SimulateError> {
+fn simulate_key_event_rawkey(
+    key: &RawKey,
+    is_press: bool,
+    extra: usize,
+) -> Result<(), SimulateError> {
     match key {
-        RawKey::ScanCode(scancode) => simulate_code(None, Some(*scancode), is_press),
+        RawKey::ScanCode(scancode) => send_code(None, Some(*scancode), is_press, extra),
         RawKey::WinVirtualKeycode(vk) => {
             let scancode =
                 unsafe { MapVirtualKeyExW(*vk as _, MAPVK_VK_TO_VSC, get_layout()) as _ };
-            simulate_code(None, Some(scancode), is_press)
+            send_code(None, Some(scancode), is_press, extra)
         }
         RawKey::MacVirtualKeycode(_) => Err(SimulateError::InvalidRawKey {
             expected: "Windows".into(),
@@ -122,7 +171,11 @@ fn simulate_key_event_rawkey(key: &RawKey, is_press: bool) -> Result<(), Simulat
     }
 }
 
-fn simulate_key_event_not_rawkey(key: &Key, is_press: bool) -> Result<(), SimulateError> {
+fn simulate_key_event_not_rawkey(
+    key: &Key,
+    is_press: bool,
+    extra: usize,
+) -> Result<(), SimulateError> {
     let layout = get_layout();
     let (vk, scan) = {
         let (code, scancode) = get_win_codes(*key).ok_or(SimulateError::GetCodes)?;
@@ -139,47 +192,71 @@ fn simulate_key_event_not_rawkey(key: &Key, is_press: bool) -> Result<(), Simula
             (Some(code as _), None)
         }
     };
-    simulate_code(vk, scan, is_press)
+    send_code(vk, scan, is_press, extra)
 }
 
 pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
+    simulate_with(event_type, ExtraInfo::current())
+}
+
+fn simulate_with(event_type: &EventType, extra: ExtraInfo) -> Result<(), SimulateError> {
     match event_type {
         EventType::KeyPress(key) => match key {
-            crate::Key::RawKey(raw_key) => simulate_key_event_rawkey(raw_key, true),
-            _ => simulate_key_event_not_rawkey(key, true),
+            crate::Key::RawKey(raw_key) => simulate_key_event_rawkey(raw_key, true, extra.keyboard),
+            _ => simulate_key_event_not_rawkey(key, true, extra.keyboard),
         },
         EventType::KeyRelease(key) => match key {
-            crate::Key::RawKey(raw_key) => simulate_key_event_rawkey(raw_key, false),
-            _ => simulate_key_event_not_rawkey(key, false),
+            crate::Key::RawKey(raw_key) => {
+                simulate_key_event_rawkey(raw_key, false, extra.keyboard)
+            }
+            _ => simulate_key_event_not_rawkey(key, false, extra.keyboard),
         },
         EventType::ButtonPress(button) => match button {
-            Button::Left => sim_mouse_event(MOUSEEVENTF_LEFTDOWN, 0, 0, 0),
-            Button::Middle => sim_mouse_event(MOUSEEVENTF_MIDDLEDOWN, 0, 0, 0),
-            Button::Right => sim_mouse_event(MOUSEEVENTF_RIGHTDOWN, 0, 0, 0),
-            Button::Unknown(code) => sim_mouse_event(MOUSEEVENTF_XDOWN, 0, 0, (*code).into()),
+            Button::Left => sim_mouse_event(MOUSEEVENTF_LEFTDOWN, 0, 0, 0, extra.mouse),
+            Button::Middle => sim_mouse_event(MOUSEEVENTF_MIDDLEDOWN, 0, 0, 0, extra.mouse),
+            Button::Right => sim_mouse_event(MOUSEEVENTF_RIGHTDOWN, 0, 0, 0, extra.mouse),
+            button => {
+                let code = code_from_button(*button).ok_or(SimulateError::NoCode)?;
+                sim_mouse_event(MOUSEEVENTF_XDOWN, code.into(), 0, 0, extra.mouse)
+            }
         },
         EventType::ButtonRelease(button) => match button {
-            Button::Left => sim_mouse_event(MOUSEEVENTF_LEFTUP, 0, 0, 0),
-            Button::Middle => sim_mouse_event(MOUSEEVENTF_MIDDLEUP, 0, 0, 0),
-            Button::Right => sim_mouse_event(MOUSEEVENTF_RIGHTUP, 0, 0, 0),
-            Button::Unknown(code) => sim_mouse_event(MOUSEEVENTF_XUP, 0, 0, (*code).into()),
+            Button::Left => sim_mouse_event(MOUSEEVENTF_LEFTUP, 0, 0, 0, extra.mouse),
+            Button::Middle => sim_mouse_event(MOUSEEVENTF_MIDDLEUP, 0, 0, 0, extra.mouse),
+            Button::Right => sim_mouse_event(MOUSEEVENTF_RIGHTUP, 0, 0, 0, extra.mouse),
+            button => {
+                let code = code_from_button(*button).ok_or(SimulateError::NoCode)?;
+                sim_mouse_event(MOUSEEVENTF_XUP, code.into(), 0, 0, extra.mouse)
+            }
         },
-        EventType::Wheel { delta_x, delta_y } => {
-            if *delta_x != 0 {
+        EventType::Wheel {
+            delta_x,
+            delta_y,
+            unit,
+        } => {
+            // Windows counts in 120ths of a notch too.
+            let (delta_x, delta_y) =
+                WHEEL_REMAINDER
+                    .lock()
+                    .unwrap()
+                    .take(*delta_x, *delta_y, *unit, WheelUnit::HighRes);
+            if delta_x != 0 {
                 sim_mouse_event(
                     MOUSEEVENTF_HWHEEL,
-                    (c_short::try_from(*delta_x)? * WHEEL_DELTA) as u32,
+                    i32::try_from(delta_x)? as u32,
                     0,
                     0,
+                    extra.mouse,
                 )?;
             }
 
-            if *delta_y != 0 {
+            if delta_y != 0 {
                 sim_mouse_event(
                     MOUSEEVENTF_WHEEL,
-                    (c_short::try_from(*delta_y)? * WHEEL_DELTA) as u32,
+                    i32::try_from(delta_y)? as u32,
                     0,
                     0,
+                    extra.mouse,
                 )?;
             }
             Ok(())
@@ -196,8 +273,17 @@ pub fn simulate(event_type: &EventType) -> Result<(), SimulateError> {
                 0,
                 (*x as i32 + 1) * 65535 / width,
                 (*y as i32 + 1) * 65535 / height,
+                extra.mouse,
             )
         }
+        EventType::MouseMoveRelative { dx, dy } => sim_mouse_event(
+            MOUSEEVENTF_MOVE,
+            0,
+            dx.round() as LONG,
+            dy.round() as LONG,
+            extra.mouse,
+        ),
+        _ => Err(SimulateError::Unsupported),
     }
 }
 
@@ -205,6 +291,15 @@ pub fn simulate_code(
     vk: Option<u16>,
     scan: Option<u32>,
     pressed: bool,
+) -> Result<(), SimulateError> {
+    send_code(vk, scan, pressed, ExtraInfo::current().keyboard)
+}
+
+fn send_code(
+    vk: Option<u16>,
+    scan: Option<u32>,
+    pressed: bool,
+    extra: usize,
 ) -> Result<(), SimulateError> {
     let keycode;
     let scancode;
@@ -229,7 +324,7 @@ pub fn simulate_code(
     if !pressed {
         flags |= KEYEVENTF_KEYUP;
     }
-    sim_keyboard_event(flags as _, keycode, scancode as _)
+    sim_keyboard_event(flags as _, keycode, scancode as _, extra)
 }
 
 pub fn simulate_key_unicode(unicode_16: u16, try_unicode: bool) -> Result<(), SimulateError> {
@@ -277,8 +372,9 @@ pub fn simulate_char(chr: char, try_unicode: bool) -> Result<(), SimulateError>
 }
 
 pub fn simulate_unicode(unicode: u16) -> Result<(), SimulateError> {
-    sim_keyboard_event(KEYEVENTF_UNICODE, 0, unicode)?;
-    sim_keyboard_event(KEYEVENTF_UNICODE | KEYEVENTF_KEYUP, 0, unicode)
+    let extra = ExtraInfo::current().keyboard;
+    sim_keyboard_event(KEYEVENTF_UNICODE, 0, unicode, extra)?;
+    sim_keyboard_event(KEYEVENTF_UNICODE | KEYEVENTF_KEYUP, 0, unicode, extra)
 }
 
 #[inline]
@@ -0,0 +1,34 @@
+use crate::rdevin::SimulateError;
+use crate::windows::simulate::{simulate_key_unicode, simulate_unicode};
+
+/// Types characters through `SendInput`.
+pub(crate) struct Typist;
+
+impl Typist {
+    pub(crate) fn new() -> Result<Typist, SimulateError> {
+        Ok(Typist)
+    }
+
+    /// Types `chr`, returning whether it could.
+    pub(crate) fn type_char(&mut self, chr: char, fallback: bool) -> Result<bool, SimulateError> {
+        // Windows applications expect a carriage return from the Enter key.
+        let chr = if chr == '\n' { '\r' } else { chr };
+        let mut buffer = [0; 2];
+        let units: &[u16] = chr.encode_utf16(&mut buffer);
+        if let [unit] = units {
+            match simulate_key_unicode(*unit, false) {
+                Ok(()) => return Ok(true),
+                Err(SimulateError::NoCorrespondingKey) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !fallback {
+            return Ok(false);
+        }
+        // KEYEVENTF_UNICODE takes surrogate pairs one unit at a time.
+        for unit in units.iter() {
+            simulate_unicode(*unit)?;
+        }
+        Ok(true)
+    }
+}
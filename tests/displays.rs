@@ -0,0 +1,40 @@
+#![cfg(target_os = "linux")]
+
+use rdevin::{cursor_position, display_size, displays, simulate, EventType};
+use serial_test::serial;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+/// Runs under any X server, like `Xvfb :99 & DISPLAY=:99 cargo test -- --ignored`.
+#[test]
+#[ignore = "needs an X server"]
+fn test_displays() -> Result<(), Box<dyn Error>> {
+    let displays = displays()?;
+    assert!(!displays.is_empty());
+    for display in &displays {
+        assert!(display.width > 0 && display.height > 0, "{:?}", display);
+        assert!(display.scale > 0.0, "{:?}", display);
+    }
+    // The default screen's monitors cover it.
+    let (width, height) = display_size()?;
+    assert!(displays
+        .iter()
+        .any(|display| display.contains(width as f64 - 1.0, height as f64 - 1.0)));
+    Ok(())
+}
+
+#[test]
+#[serial]
+#[ignore = "needs an X server"]
+fn test_cursor_position() -> Result<(), Box<dyn Error>> {
+    let settle = Duration::from_millis(100);
+    simulate(&EventType::MouseMove { x: 10.0, y: 20.0 })?;
+    thread::sleep(settle);
+    assert_eq!(cursor_position()?, (10.0, 20.0));
+    // Small enough for X's default acceleration to leave alone.
+    simulate(&EventType::MouseMoveRelative { dx: 2.0, dy: -1.0 })?;
+    thread::sleep(settle);
+    assert_eq!(cursor_position()?, (12.0, 19.0));
+    Ok(())
+}
@@ -1,7 +1,13 @@
-#![cfg(not(any(target_os = "android", target_os = "ios", target_os = "linux")))]
+#![cfg(not(any(target_os = "android", target_os = "ios")))]
 
 use lazy_static::lazy_static;
-use rdevin::{grab, listen, simulate, Event, EventType, Key};
+// The Linux grab works at the evdev level, below the X server, so it is tested with the evdev
+// backends.
+#[cfg(target_os = "linux")]
+use rdevin::linux::evdev::{listen, simulate};
+use rdevin::{grab, Event, EventType, GrabAction, Key};
+#[cfg(not(target_os = "linux"))]
+use rdevin::{listen, simulate};
 use serial_test::serial;
 use std::error::Error;
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
@@ -25,25 +31,32 @@ fn send_event(event: Event) {
         .expect("Receiving end of EVENT_CHANNEL was closed");
 }
 
-fn grab_tab(event: Event) -> Option<Event> {
+fn grab_tab(event: Event) -> GrabAction {
     match event.event_type {
-        EventType::KeyPress(Key::Tab) => None,
-        EventType::KeyRelease(Key::Tab) => None,
-        _ => Some(event),
+        EventType::KeyPress(Key::Tab) => GrabAction::Drop,
+        EventType::KeyRelease(Key::Tab) => GrabAction::Drop,
+        _ => GrabAction::Pass,
     }
 }
 
+/// On Linux, this needs access to `/dev/uinput` and `/dev/input`. Run it with
+/// `cargo test -- --ignored`.
 #[test]
 #[serial]
+#[cfg_attr(target_os = "linux", ignore = "needs /dev/uinput and /dev/input")]
 fn test_grab() -> Result<(), Box<dyn Error>> {
     // Wait for tester's key to go back up
     // otherwise, test fails due to KeyRelease(Return)
     thread::sleep(Duration::from_millis(300));
 
-    // spawn new thread because listen blocks
-    let _listener = thread::spawn(move || {
-        listen(send_event).expect("Could not listen");
-    });
+    #[cfg(target_os = "linux")]
+    {
+        // Create the virtual devices up front so that the grab takes them too. Releasing a key
+        // that isn't down produces no event.
+        simulate(&EventType::KeyRelease(Key::Tab))?;
+    }
+
+    let _listener = listen(send_event)?;
     // Make sure grab ends up on top of listen so it can properly discard.
     thread::sleep(Duration::from_secs(1));
     let _grab = thread::spawn(move || {
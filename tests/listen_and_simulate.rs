@@ -1,43 +1,21 @@
-use lazy_static::lazy_static;
-use rdevin::{listen, simulate, Button, Event, EventType, Key};
+use rdevin::{listen_channel, simulate, Button, EventType, Key, WheelUnit};
 use serial_test::serial;
 use std::error::Error;
 use std::iter::Iterator;
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
-lazy_static! {
-    static ref EVENT_CHANNEL: (Mutex<Sender<Event>>, Mutex<Receiver<Event>>) = {
-        let (send, recv) = channel();
-        (Mutex::new(send), Mutex::new(recv))
-    };
-}
-
-fn send_event(event: Event) {
-    EVENT_CHANNEL
-        .0
-        .lock()
-        .expect("Failed to unlock Mutex")
-        .send(event)
-        .expect("Receiving end of EVENT_CHANNEL was closed");
-}
-
 fn sim_then_listen(events: &mut dyn Iterator<Item = EventType>) -> Result<(), Box<dyn Error>> {
-    // spawn new thread because listen blocks
-    let _listener = thread::spawn(move || {
-        listen(send_event).expect("Could not listen");
-    });
+    let (listener, recv) = listen_channel()?;
     let second = Duration::from_millis(1000);
     thread::sleep(second);
 
-    let recv = EVENT_CHANNEL.1.lock()?;
     for event in events {
         simulate(&event)?;
         let recieved_event = recv.recv_timeout(second).expect("No events to recieve");
         assert_eq!(recieved_event.event_type, event);
     }
+    assert_eq!(listener.dropped_events(), 0);
     Ok(())
 }
 
@@ -56,12 +34,14 @@ fn test_listen_and_simulate() -> Result<(), Box<dyn Error>> {
         EventType::ButtonPress(Button::Right),
         EventType::ButtonRelease(Button::Right),
         EventType::Wheel {
-            delta_x: 0,
-            delta_y: 1,
+            delta_x: 0.0,
+            delta_y: 1.0,
+            unit: WheelUnit::Lines,
         },
         EventType::Wheel {
-            delta_x: 0,
-            delta_y: -1,
+            delta_x: 0.0,
+            delta_y: -1.0,
+            unit: WheelUnit::Lines,
         },
     ]
     .into_iter();
@@ -0,0 +1,86 @@
+#![cfg(target_os = "linux")]
+
+use lazy_static::lazy_static;
+use rdevin::linux::evdev::{self, devices};
+use rdevin::{Button, Event, EventType, Key, WheelUnit};
+use serial_test::serial;
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+lazy_static! {
+    static ref EVENT_CHANNEL: (Mutex<Sender<Event>>, Mutex<Receiver<Event>>) = {
+        let (send, recv) = channel();
+        (Mutex::new(send), Mutex::new(recv))
+    };
+}
+
+fn send_event(event: Event) {
+    EVENT_CHANNEL
+        .0
+        .lock()
+        .expect("Failed to unlock Mutex")
+        .send(event)
+        .expect("Receiving end of EVENT_CHANNEL was closed");
+}
+
+/// Runs without a display server, but needs access to uinput and the input devices, as in a
+/// container started with `--device /dev/uinput --device /dev/input`. Run it with
+/// `cargo test -- --ignored`.
+#[test]
+#[serial]
+#[ignore = "needs /dev/uinput and /dev/input"]
+fn test_uinput_listen_and_simulate() -> Result<(), Box<dyn Error>> {
+    evdev::set_screen_size(1920, 1080);
+    // Create the virtual devices before listening, so we can tell their events apart from those
+    // of real hardware.
+    evdev::simulate(&EventType::KeyRelease(Key::KeyS))?;
+    evdev::simulate(&EventType::MouseMove { x: 0.0, y: 0.0 })?;
+    let ours: HashSet<u32> = devices()?
+        .into_iter()
+        .filter(|device| device.name.starts_with("rdevin virtual"))
+        .map(|device| device.id)
+        .collect();
+    assert!(!ours.is_empty(), "Virtual devices are not visible");
+
+    let _listener = evdev::listen(send_event)?;
+    let second = Duration::from_millis(1000);
+    thread::sleep(second);
+
+    let events = vec![
+        EventType::KeyPress(Key::ShiftLeft),
+        EventType::KeyRelease(Key::ShiftLeft),
+        EventType::KeyPress(Key::KeyS),
+        EventType::KeyRelease(Key::KeyS),
+        EventType::ButtonPress(Button::Right),
+        EventType::ButtonRelease(Button::Right),
+        // The virtual mouse has a high-resolution wheel.
+        EventType::Wheel {
+            delta_x: 0.0,
+            delta_y: 120.0,
+            unit: WheelUnit::HighRes,
+        },
+        EventType::Wheel {
+            delta_x: -30.0,
+            delta_y: 0.0,
+            unit: WheelUnit::HighRes,
+        },
+        EventType::MouseMove { x: 10.0, y: 20.0 },
+    ];
+
+    let recv = EVENT_CHANNEL.1.lock()?;
+    for event in events {
+        evdev::simulate(&event)?;
+        let received = loop {
+            let received = recv.recv_timeout(second).expect("No events to receive");
+            if received.device.is_some_and(|id| ours.contains(&id)) {
+                break received;
+            }
+        };
+        assert_eq!(received.event_type, event);
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+#![cfg(target_os = "linux")]
+
+use rdevin::linux::xinput;
+use rdevin::{simulate, Button, EventType, Key, Origin, WheelUnit};
+use serial_test::serial;
+use std::error::Error;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+/// Runs under any X server, like `Xvfb :99 & DISPLAY=:99 cargo test -- --ignored`.
+#[test]
+#[serial]
+#[ignore = "needs an X server"]
+fn test_xinput_listen_and_simulate() -> Result<(), Box<dyn Error>> {
+    let (send, recv) = channel();
+    let _listener = xinput::listen(move |event| {
+        let _ = send.send(event);
+    })?;
+    let second = Duration::from_millis(1000);
+    thread::sleep(second);
+
+    let events = vec![
+        EventType::KeyPress(Key::KeyS),
+        EventType::KeyRelease(Key::KeyS),
+        EventType::ButtonPress(Button::Right),
+        EventType::ButtonRelease(Button::Right),
+        EventType::Wheel {
+            delta_x: 0.0,
+            delta_y: 1.0,
+            unit: WheelUnit::Lines,
+        },
+        EventType::MouseMove { x: 10.0, y: 20.0 },
+    ];
+    for event in events {
+        simulate(&event)?;
+        // XTest input comes from the XTEST devices, which real hardware never does.
+        let received = loop {
+            let received = recv.recv_timeout(second).expect("No events to receive");
+            if received.origin == (Origin::Synthetic { tag: 0 }) {
+                break received;
+            }
+        };
+        assert_eq!(received.event_type, event);
+        assert!(received.device.is_some());
+    }
+    Ok(())
+}